@@ -0,0 +1,57 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use time::{SteadyTime, Duration};
+
+/// The source of the current time for the cluster server
+///
+/// Nodes started with `NodeBuilder` use the system clock. Nodes in a `Simulation` share a virtual
+/// clock that only moves forward when the simulation advances it, so that failure detection and
+/// queue timeouts don't depend on how fast the simulation runs.
+#[derive(Clone)]
+pub struct Clock {
+    start: SteadyTime,
+
+    /// The number of ms the virtual clock was advanced, or `None` for the system clock
+    elapsed: Option<Arc<AtomicUsize>>
+}
+
+impl Clock {
+    pub fn system() -> Clock {
+        Clock {
+            start: SteadyTime::now(),
+            elapsed: None
+        }
+    }
+
+    /// Create a virtual clock starting at the current time
+    pub fn virtual_clock() -> Clock {
+        Clock {
+            start: SteadyTime::now(),
+            elapsed: Some(Arc::new(AtomicUsize::new(0)))
+        }
+    }
+
+    pub fn now(&self) -> SteadyTime {
+        match self.elapsed {
+            Some(ref elapsed) => {
+                self.start + Duration::milliseconds(elapsed.load(Ordering::SeqCst) as i64)
+            },
+            None => SteadyTime::now()
+        }
+    }
+
+    /// Return the number of ms since the clock was created
+    pub fn elapsed_ms(&self) -> usize {
+        match self.elapsed {
+            Some(ref elapsed) => elapsed.load(Ordering::SeqCst),
+            None => (SteadyTime::now() - self.start).num_milliseconds() as usize
+        }
+    }
+
+    /// Move a virtual clock forward by `ms`. The system clock can't be moved.
+    pub fn advance(&self, ms: usize) {
+        if let Some(ref elapsed) = self.elapsed {
+            elapsed.fetch_add(ms, Ordering::SeqCst);
+        }
+    }
+}
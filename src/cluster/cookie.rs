@@ -0,0 +1,51 @@
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use errors::*;
+
+const NONCE_SIZE: usize = 32;
+
+/// A shared secret that nodes must prove they know before they are allowed to join the cluster
+///
+/// When a connection is established, each side sends the other a random challenge. The peer must
+/// respond with an HMAC of the challenge keyed with the cookie. The HMAC also covers whether the
+/// responder is the client or server side of the connection, so that a peer can't reflect a
+/// challenge back and use the answer as its own response.
+pub struct Cookie {
+    key: hmac::Key,
+    rng: SystemRandom
+}
+
+impl Cookie {
+    pub fn new(secret: &str) -> Cookie {
+        Cookie {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+            rng: SystemRandom::new()
+        }
+    }
+
+    /// Generate a random challenge
+    pub fn challenge(&self) -> Result<Vec<u8>> {
+        let mut nonce = vec![0; NONCE_SIZE];
+        match self.rng.fill(&mut nonce) {
+            Ok(()) => Ok(nonce),
+            Err(_) => Err("Failed to generate cookie challenge".into())
+        }
+    }
+
+    /// Return the response to a challenge received by the client or server side of a connection
+    pub fn response(&self, is_client: bool, challenge: &[u8]) -> Vec<u8> {
+        hmac::sign(&self.key, &signed_data(is_client, challenge)).as_ref().to_vec()
+    }
+
+    /// Return true if `response` is the correct response to `challenge` from the client or server
+    /// side of a connection
+    pub fn verify(&self, is_client: bool, challenge: &[u8], response: &[u8]) -> bool {
+        hmac::verify(&self.key, &signed_data(is_client, challenge), response).is_ok()
+    }
+}
+
+fn signed_data(is_client: bool, challenge: &[u8]) -> Vec<u8> {
+    let mut data = if is_client { b"client".to_vec() } else { b"server".to_vec() };
+    data.extend_from_slice(challenge);
+    data
+}
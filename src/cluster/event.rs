@@ -0,0 +1,17 @@
+use node_id::NodeId;
+
+/// A change in cluster membership or connectivity, published to subscribers by the cluster server
+#[derive(Debug, Clone, Eq, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum ClusterEvent {
+    /// A node was added to the cluster membership
+    NodeJoined(NodeId),
+
+    /// A connection to a member of the cluster was established
+    ConnectionEstablished(NodeId),
+
+    /// An established connection to a member of the cluster was closed
+    ConnectionLost(NodeId),
+
+    /// A node was removed from the cluster membership
+    NodeLeft(NodeId)
+}
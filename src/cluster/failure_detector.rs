@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+use time::{SteadyTime, Duration};
+
+/// Configuration for the phi accrual failure detector used to decide when a peer is unreachable
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailureDetectorConfig {
+    /// A connection is closed once the suspicion level (phi) of its peer exceeds this value.
+    /// A threshold of 8 means there is roughly a 1 in 10^8 chance of wrongly suspecting a peer,
+    /// given the observed heartbeat intervals.
+    pub threshold: f64,
+
+    /// The number of heartbeat intervals used to estimate the interval distribution
+    pub max_samples: usize,
+
+    /// The minimum standard deviation in ms. This prevents a very regular heartbeat from
+    /// making the detector overly sensitive to small delays.
+    pub min_std_deviation: u64,
+
+    /// An additional pause in ms that is tolerated before the suspicion level starts rising. This
+    /// covers things like garbage collection or a busy peer.
+    pub acceptable_heartbeat_pause: u64,
+
+    /// The expected heartbeat interval in ms, used before any heartbeats have been received
+    pub first_heartbeat_estimate: u64
+}
+
+impl Default for FailureDetectorConfig {
+    fn default() -> FailureDetectorConfig {
+        FailureDetectorConfig {
+            threshold: 8.0,
+            max_samples: 100,
+            min_std_deviation: 200,
+            acceptable_heartbeat_pause: 3000,
+            first_heartbeat_estimate: 1000
+        }
+    }
+}
+
+/// A phi accrual failure detector as described in "The φ Accrual Failure Detector" by Hayashibara
+/// et al.
+///
+/// Instead of a binary up or down decision based on a fixed timeout, the detector outputs a
+/// suspicion level, phi, based on how late the next heartbeat is relative to the distribution of
+/// previously observed heartbeat intervals. A phi of 1 means there is about a 10% chance the peer
+/// is still alive, a phi of 2 about a 1% chance, and so on.
+pub struct PhiAccrualDetector {
+    intervals: VecDeque<f64>,
+    last_heartbeat: SteadyTime,
+
+    /// False until the first heartbeat is received. The time from creation to the first heartbeat
+    /// includes setting up the connection, so it isn't a heartbeat interval.
+    sampling: bool
+}
+
+impl PhiAccrualDetector {
+    /// Create a new detector. Phi is measured from the creation time until the first heartbeat is
+    /// received, but intervals are only sampled between heartbeats.
+    pub fn new(now: SteadyTime) -> PhiAccrualDetector {
+        PhiAccrualDetector {
+            intervals: VecDeque::new(),
+            last_heartbeat: now,
+            sampling: false
+        }
+    }
+
+    /// Record a heartbeat received at `now`
+    pub fn heartbeat(&mut self, config: &FailureDetectorConfig, now: SteadyTime) {
+        let interval = to_ms(now - self.last_heartbeat);
+        self.last_heartbeat = now;
+        if !self.sampling {
+            self.sampling = true;
+            return;
+        }
+        if self.intervals.len() >= config.max_samples {
+            self.intervals.pop_front();
+        }
+        self.intervals.push_back(interval);
+    }
+
+    /// Return the current suspicion level of the peer
+    pub fn phi(&self, config: &FailureDetectorConfig, now: SteadyTime) -> f64 {
+        let elapsed = to_ms(now - self.last_heartbeat);
+        let (mean, std_deviation) = self.distribution(config);
+        let mean = mean + config.acceptable_heartbeat_pause as f64;
+        let std_deviation = std_deviation.max(config.min_std_deviation as f64);
+        phi(elapsed, mean, std_deviation)
+    }
+
+    /// Return the mean and standard deviation of the heartbeat intervals
+    fn distribution(&self, config: &FailureDetectorConfig) -> (f64, f64) {
+        if self.intervals.is_empty() {
+            let estimate = config.first_heartbeat_estimate as f64;
+            return (estimate, estimate / 4.0);
+        }
+        let n = self.intervals.len() as f64;
+        let mean = self.intervals.iter().fold(0.0, |acc, i| acc + i) / n;
+        let variance = self.intervals.iter().fold(0.0, |acc, i| acc + (i - mean).powi(2)) / n;
+        (mean, variance.sqrt())
+    }
+}
+
+/// Compute phi using a logistic approximation of the cumulative normal distribution
+fn phi(elapsed: f64, mean: f64, std_deviation: f64) -> f64 {
+    let y = (elapsed - mean) / std_deviation;
+    let e = (-y * (1.5976 + 0.070566 * y * y)).exp();
+    if elapsed > mean {
+        -(e / (1.0 + e)).log10()
+    } else {
+        -(1.0 - 1.0 / (1.0 + e)).log10()
+    }
+}
+
+fn to_ms(duration: Duration) -> f64 {
+    duration.num_milliseconds() as f64
+}
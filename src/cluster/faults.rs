@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::mem;
+use std::fmt::Debug;
+use rustc_serialize::{Encodable, Decodable};
+use time::{SteadyTime, Duration};
+use node_id::NodeId;
+use rng::Rng;
+use super::ExternalMsg;
+
+/// Faults injected into the link between a node and one of its peers, set with
+/// `Node::set_faults`
+///
+/// This is meant for testing how processes behave on an unreliable network, and is only compiled
+/// with the `fault-injection` feature. Without it, every message passes through unchanged.
+/// Dropping, delaying, duplicating and reordering only affect envelopes and their
+/// acknowledgements. Pings and
+/// membership messages still get through, so the connection stays up unless the link is
+/// partitioned. Probabilities are between 0 and 1.
+///
+/// ```
+/// # #[cfg(feature = "fault-injection")] {
+/// let faults = rabble::LinkFaults::new()
+///     .drop_rate(0.1)
+///     .delay(50, 200);
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkFaults {
+    /// Drop all traffic to and from the peer, and don't connect to it
+    pub partition: bool,
+
+    /// The probability that an envelope is dropped
+    pub drop_rate: f64,
+
+    /// The probability that an envelope is delivered twice
+    pub duplicate_rate: f64,
+
+    /// The probability that an envelope is held back and delivered after the next envelope on the
+    /// link, or on the next executor tick if there is none
+    pub reorder_rate: f64,
+
+    /// Each envelope is delayed by a random time between `min_delay` and `max_delay` ms. Delayed
+    /// envelopes are delivered on the next executor tick after the delay expires.
+    pub min_delay: usize,
+    pub max_delay: usize
+}
+
+impl Default for LinkFaults {
+    fn default() -> LinkFaults {
+        LinkFaults {
+            partition: false,
+            drop_rate: 0.0,
+            duplicate_rate: 0.0,
+            reorder_rate: 0.0,
+            min_delay: 0,
+            max_delay: 0
+        }
+    }
+}
+
+#[cfg(feature = "fault-injection")]
+impl LinkFaults {
+    /// Create faults for a link that works perfectly
+    pub fn new() -> LinkFaults {
+        LinkFaults::default()
+    }
+
+    pub fn partition(mut self) -> LinkFaults {
+        self.partition = true;
+        self
+    }
+
+    pub fn drop_rate(mut self, p: f64) -> LinkFaults {
+        self.drop_rate = p;
+        self
+    }
+
+    pub fn duplicate_rate(mut self, p: f64) -> LinkFaults {
+        self.duplicate_rate = p;
+        self
+    }
+
+    pub fn reorder_rate(mut self, p: f64) -> LinkFaults {
+        self.reorder_rate = p;
+        self
+    }
+
+    pub fn delay(mut self, min_ms: usize, max_ms: usize) -> LinkFaults {
+        self.min_delay = min_ms;
+        self.max_delay = max_ms;
+        self
+    }
+}
+
+/// Whether a message is sent to or received from a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    Outgoing,
+    Incoming
+}
+
+/// The faults injected into the links to each peer of the cluster server, along with the messages
+/// held back by them
+pub struct FaultInjector<T: Encodable + Decodable + Debug + Clone> {
+    links: BTreeMap<NodeId, LinkFaults>,
+    rng: Rng,
+
+    /// Delayed messages ordered by the time they are due, and by the order they were delayed in
+    delayed: BTreeMap<(SteadyTime, u64), (NodeId, Direction, ExternalMsg<T>)>,
+    next_seq: u64,
+
+    /// A message held back on each link until the next one passes it
+    reordered: BTreeMap<(NodeId, Direction), ExternalMsg<T>>
+}
+
+impl<T: Encodable + Decodable + Debug + Clone> FaultInjector<T> {
+    /// Create an injector for the cluster server of `node`. Its choices only depend on the node, so
+    /// that a `Simulation` injects the same faults every time it's run with the same seed.
+    pub fn new(node: &NodeId) -> FaultInjector<T> {
+        let seed = node.to_string().bytes().fold(0, |seed: u64, byte| {
+            seed.wrapping_mul(31).wrapping_add(byte as u64)
+        });
+        FaultInjector {
+            links: BTreeMap::new(),
+            rng: Rng::new(seed),
+            delayed: BTreeMap::new(),
+            next_seq: 0,
+            reordered: BTreeMap::new()
+        }
+    }
+
+    /// Replace the faults of the link to `node`. Messages already held back are still delivered.
+    #[cfg(feature = "fault-injection")]
+    pub fn set(&mut self, node: NodeId, faults: LinkFaults) {
+        if faults == LinkFaults::default() {
+            self.links.remove(&node);
+        } else {
+            self.links.insert(node, faults);
+        }
+    }
+
+    pub fn is_partitioned(&self, node: &NodeId) -> bool {
+        self.links.get(node).map_or(false, |faults| faults.partition)
+    }
+
+    /// Apply the faults of the link to `node` to a message sent or received on it. Return the
+    /// messages to pass on now, in order.
+    pub fn apply(&mut self,
+                 node: &NodeId,
+                 direction: Direction,
+                 msg: ExternalMsg<T>,
+                 now: SteadyTime) -> Vec<ExternalMsg<T>>
+    {
+        let faults = match self.links.get(node) {
+            Some(faults) => faults.clone(),
+            None => return vec![msg]
+        };
+        if faults.partition {
+            return Vec::new();
+        }
+        if !is_envelope_traffic(&msg) {
+            return vec![msg];
+        }
+        if self.rng.chance(faults.drop_rate) {
+            return Vec::new();
+        }
+        let mut output = vec![msg];
+        if self.rng.chance(faults.duplicate_rate) {
+            let duplicate = output[0].clone();
+            output.push(duplicate);
+        }
+        let delay = self.rng.between(faults.min_delay, faults.max_delay);
+        if delay > 0 {
+            let due = now + Duration::milliseconds(delay as i64);
+            for msg in output {
+                self.delayed.insert((due, self.next_seq), (node.clone(), direction, msg));
+                self.next_seq += 1;
+            }
+            return Vec::new();
+        }
+        let key = (node.clone(), direction);
+        if let Some(held) = self.reordered.remove(&key) {
+            output.push(held);
+        } else if self.rng.chance(faults.reorder_rate) {
+            self.reordered.insert(key, output.remove(0));
+        }
+        output
+    }
+
+    /// Remove and return all delayed messages that are due, and all messages held back for
+    /// reordering
+    pub fn expire(&mut self, now: SteadyTime) -> Vec<(NodeId, Direction, ExternalMsg<T>)> {
+        let mut expired = Vec::new();
+        while self.delayed.keys().next().map_or(false, |&(due, _)| due <= now) {
+            let key = *self.delayed.keys().next().unwrap();
+            expired.push(self.delayed.remove(&key).unwrap());
+        }
+        let reordered = mem::replace(&mut self.reordered, BTreeMap::new());
+        for ((node, direction), msg) in reordered {
+            expired.push((node, direction, msg));
+        }
+        expired
+    }
+}
+
+/// Return true if a message carries an envelope or acknowledges one
+fn is_envelope_traffic<T>(msg: &ExternalMsg<T>) -> bool
+    where T: Encodable + Decodable + Debug + Clone
+{
+    match *msg {
+        ExternalMsg::Envelope(_) | ExternalMsg::Reliable {..} | ExternalMsg::Ack {..} => true,
+        _ => false
+    }
+}
@@ -9,5 +9,15 @@ metrics!(ClusterMetrics {
     received_remote_envelopes: u64,
     status_requests: u64,
     accepted_connections: u64,
-    connection_attempts: u64
+    connection_attempts: u64,
+    spawn_requests: u64,
+    queued_envelopes: u64,
+    dropped_queue_full: u64,
+    dropped_expired: u64,
+    dropped_node_unreachable: u64,
+    decode_failures: u64,
+    retransmitted_envelopes: u64,
+    duplicate_envelopes: u64,
+    auth_failures: u64,
+    incompatible_peers: u64
 });
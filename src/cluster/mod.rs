@@ -1,12 +1,24 @@
 mod server;
 mod status;
+mod event;
 mod msg;
 mod metrics;
+mod failure_detector;
+mod outbound;
+mod reliable;
+mod socket;
+mod cookie;
+#[cfg(feature = "fault-injection")]
+mod faults;
 
-pub use self::server::ClusterServer;
+pub use self::server::{ClusterServer, cluster_server_pid};
 pub use self::status::ClusterStatus;
+pub use self::event::ClusterEvent;
 pub use self::msg::{
     ClusterMsg,
     ExternalMsg
 };
 pub use self::metrics::ClusterMetrics;
+pub use self::failure_detector::FailureDetectorConfig;
+#[cfg(feature = "fault-injection")]
+pub use self::faults::LinkFaults;
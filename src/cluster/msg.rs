@@ -5,6 +5,12 @@ use orset::{ORSet, Delta};
 use node_id::NodeId;
 use envelope::Envelope;
 use correlation_id::CorrelationId;
+use pid::Pid;
+use registry;
+use groups;
+use process_spec::{ProcessSpec, Factory};
+#[cfg(feature = "fault-injection")]
+use super::LinkFaults;
 
 /// Messages sent to the Cluster Server
 pub enum ClusterMsg<T: Encodable + Decodable + Debug + Clone> {
@@ -12,8 +18,56 @@ pub enum ClusterMsg<T: Encodable + Decodable + Debug + Clone> {
     Join(NodeId),
     Leave(NodeId),
     Envelope(Envelope<T>),
+
+    /// An envelope to a remote process that is retained until the peer acknowledges it
+    ReliableEnvelope(Envelope<T>),
+
     GetStatus(CorrelationId),
-    Shutdown
+    RegisterFactory(String, Factory<T>),
+    Spawn(Pid, ProcessSpec<T>, CorrelationId),
+    #[cfg(feature = "fault-injection")]
+    SetFaults(NodeId, LinkFaults),
+    Shutdown,
+
+    /// Sent by each executor worker once it has stopped during shutdown
+    ExecutorStopped
+}
+
+/// The version of the protocol spoken between nodes. It must be incremented whenever the encoding
+/// of `Hello`, `ExternalMsg`, `Envelope` or `Msg` changes.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The first frame sent by each side of a connection between nodes
+///
+/// `Hello` is encoded separately from `ExternalMsg`, so that it can be decoded even when the peer
+/// uses an incompatible version of `ExternalMsg`. A peer is only accepted if it speaks the same
+/// protocol version and uses the same version of the user message type, as configured by
+/// `RabbleConfig::msg_version`.
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Hello {
+    pub version: u32,
+    pub msg_version: u32
+}
+
+impl Hello {
+    pub fn new(msg_version: u32) -> Hello {
+        Hello {
+            version: PROTOCOL_VERSION,
+            msg_version: msg_version
+        }
+    }
+
+    /// Return why a peer that sent this `Hello` can't talk to a node using `msg_version` of the
+    /// user message type, if it can't
+    pub fn incompatibility(&self, msg_version: u32) -> Option<String> {
+        if self.version != PROTOCOL_VERSION {
+            Some(format!("protocol version {} is not {}", self.version, PROTOCOL_VERSION))
+        } else if self.msg_version != msg_version {
+            Some(format!("user message version {} is not {}", self.msg_version, msg_version))
+        } else {
+            None
+        }
+    }
 }
 
 /// A message sent between nodes in Rabble.
@@ -23,5 +77,25 @@ pub enum ExternalMsg<T: Encodable + Decodable + Debug + Clone> {
    Members {from: NodeId, orset: ORSet<NodeId>},
    Ping,
    Envelope(Envelope<T>),
-   Delta(Delta<NodeId>)
+   Delta(Delta<NodeId>),
+   Spawn(Pid, ProcessSpec<T>, CorrelationId),
+   Names(ORSet<registry::Entry>),
+   NameDelta(Delta<registry::Entry>),
+   Groups(ORSet<groups::Member>),
+   GroupDelta(Delta<groups::Member>),
+
+   /// The sending node is shutting down and will close the connection
+   ShuttingDown,
+
+   /// An envelope sent with `Delivery::AtLeastOnce`. `seq` is the sequence number of the envelope
+   /// in the sender's `session`, and all envelopes up to and including `acked` were acknowledged.
+   Reliable {session: u64, seq: u64, acked: u64, envelope: Envelope<T>},
+
+   /// Acknowledge receipt of all reliable envelopes up to and including `seq` in `session`
+   Ack {session: u64, seq: u64},
+
+   /// A random challenge that the peer must answer with a `ChallengeResponse` proving it knows the
+   /// cluster cookie. Sent before any other message when a cookie is configured.
+   Challenge(Vec<u8>),
+   ChallengeResponse(Vec<u8>)
 }
@@ -0,0 +1,59 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Debug;
+use rustc_serialize::{Encodable, Decodable};
+use time::{SteadyTime, Duration};
+use node_id::NodeId;
+use envelope::Envelope;
+
+/// Bounded per-peer queues of envelopes waiting for a connection to be established
+pub struct OutboundQueues<T: Encodable + Decodable + Debug + Clone> {
+    max_size: usize,
+    timeout: Duration,
+    queues: BTreeMap<NodeId, VecDeque<(SteadyTime, Envelope<T>)>>
+}
+
+impl<T: Encodable + Decodable + Debug + Clone> OutboundQueues<T> {
+    /// Create queues holding at most `max_size` envelopes per peer for `timeout` ms each
+    pub fn new(max_size: usize, timeout: usize) -> OutboundQueues<T> {
+        OutboundQueues {
+            max_size: max_size,
+            timeout: Duration::milliseconds(timeout as i64),
+            queues: BTreeMap::new()
+        }
+    }
+
+    /// Queue an envelope for the node it is addressed to. Return the envelope if the queue is full.
+    ///
+    /// Envelopes sent with `Delivery::AtLeastOnce` are retained by the reliable delivery state
+    /// instead, so that they don't expire.
+    pub fn push(&mut self, envelope: Envelope<T>, now: SteadyTime) -> Result<(), Envelope<T>> {
+        let max_size = self.max_size;
+        let queue = self.queues.entry(envelope.to.node.clone()).or_insert_with(VecDeque::new);
+        if queue.len() >= max_size {
+            return Err(envelope);
+        }
+        queue.push_back((now, envelope));
+        Ok(())
+    }
+
+    /// Remove and return all envelopes queued for `node` in the order they were queued
+    pub fn take(&mut self, node: &NodeId) -> Vec<Envelope<T>> {
+        self.queues.remove(node).map_or(Vec::new(), |queue| {
+            queue.into_iter().map(|(_, envelope)| envelope).collect()
+        })
+    }
+
+    /// Remove and return all envelopes that were queued longer than the timeout
+    pub fn expire(&mut self, now: SteadyTime) -> Vec<Envelope<T>> {
+        let timeout = self.timeout;
+        let mut expired = Vec::new();
+        for queue in self.queues.values_mut() {
+            // Envelopes are queued in time order, so only the front of the queue can expire
+            while queue.front().map_or(false, |&(queued_at, _)| now - queued_at >= timeout) {
+                expired.push(queue.pop_front().unwrap().1);
+            }
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+        expired
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::{BTreeMap, VecDeque, BTreeSet};
+use std::fmt::Debug;
+use rustc_serialize::{Encodable, Decodable};
+use time;
+use node_id::NodeId;
+use envelope::Envelope;
+
+/// Sequence numbers and acknowledgements for envelopes sent with `Delivery::AtLeastOnce`
+///
+/// Each reliable envelope is assigned the next sequence number for its destination node and
+/// retained until the peer acknowledges it. Sequence numbers are scoped to a session that
+/// identifies this run of the node, so that a restarted node starts over at 1 without its
+/// envelopes being mistaken for duplicates by its peers.
+pub struct Reliable<T: Encodable + Decodable + Debug + Clone> {
+    session: u64,
+    max_unacked: usize,
+    outgoing: BTreeMap<NodeId, Outgoing<T>>,
+    incoming: BTreeMap<NodeId, Incoming>
+}
+
+/// Envelopes sent to a single peer
+struct Outgoing<T: Encodable + Decodable + Debug + Clone> {
+    next_seq: u64,
+    unacked: VecDeque<(u64, Envelope<T>)>
+}
+
+impl<T: Encodable + Decodable + Debug + Clone> Outgoing<T> {
+    /// Every envelope with a lower sequence number than the first unacked one was acknowledged
+    fn acked(&self) -> u64 {
+        self.unacked.front().map_or(self.next_seq - 1, |&(seq, _)| seq - 1)
+    }
+}
+
+/// Envelopes received from a single peer in its current session
+struct Incoming {
+    session: u64,
+
+    /// All sequence numbers up to and including this one were received
+    received: u64,
+
+    /// Sequence numbers greater than `received + 1` that were received out of order
+    ahead: BTreeSet<u64>,
+
+    ack_pending: bool
+}
+
+impl Incoming {
+    fn new(session: u64) -> Incoming {
+        Incoming {
+            session: session,
+            received: 0,
+            ahead: BTreeSet::new(),
+            ack_pending: false
+        }
+    }
+}
+
+impl<T: Encodable + Decodable + Debug + Clone> Reliable<T> {
+    /// Create the state for a new session, retaining at most `max_unacked` envelopes per peer
+    pub fn new(max_unacked: usize) -> Reliable<T> {
+        let now = time::get_time();
+        Reliable {
+            session: now.sec as u64 * 1_000_000_000 + now.nsec as u64,
+            max_unacked: max_unacked,
+            outgoing: BTreeMap::new(),
+            incoming: BTreeMap::new()
+        }
+    }
+
+    pub fn session(&self) -> u64 {
+        self.session
+    }
+
+    /// Assign the next sequence number to an envelope and retain a copy of it until it's
+    /// acknowledged.
+    ///
+    /// Return the sequence number and the sequence number up to which all envelopes to the same
+    /// peer were acknowledged, or the envelope if too many are already unacknowledged.
+    pub fn send(&mut self, envelope: Envelope<T>) -> Result<(u64, u64, Envelope<T>), Envelope<T>> {
+        let max_unacked = self.max_unacked;
+        let outgoing = self.outgoing.entry(envelope.to.node.clone()).or_insert_with(|| {
+            Outgoing {
+                next_seq: 1,
+                unacked: VecDeque::new()
+            }
+        });
+        if outgoing.unacked.len() >= max_unacked {
+            return Err(envelope);
+        }
+        let seq = outgoing.next_seq;
+        outgoing.next_seq += 1;
+        outgoing.unacked.push_back((seq, envelope.clone()));
+        Ok((seq, outgoing.acked(), envelope))
+    }
+
+    /// Return all unacknowledged envelopes to `node` in order, along with their sequence numbers
+    /// and the highest acknowledged sequence number.
+    pub fn unacked(&self, node: &NodeId) -> Vec<(u64, u64, Envelope<T>)> {
+        self.outgoing.get(node).map_or(Vec::new(), |outgoing| {
+            let acked = outgoing.acked();
+            outgoing.unacked.iter().map(|&(seq, ref envelope)| {
+                (seq, acked, envelope.clone())
+            }).collect()
+        })
+    }
+
+    /// Release all envelopes to `node` with sequence numbers up to and including `seq`
+    pub fn ack(&mut self, node: &NodeId, session: u64, seq: u64) {
+        if session != self.session {
+            // An ack for an envelope sent before this node restarted
+            return;
+        }
+        if let Some(outgoing) = self.outgoing.get_mut(node) {
+            while outgoing.unacked.front().map_or(false, |&(s, _)| s <= seq) {
+                outgoing.unacked.pop_front();
+            }
+        }
+    }
+
+    /// Record the receipt of envelope `seq` from `node`. `acked` is the sequence number up to
+    /// which the sender has already had all envelopes acknowledged.
+    ///
+    /// Return true if the envelope should be delivered, and false if it's a duplicate.
+    pub fn receive(&mut self, node: &NodeId, session: u64, seq: u64, acked: u64) -> bool {
+        let incoming = self.incoming.entry(node.clone()).or_insert_with(|| Incoming::new(session));
+        if incoming.session != session {
+            // The peer restarted
+            *incoming = Incoming::new(session);
+        }
+        incoming.ack_pending = true;
+
+        // Envelopes acknowledged by the sender were either received or were received by a previous
+        // run of this node
+        if acked > incoming.received {
+            incoming.received = acked;
+            incoming.ahead = incoming.ahead.split_off(&(acked + 1));
+        }
+
+        if seq <= incoming.received || !incoming.ahead.insert(seq) {
+            return false;
+        }
+        while incoming.ahead.remove(&(incoming.received + 1)) {
+            incoming.received += 1;
+        }
+        true
+    }
+
+    /// Return the session and sequence number to acknowledge for each peer that sent reliable
+    /// envelopes since the last call.
+    pub fn pending_acks(&mut self) -> Vec<(NodeId, u64, u64)> {
+        self.incoming.iter_mut().filter(|&(_, ref incoming)| incoming.ack_pending)
+            .map(|(node, incoming)| {
+                incoming.ack_pending = false;
+                (node.clone(), incoming.session, incoming.received)
+            }).collect()
+    }
+
+    /// Forget all state for a node that left the cluster, and return its unacknowledged envelopes
+    pub fn remove_node(&mut self, node: &NodeId) -> Vec<Envelope<T>> {
+        self.incoming.remove(node);
+        self.outgoing.remove(node).map_or(Vec::new(), |outgoing| {
+            outgoing.unacked.into_iter().map(|(_, envelope)| envelope).collect()
+        })
+    }
+}
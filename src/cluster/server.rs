@@ -1,56 +1,112 @@
-use std::sync::mpsc::{self, Sender, Receiver};
-use std::collections::{HashMap, HashSet};
-use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::mem;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::collections::{HashMap, BTreeMap, BTreeSet};
 use std::fmt::Debug;
-use libc::EINPROGRESS;
-use net2::{TcpBuilder, TcpStreamExt};
 use rustc_serialize::{Encodable, Decodable};
 use msgpack::{Encoder, Decoder};
 use slog;
-use amy::{Registrar, Notification, Event, Timer, FrameReader, FrameWriter};
+use time::{SteadyTime, Duration};
+use amy::{self, Registrar, Notification, Event, Timer, FrameReader, FrameWriter};
 use members::Members;
+use registry::{self, Registry};
+use groups::{self, Groups};
 use node_id::NodeId;
 use msg::Msg;
-use executor::ExecutorMsg;
-use timer_wheel::TimerWheel;
-use envelope::Envelope;
-use orset::{ORSet, Delta};
+use executor::{ExecutorMsg, Workers, executor_pid, panic_description};
+use envelope::{Envelope, Delivery};
+use orset::Delta;
 use pid::Pid;
+use delivery_failure::{self, DeliveryFailure};
+use process_spec::{ProcessSpec, Factory};
 use correlation_id::CorrelationId;
 use errors::*;
 use metrics::Metrics;
-use super::{ClusterStatus, ClusterMsg, ExternalMsg, ClusterMetrics};
-
-// TODO: This is totally arbitrary right now and should probably be user configurable
-const MAX_FRAME_SIZE: u32 = 100*1024*1024; // 100 MB
-const TICK_TIME: usize = 1000; // milliseconds
-const REQUEST_TIMEOUT: usize = 5000; // milliseconds
-
-// This tick allows process specific timers to fire
-const EXECUTOR_TICK_TIME: usize = 100; // milliseconds
+use config::RabbleConfig;
+use clock::Clock;
+use tls::Tls;
+use transport::{Transport, Stream};
+use super::{ClusterStatus, ClusterEvent, ClusterMsg, ExternalMsg, ClusterMetrics};
+#[cfg(feature = "fault-injection")]
+use super::LinkFaults;
+#[cfg(feature = "fault-injection")]
+use super::faults::{FaultInjector, Direction};
+use super::msg::Hello;
+use super::failure_detector::PhiAccrualDetector;
+use super::outbound::OutboundQueues;
+use super::reliable::Reliable;
+use super::socket::Socket;
+use super::cookie::Cookie;
 
 struct Conn {
-    sock: TcpStream,
+    sock: Socket,
     node: Option<NodeId>,
     is_client: bool,
+
+    hello_sent: bool,
+
+    /// Whether the peer sent a compatible `Hello`
+    hello_received: bool,
     members_sent: bool,
-    timer_wheel_index: usize,
+
+    /// The cookie challenge sent to the peer, if any
+    challenge: Option<Vec<u8>>,
+
+    /// Whether the peer proved it knows the cookie. Always true if no cookie is configured.
+    authenticated: bool,
+
+    /// Whether the peer is partitioned from this node by an injected fault. Nothing is written to
+    /// or read from the connection while it is.
+    #[cfg(feature = "fault-injection")]
+    partitioned: bool,
+    detector: PhiAccrualDetector,
     reader: FrameReader,
     writer: FrameWriter
 }
 
 impl Conn {
-    pub fn new(sock: TcpStream, node: Option<NodeId>, is_client: bool) -> Conn {
+    pub fn new(sock: Socket,
+               node: Option<NodeId>,
+               is_client: bool,
+               authenticated: bool,
+               max_frame_size: u32,
+               now: SteadyTime) -> Conn {
         Conn {
             sock: sock,
             node: node,
             is_client: is_client,
+            hello_sent: false,
+            hello_received: false,
             members_sent: false,
-            timer_wheel_index: 0, // Initialize with a fake value
-            reader: FrameReader::new(MAX_FRAME_SIZE),
+            challenge: None,
+            authenticated: authenticated,
+            #[cfg(feature = "fault-injection")]
+            partitioned: false,
+            detector: PhiAccrualDetector::new(now),
+            reader: FrameReader::new(max_frame_size),
             writer: FrameWriter::new(),
         }
     }
+
+    #[cfg(feature = "fault-injection")]
+    fn is_partitioned(&self) -> bool {
+        self.partitioned
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn is_partitioned(&self) -> bool {
+        false
+    }
+}
+
+/// Progress of a graceful shutdown
+struct ShutdownState {
+    /// Stop flushing writes to peers at this time
+    deadline: SteadyTime,
+
+    /// The number of executor workers that haven't stopped yet
+    executors: usize
 }
 
 /// A struct that handles cluster membership connection and routing of messages to processes on
@@ -59,15 +115,31 @@ pub struct ClusterServer<T: Encodable + Decodable + Debug + Clone> {
     pid: Pid,
     node: NodeId,
     rx: Receiver<ClusterMsg<T>>,
-    executor_tx: Sender<ExecutorMsg<T>>,
+    executors: Workers<T>,
     executor_timer: Timer,
     timer: Timer,
-    timer_wheel: TimerWheel<usize>,
-    listener: TcpListener,
+    config: RabbleConfig,
+    tls: Option<Tls>,
+    cookie: Option<Cookie>,
+    transport: Box<Transport>,
+    clock: Clock,
     listener_id: usize,
     members: Members,
-    connections: HashMap<usize, Conn>,
-    established: HashMap<NodeId, usize>,
+    registry: Registry,
+    groups: Groups,
+    subscribers: BTreeSet<Pid>,
+
+    // Ordered collections are used wherever iteration order determines the order of messages, so
+    // that a `Simulation` with the same seed always runs the same way.
+    connections: BTreeMap<usize, Conn>,
+    established: BTreeMap<NodeId, usize>,
+    outbound: OutboundQueues<T>,
+    reliable: Reliable<T>,
+    #[cfg(feature = "fault-injection")]
+    faults: FaultInjector<T>,
+    factories: HashMap<String, Factory<T>>,
+    shutdown: Option<ShutdownState>,
+    stop_poller: amy::Sender<()>,
     registrar: Registrar,
     logger: slog::Logger,
     metrics: ClusterMetrics
@@ -76,67 +148,119 @@ pub struct ClusterServer<T: Encodable + Decodable + Debug + Clone> {
 impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
     pub fn new(node: NodeId,
                rx: Receiver<ClusterMsg<T>>,
-               executor_tx: Sender<ExecutorMsg<T>>,
+               executors: Workers<T>,
                registrar: Registrar,
-               logger: slog::Logger) -> ClusterServer<T> {
-        let pid = Pid {
-            group: Some("rabble".to_string()),
-            name: "cluster_server".to_string(),
-            node: node.clone()
+               stop_poller: amy::Sender<()>,
+               config: RabbleConfig,
+               mut transport: Box<Transport>,
+               clock: Clock,
+               logger: slog::Logger) -> Result<ClusterServer<T>> {
+        let pid = cluster_server_pid(node.clone());
+        let addr = config.listen_addr.clone().unwrap_or(node.addr.clone());
+
+        // Nothing is polled until the poller thread starts, so registering early just allows
+        // failures to be reported at startup.
+        let listener_id = try!(transport.listen(&addr, &registrar)
+                               .chain_err(|| ErrorKind::BindError(addr)));
+        let timer = try!(registrar.set_interval(config.tick_time)
+                         .chain_err(|| ErrorKind::RegistrarError(None, None)));
+        let executor_timer = try!(registrar.set_interval(config.executor_tick_time)
+                                  .chain_err(|| ErrorKind::RegistrarError(None, None)));
+        let outbound = OutboundQueues::new(config.outbound_queue_size,
+                                           config.outbound_queue_timeout);
+        let reliable = Reliable::new(config.max_unacked);
+        #[cfg(feature = "fault-injection")]
+        let faults = FaultInjector::new(&node);
+        let cookie = config.cookie.as_ref().map(|cookie| Cookie::new(cookie));
+        let tls = match config.tls {
+            Some(ref tls) => Some(try!(tls.load())),
+            None => None
         };
-        // We don't want to actually start polling yet, so create a dummy timer.
-        let dummy_timer = Timer {id: 0, fd: 0};
-        let listener = TcpListener::bind(&node.addr[..]).unwrap();
-        listener.set_nonblocking(true).unwrap();
-        ClusterServer {
+        Ok(ClusterServer {
             pid: pid,
             node: node.clone(),
             rx: rx,
-            executor_tx: executor_tx,
-            executor_timer: dummy_timer.clone(),
-            timer: dummy_timer,
-            timer_wheel: TimerWheel::new(REQUEST_TIMEOUT / TICK_TIME),
-            listener: listener,
-            listener_id: 0,
-            members: Members::new(node),
-            connections: HashMap::new(),
-            established: HashMap::new(),
+            executors: executors,
+            executor_timer: executor_timer,
+            timer: timer,
+            config: config,
+            tls: tls,
+            cookie: cookie,
+            transport: transport,
+            clock: clock,
+            listener_id: listener_id,
+            members: Members::new(node.clone()),
+            registry: Registry::new(node.clone()),
+            groups: Groups::new(node),
+            subscribers: BTreeSet::new(),
+            connections: BTreeMap::new(),
+            established: BTreeMap::new(),
+            outbound: outbound,
+            reliable: reliable,
+            #[cfg(feature = "fault-injection")]
+            faults: faults,
+            factories: HashMap::new(),
+            shutdown: None,
+            stop_poller: stop_poller,
             registrar: registrar,
             logger: logger.new(o!("component" => "cluster_server")),
             metrics: ClusterMetrics::new()
-        }
+        })
     }
 
     pub fn run(mut self) {
         info!(self.logger, "Starting");
-        self.timer = self.registrar.set_interval(TICK_TIME).unwrap();
-        self.executor_timer = self.registrar.set_interval(EXECUTOR_TICK_TIME).unwrap();
-        self.listener_id = self.registrar.register(&self.listener, Event::Read).unwrap();
         while let Ok(msg) = self.rx.recv() {
-            if let Err(e) = self.handle_cluster_msg(msg) {
-                self.metrics.errors += 1;
-                for id in e.kind().get_ids() {
-                    self.close(id)
+            if !self.handle(msg) {
+                break;
+            }
+        }
+        // Wake up the poller thread so that it exits
+        let _ = self.stop_poller.send(());
+    }
+
+    /// Handle a single message. Return false if the cluster server must exit.
+    ///
+    /// `run` calls this for every received message. A `Simulation` calls it directly, so that it
+    /// controls when each message is handled.
+    pub(crate) fn handle(&mut self, msg: ClusterMsg<T>) -> bool {
+        if let Err(e) = self.handle_cluster_msg(msg) {
+            self.metrics.errors += 1;
+            for id in e.kind().get_ids() {
+                self.close(id)
+            }
+            match *e.kind() {
+                ErrorKind::EncodeError(..) | ErrorKind::DecodeError(..) |
+                ErrorKind::RegistrarError(..) | ErrorKind::SendError(..) => {
+                    error!(self.logger, e.to_string());
+                    return false;
                 }
-                match *e.kind() {
-                    ErrorKind::EncodeError(..) | ErrorKind::DecodeError(..) |
-                    ErrorKind::RegistrarError(..) | ErrorKind::SendError(..) => {
-                        error!(self.logger, e.to_string());
-                        break;
-                    }
 
-                    ErrorKind::Shutdown(..) => {
-                        info!(self.logger, e.to_string());
-                        break;
-                    },
+                ErrorKind::Shutdown(..) => {
+                    info!(self.logger, e.to_string());
+                    return false;
+                },
 
-                    _ => warn!(self.logger, e.to_string())
-                }
+                _ => warn!(self.logger, e.to_string())
             }
         }
+        true
+    }
+
+    /// Return the next received message without blocking, if there is one
+    pub(crate) fn try_recv(&self) -> Option<ClusterMsg<T>> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Return the ids of the poll notifications of the cluster timer and the executor timer
+    pub(crate) fn timer_ids(&self) -> (usize, usize) {
+        (self.timer.id, self.executor_timer.id)
     }
 
     fn handle_cluster_msg(&mut self, msg: ClusterMsg<T>) -> Result<()> {
+        if self.shutdown.is_some() {
+            return self.handle_cluster_msg_during_shutdown(msg);
+        }
         match msg {
             ClusterMsg::PollNotifications(notifications) => {
                 self.metrics.poll_notifications += 1;
@@ -152,26 +276,143 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
             },
             ClusterMsg::Envelope(envelope) => {
                 self.metrics.received_local_envelopes += 1;
-                // Only metric requests are directly sent to the cluster server
                 if envelope.to == self.pid {
-                    self.send_metrics(envelope);
-                    return Ok(());
+                    return self.handle_envelope(envelope);
                 }
-                self.send_remote(envelope)
+                self.send_remote(envelope, Delivery::AtMostOnce)
+            },
+            ClusterMsg::ReliableEnvelope(envelope) => {
+                self.metrics.received_local_envelopes += 1;
+                self.send_remote(envelope, Delivery::AtLeastOnce)
             },
             ClusterMsg::GetStatus(correlation_id) => {
                 self.metrics.status_requests += 1;
                 self.get_status(correlation_id)
             },
-            ClusterMsg::Shutdown => Err(ErrorKind::Shutdown(self.pid.clone()).into())
+            ClusterMsg::RegisterFactory(name, factory) => {
+                self.factories.insert(name, factory);
+                Ok(())
+            },
+            ClusterMsg::Spawn(pid, spec, correlation_id) => {
+                self.metrics.spawn_requests += 1;
+                self.send_spawn(pid, spec, correlation_id)
+            },
+            #[cfg(feature = "fault-injection")]
+            ClusterMsg::SetFaults(node, faults) => {
+                self.set_faults(node, faults);
+                Ok(())
+            },
+            ClusterMsg::Shutdown => self.start_shutdown(),
+            ClusterMsg::ExecutorStopped => Ok(())
+        }
+    }
+
+    /// Replace the faults injected into the link to `node`
+    #[cfg(feature = "fault-injection")]
+    fn set_faults(&mut self, node: NodeId, faults: LinkFaults) {
+        info!(self.logger, "Injecting faults";
+              "peer" => node.to_string(), "faults" => format!("{:?}", faults));
+        for conn in self.connections.values_mut() {
+            if conn.node.as_ref() == Some(&node) {
+                conn.partitioned = faults.partition;
+            }
         }
+        self.faults.set(node, faults);
+    }
+
+    /// Start a graceful shutdown of the node.
+    ///
+    /// The executor workers are stopped first. Before they stop, they forward any envelopes for
+    /// remote processes they have already received. Once all workers have stopped, peers are told
+    /// that this node is shutting down, and the cluster server exits once all pending writes are
+    /// flushed or the shutdown timeout expires.
+    fn start_shutdown(&mut self) -> Result<()> {
+        info!(self.logger, "Shutting down");
+        let timeout = Duration::milliseconds(self.config.shutdown_timeout as i64);
+        self.shutdown = Some(ShutdownState {
+            deadline: self.clock.now() + timeout,
+            executors: self.executors.len()
+        });
+        // A worker that already exited will never acknowledge, so the deadline still applies
+        let _ = self.executors.broadcast(|| ExecutorMsg::Shutdown);
+        Ok(())
+    }
+
+    /// Only flush envelopes to remote processes and pending writes while shutting down. Everything
+    /// else is dropped, since the executor workers may have stopped already.
+    fn handle_cluster_msg_during_shutdown(&mut self, msg: ClusterMsg<T>) -> Result<()> {
+        let result = match msg {
+            ClusterMsg::PollNotifications(notifications) => {
+                self.flush_writes(notifications)
+            },
+            ClusterMsg::Envelope(envelope) => {
+                if envelope.to.node != self.node {
+                    self.send_remote(envelope, Delivery::AtMostOnce)
+                } else {
+                    Ok(())
+                }
+            },
+            ClusterMsg::ReliableEnvelope(envelope) => {
+                self.send_remote(envelope, Delivery::AtLeastOnce)
+            },
+            ClusterMsg::ExecutorStopped => {
+                let stopped = self.shutdown.as_mut().map_or(false, |state| {
+                    state.executors -= 1;
+                    state.executors == 0
+                });
+                if stopped {
+                    self.broadcast_shutting_down()
+                } else {
+                    Ok(())
+                }
+            },
+            _ => Ok(())
+        };
+        try!(self.check_shutdown_complete());
+        result
+    }
+
+    fn flush_writes(&mut self, notifications: Vec<Notification>) -> Result<()> {
+        let mut errors = Vec::new();
+        for n in notifications {
+            if n.id == self.timer.id {
+                self.timer.arm();
+            } else if n.id == self.executor_timer.id {
+                self.executor_timer.arm();
+            } else if let Event::Read = n.event {
+                continue;
+            } else if let Err(e) = self.write(n.id, None) {
+                errors.push(e);
+            }
+        }
+        if errors.len() != 0 {
+            return Err(ErrorKind::PollNotificationErrors(errors).into());
+        }
+        Ok(())
+    }
+
+    /// Return an `ErrorKind::Shutdown` to exit the cluster server if the shutdown is complete
+    fn check_shutdown_complete(&self) -> Result<()> {
+        if let Some(ref state) = self.shutdown {
+            let flushed = self.connections.values().all(|conn| conn.writer.is_empty());
+            if state.executors == 0 && flushed {
+                return Err(ErrorKind::Shutdown(self.pid.clone()).into());
+            }
+            if self.clock.now() >= state.deadline {
+                warn!(self.logger, "Shutdown timeout expired";
+                      "executors_running" => state.executors, "flushed" => flushed);
+                return Err(ErrorKind::Shutdown(self.pid.clone()).into());
+            }
+        }
+        Ok(())
     }
 
     fn get_status(&self, correlation_id: CorrelationId) -> Result<()> {
         let status = ClusterStatus {
-            members: self.members.all(),
+            members: self.members.all().into_iter().collect(),
             established: self.established.keys().cloned().collect(),
-            num_connections: self.connections.len()
+            num_connections: self.connections.len(),
+            phi: self.phi()
         };
         let envelope = Envelope {
             to: correlation_id.pid.clone(),
@@ -179,9 +420,25 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
             msg: Msg::ClusterStatus(status),
             correlation_id: Some(correlation_id)
         };
-        // Route the response through the executor since it knows how to contact all Pids
+        self.send_local(envelope)
+    }
+
+    /// Return the suspicion level of each peer with an established connection in thousandths
+    fn phi(&self) -> HashMap<NodeId, u64> {
+        let now = self.clock.now();
+        self.established.iter().filter_map(|(node, id)| {
+            self.connections.get(id).map(|conn| {
+                // Casting saturates, so an infinite phi is reported as u64::MAX
+                let phi = conn.detector.phi(&self.config.failure_detector, now);
+                (node.clone(), (phi * 1000.0).round() as u64)
+            })
+        }).collect()
+    }
+
+    /// Route an envelope through the executor since it knows how to contact all local Pids
+    fn send_local(&self, envelope: Envelope<T>) -> Result<()> {
         if let Err(mpsc::SendError(ExecutorMsg::Envelope(envelope))) =
-            self.executor_tx.send(ExecutorMsg::Envelope(envelope))
+            self.executors.send_envelope(envelope)
         {
             return Err(ErrorKind::SendError("ExecutorMsg::Envelope".to_string(),
                                             Some(envelope.to)).into());
@@ -189,18 +446,433 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
         Ok(())
     }
 
-    fn send_remote(&mut self, envelope: Envelope<T>) -> Result<()> {
-        if let Some(id) = self.established.get(&envelope.to.node).cloned() {
-            trace!(self.logger, "send remote"; "to" => envelope.to.to_string());
+    /// Forward a spawn request to the node of `pid`, or start the process if it is local.
+    fn send_spawn(&mut self,
+                  pid: Pid,
+                  spec: ProcessSpec<T>,
+                  correlation_id: CorrelationId) -> Result<()>
+    {
+        if pid.node == self.node {
+            return self.spawn(pid, spec, correlation_id);
+        }
+        if let Some(id) = self.established.get(&pid.node).cloned() {
             let mut encoded = Vec::new();
-            let node = envelope.to.node.clone();
-            try!(ExternalMsg::Envelope(envelope).encode(&mut Encoder::new(&mut encoded))
+            let node = pid.node.clone();
+            let msg = ExternalMsg::Spawn(pid, spec, correlation_id);
+            try!(msg.encode(&mut Encoder::new(&mut encoded))
                 .chain_err(|| ErrorKind::EncodeError(Some(id), Some(node))));
-            try!(self.write(id, Some(encoded)));
+            return self.write(id, Some(encoded));
+        }
+        let reason = format!("Not connected to {}", pid.node);
+        self.spawn_reply(correlation_id, Msg::SpawnFailed(pid, reason))
+    }
+
+    /// Start a local process using a registered factory and reply with the result
+    fn spawn(&mut self,
+             pid: Pid,
+             spec: ProcessSpec<T>,
+             correlation_id: CorrelationId) -> Result<()>
+    {
+        let msg = if pid.node != self.node {
+            Msg::SpawnFailed(pid.clone(), format!("{} is not on node {}", pid, self.node))
+        } else if let Some(factory) = self.factories.get(&spec.factory) {
+            // Factories can be called by remote nodes, so don't let a panic kill the cluster server
+            let arg = spec.arg;
+            match panic::catch_unwind(AssertUnwindSafe(|| factory(pid.clone(), arg))) {
+                Ok(process) => {
+                    let msg = ExecutorMsg::Start(pid.clone(), process);
+                    if let Err(_) = self.executors.send(&pid, msg) {
+                        return Err(ErrorKind::SendError(format!("ExecutorMsg::Start({}, ..)", pid),
+                                                        Some(pid)).into());
+                    }
+                    Msg::Spawned(pid)
+                },
+                Err(payload) => {
+                    let description = panic_description(payload);
+                    error!(self.logger, "Process factory panicked";
+                           "pid" => pid.to_string(),
+                           "factory" => spec.factory,
+                           "panic" => description.clone());
+                    Msg::SpawnFailed(pid, format!("Process factory panicked: {}", description))
+                }
+            }
+        } else {
+            Msg::SpawnFailed(pid, format!("Unknown process factory: {}", spec.factory))
+        };
+        self.spawn_reply(correlation_id, msg)
+    }
+
+    fn spawn_reply(&mut self, correlation_id: CorrelationId, msg: Msg<T>) -> Result<()> {
+        let envelope = Envelope {
+            to: correlation_id.pid.clone(),
+            from: self.pid.clone(),
+            msg: msg,
+            correlation_id: Some(correlation_id)
+        };
+        self.route(envelope)
+    }
+
+    /// Send an envelope to a local or remote pid
+    fn route(&mut self, envelope: Envelope<T>) -> Result<()> {
+        if envelope.to.node == self.node {
+            return self.send_local(envelope);
+        }
+        self.send_remote(envelope, Delivery::AtMostOnce)
+    }
+
+    /// Handle an envelope addressed to the cluster server
+    fn handle_envelope(&mut self, envelope: Envelope<T>) -> Result<()> {
+        let Envelope {from, msg, correlation_id, ..} = envelope;
+        match msg {
+            Msg::GetMetrics => {
+                self.send_metrics(from, correlation_id);
+                Ok(())
+            },
+            Msg::RegisterName(name) => self.register_name(name, from),
+            Msg::UnregisterName(name) => {
+                match self.registry.unregister(name, from) {
+                    Some(delta) => self.broadcast_name_delta(delta),
+                    None => Ok(())
+                }
+            },
+            Msg::WhereIsName(name) => {
+                let pid = self.registry.whereis(&name);
+                let envelope = Envelope::new(from, self.pid.clone(), Msg::NamedPid(name, pid),
+                                             correlation_id);
+                self.route(envelope)
+            },
+            Msg::SendNamed(name, msg) => {
+                match self.registry.whereis(&name) {
+                    Some(pid) => self.route(Envelope::new(pid, from, *msg, correlation_id)),
+                    None => {
+                        warn!(self.logger, "No pid registered for name";
+                              "name" => name, "from" => from.to_string());
+                        Ok(())
+                    }
+                }
+            },
+            Msg::JoinGroup(group) => self.join_group(group, from),
+            Msg::LeaveGroup(group) => {
+                match self.groups.leave(group, from) {
+                    Some(delta) => self.broadcast_group_delta(delta),
+                    None => Ok(())
+                }
+            },
+            Msg::Multicast(group, msg) => {
+                for pid in self.groups.members(&group) {
+                    let envelope = Envelope::new(pid, from.clone(), (*msg).clone(),
+                                                 correlation_id.clone());
+                    try!(self.route(envelope));
+                }
+                Ok(())
+            },
+            Msg::SendAny(group, msg) => {
+                match self.groups.any(&group) {
+                    Some(pid) => self.route(Envelope::new(pid, from, *msg, correlation_id)),
+                    None => {
+                        warn!(self.logger, "Process group is empty";
+                              "group" => group, "from" => from.to_string());
+                        Ok(())
+                    }
+                }
+            },
+            Msg::GetGroupMembers(group) => {
+                let members = self.groups.members(&group);
+                let envelope = Envelope::new(from, self.pid.clone(),
+                                             Msg::GroupMembers(group, members), correlation_id);
+                self.route(envelope)
+            },
+            Msg::SubscribeClusterEvents => {
+                if from.node == self.node {
+                    try!(self.monitor(from.clone()));
+                }
+                self.subscribers.insert(from);
+                Ok(())
+            },
+            Msg::UnsubscribeClusterEvents => {
+                self.subscribers.remove(&from);
+                Ok(())
+            },
+            Msg::Down(pid, _) => {
+                // A process with a registered name, in a group, or subscribed to events exited
+                self.subscribers.remove(&pid);
+                for delta in self.registry.remove_pid(&pid) {
+                    try!(self.broadcast_name_delta(delta));
+                }
+                for delta in self.groups.remove_pid(&pid) {
+                    try!(self.broadcast_group_delta(delta));
+                }
+                Ok(())
+            },
+            msg => {
+                error!(self.logger, "Received Unknown Msg";
+                       "from" => from.to_string(), "msg" => format!("{:?}", msg));
+                Ok(())
+            }
+        }
+    }
+
+    /// Add a local pid to a group and monitor the pid, so that it can be removed from the group
+    /// when the process exits.
+    fn join_group(&mut self, group: String, pid: Pid) -> Result<()> {
+        if pid.node != self.node {
+            warn!(self.logger, "Only local pids can join a group";
+                  "group" => group, "pid" => pid.to_string());
+            return Ok(());
+        }
+        match self.groups.join(group, pid.clone()) {
+            Some(delta) => {
+                try!(self.monitor(pid));
+                self.broadcast_group_delta(delta)
+            },
+            None => Ok(())
+        }
+    }
+
+    /// Send an event to all subscribers
+    ///
+    /// Failures are only logged, since the event has nothing to do with the cause of the failure.
+    fn publish(&mut self, event: ClusterEvent) {
+        info!(self.logger, "Publishing cluster event"; "event" => format!("{:?}", event));
+        let subscribers: Vec<Pid> = self.subscribers.iter().cloned().collect();
+        for pid in subscribers {
+            let msg = Msg::ClusterEvent(event.clone());
+            if let Err(e) = self.route(Envelope::new(pid, self.pid.clone(), msg, None)) {
+                warn!(self.logger, "Failed to publish cluster event"; "error" => e.to_string());
+            }
+        }
+    }
+
+    /// Ask the executor to notify the cluster server when a local process exits
+    fn monitor(&self, pid: Pid) -> Result<()> {
+        let envelope = Envelope::new(executor_pid(self.node.clone()),
+                                     self.pid.clone(),
+                                     Msg::Monitor(pid),
+                                     None);
+        self.send_local(envelope)
+    }
+
+    /// Register a name for a local pid and monitor the pid, so that the name can be removed when
+    /// the process exits.
+    fn register_name(&mut self, name: String, pid: Pid) -> Result<()> {
+        if pid.node != self.node {
+            warn!(self.logger, "Only local pids can be registered";
+                  "name" => name, "pid" => pid.to_string());
+            return Ok(());
+        }
+        match self.registry.register(name.clone(), pid.clone()) {
+            Some(delta) => {
+                try!(self.monitor(pid));
+                self.broadcast_name_delta(delta)
+            },
+            None => {
+                if self.registry.whereis(&name).as_ref() != Some(&pid) {
+                    warn!(self.logger, "Name already registered";
+                          "name" => name, "pid" => pid.to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Send an envelope to a remote node
+    ///
+    /// If the node is a member of the cluster without an established connection, the envelope is
+    /// queued until the connection is established. Envelopes to nodes that aren't members are
+    /// dropped. Envelopes sent with `Delivery::AtLeastOnce` are retained until acknowledged.
+    /// They are retained from the time they are sent, rather than queued, so they don't expire
+    /// while waiting for a connection.
+    fn send_remote(&mut self, envelope: Envelope<T>, delivery: Delivery) -> Result<()> {
+        let established = self.established.get(&envelope.to.node).cloned();
+        if established.is_none() && !self.members.all().contains(&envelope.to.node) {
+            self.drop_envelope(Some(envelope), DeliveryFailure::NodeUnreachable);
+            return Ok(());
+        }
+        if delivery == Delivery::AtLeastOnce {
+            return self.send_reliable(envelope, established);
+        }
+        if let Some(id) = established {
+            trace!(self.logger, "send remote"; "to" => envelope.to.to_string());
+            let node = envelope.to.node.clone();
+            return self.send_with_faults(id, node, ExternalMsg::Envelope(envelope));
+        }
+        trace!(self.logger, "queue remote"; "to" => envelope.to.to_string());
+        match self.outbound.push(envelope, self.clock.now()) {
+            Ok(()) => self.metrics.queued_envelopes += 1,
+            Err(envelope) => self.drop_envelope(Some(envelope), DeliveryFailure::QueueFull)
+        }
+        Ok(())
+    }
+
+    /// Retain an envelope until it's acknowledged, and send it if the connection with the given id
+    /// is established. Otherwise it's sent by `retransmit` once the connection is established.
+    fn send_reliable(&mut self, envelope: Envelope<T>, established: Option<usize>) -> Result<()> {
+        let node = envelope.to.node.clone();
+        let (seq, acked, envelope) = match self.reliable.send(envelope) {
+            Ok(sent) => sent,
+            Err(envelope) => {
+                self.drop_envelope(Some(envelope), DeliveryFailure::QueueFull);
+                return Ok(());
+            }
+        };
+        let id = match established {
+            Some(id) => id,
+            None => {
+                trace!(self.logger, "retain reliable"; "to" => envelope.to.to_string());
+                return Ok(());
+            }
+        };
+        trace!(self.logger, "send remote"; "to" => envelope.to.to_string());
+        let msg = ExternalMsg::Reliable {
+            session: self.reliable.session(),
+            seq: seq,
+            acked: acked,
+            envelope: envelope
+        };
+        self.send_with_faults(id, node, msg)
+    }
+
+    /// Encode a message and write it to the connection with the given id
+    fn send_external(&mut self, id: usize, node: NodeId, msg: ExternalMsg<T>) -> Result<()> {
+        let mut encoded = Vec::new();
+        try!(msg.encode(&mut Encoder::new(&mut encoded))
+            .chain_err(|| ErrorKind::EncodeError(Some(id), Some(node))));
+        self.write(id, Some(encoded))
+    }
+
+    /// Pass a message to a peer through the faults injected into the link to it
+    #[cfg(feature = "fault-injection")]
+    fn send_with_faults(&mut self, id: usize, node: NodeId, msg: ExternalMsg<T>) -> Result<()> {
+        let now = self.clock.now();
+        for msg in self.faults.apply(&node, Direction::Outgoing, msg, now) {
+            try!(self.send_external(id, node.clone(), msg));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn send_with_faults(&mut self, id: usize, node: NodeId, msg: ExternalMsg<T>) -> Result<()> {
+        self.send_external(id, node, msg)
+    }
+
+    /// Pass on messages held back by injected faults once they are due. Messages for peers that
+    /// are no longer connected or are partitioned from this node are lost.
+    #[cfg(feature = "fault-injection")]
+    fn release_held_messages(&mut self) -> Result<()> {
+        let now = self.clock.now();
+        for (node, direction, msg) in self.faults.expire(now) {
+            let id = match self.established.get(&node) {
+                Some(id) if !self.is_partitioned(&node) => *id,
+                _ => continue
+            };
+            try!(match direction {
+                Direction::Outgoing => self.send_external(id, node, msg),
+                Direction::Incoming => self.handle_decoded_message(id, msg)
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn release_held_messages(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Return true if `node` is partitioned from this node by an injected fault
+    #[cfg(feature = "fault-injection")]
+    fn is_partitioned(&self, node: &NodeId) -> bool {
+        self.faults.is_partitioned(node)
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn is_partitioned(&self, _: &NodeId) -> bool {
+        false
+    }
+
+    /// Resend all unacknowledged reliable envelopes to `node` if there is an established
+    /// connection to it
+    fn retransmit(&mut self, node: &NodeId) -> Result<()> {
+        let id = match self.established.get(node) {
+            Some(id) => *id,
+            None => return Ok(())
+        };
+        let session = self.reliable.session();
+        for (seq, acked, envelope) in self.reliable.unacked(node) {
+            self.metrics.retransmitted_envelopes += 1;
+            let msg = ExternalMsg::Reliable {
+                session: session,
+                seq: seq,
+                acked: acked,
+                envelope: envelope
+            };
+            try!(self.send_with_faults(id, node.clone(), msg));
+        }
+        Ok(())
+    }
+
+    /// Acknowledge reliable envelopes received since the last acknowledgement. Acknowledgements
+    /// that can't be sent are resent when the peer retransmits.
+    fn send_acks(&mut self) -> Result<()> {
+        for (node, session, seq) in self.reliable.pending_acks() {
+            if let Some(id) = self.established.get(&node).cloned() {
+                let ack = ExternalMsg::Ack {session: session, seq: seq};
+                try!(self.send_with_faults(id, node, ack));
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop all envelopes awaiting acknowledgement from or queued for `node`, because it left the
+    /// cluster
+    fn drop_outbound(&mut self, node: &NodeId) {
+        for envelope in self.reliable.remove_node(node) {
+            self.drop_envelope(Some(envelope), DeliveryFailure::NodeUnreachable);
+        }
+        for envelope in self.outbound.take(node) {
+            self.drop_envelope(Some(envelope), DeliveryFailure::NodeUnreachable);
+        }
+    }
+
+    /// Send all envelopes queued for `node` if there is an established connection to it
+    fn flush_outbound(&mut self, node: &NodeId) -> Result<()> {
+        if !self.established.contains_key(node) {
+            return Ok(());
+        }
+        for envelope in self.outbound.take(node) {
+            try!(self.send_remote(envelope, Delivery::AtMostOnce));
         }
         Ok(())
     }
 
+    fn expire_outbound(&mut self) {
+        for envelope in self.outbound.expire(self.clock.now()) {
+            self.drop_envelope(Some(envelope), DeliveryFailure::Expired);
+        }
+    }
+
+    /// Count an envelope that couldn't be sent to or received from a remote node, and report it
+    /// to the dead letter pid and the sender if configured to do so.
+    ///
+    /// `envelope` is `None` if a received message could not be decoded.
+    fn drop_envelope(&mut self, envelope: Option<Envelope<T>>, failure: DeliveryFailure) {
+        match failure {
+            DeliveryFailure::QueueFull => self.metrics.dropped_queue_full += 1,
+            DeliveryFailure::Expired => self.metrics.dropped_expired += 1,
+            DeliveryFailure::NodeUnreachable => self.metrics.dropped_node_unreachable += 1,
+            DeliveryFailure::DecodeFailure(_) => self.metrics.decode_failures += 1,
+            DeliveryFailure::NoSuchPid => ()
+        }
+        debug!(self.logger, "Dropped envelope";
+               "to" => envelope.as_ref().map_or("unknown".to_string(), |e| e.to.to_string()),
+               "reason" => format!("{:?}", failure));
+        for report in delivery_failure::reports(envelope, failure, &self.pid, &self.config) {
+            if let Err(e) = self.route(report) {
+                warn!(self.logger, "Failed to report undeliverable envelope";
+                      "error" => e.to_string());
+            }
+        }
+    }
+
     fn handle_poll_notifications(&mut self, notifications: Vec<Notification>) -> Result<()> {
         trace!(self.logger, "handle_poll_notification"; "num_notifications" => notifications.len());
         let mut errors = Vec::new();
@@ -216,6 +888,9 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
                 errors.push(e);
             }
         }
+        if let Err(e) = self.connect_ready() {
+            errors.push(e);
+        }
         if errors.len() != 0 {
             return Err(ErrorKind::PollNotificationErrors(errors).into());
         }
@@ -233,65 +908,298 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
         }
     }
 
-    /// Returns `Some(true)` if there is such a connection and the members were already sent.
-    /// Returns `Some(false)` if there is such a connection and the members were NOT sent.
-    /// Returns None if there is no such connection.
-    fn members_sent(&self, id: usize) -> Option<bool> {
-        if let Some(conn) = self.connections.get(&id) {
-            return Some(conn.members_sent);
+    fn read(&mut self, id: usize) -> Result<()> {
+        trace!(self.logger, "read"; "id" => id);
+        if !self.connections.contains_key(&id) {
+            return Ok(());
         }
-        None
+        // The peer may have accepted our connection and started the handshake
+        try!(self.start_handshake(id));
+        let (hello_received, messages) = try!(self.decode_messages(id));
+        if hello_received {
+            try!(self.handle_hello(id));
+        }
+        for msg in messages {
+            try!(self.receive_with_faults(id, msg));
+        }
+        try!(self.send_acks());
+        self.flush_tls(id)
     }
 
-    fn read(&mut self, id: usize) -> Result<()> {
-        trace!(self.logger, "read"; "id" => id);
-        match self.members_sent(id) {
-            Some(false) => try!(self.send_members(id)),
-            None => (),
-            Some(true) => {
-                let messages = try!(self.decode_messages(id));
-                for msg in messages {
-                    try!(self.handle_decoded_message(id, msg));
-                }
+    /// Send any data buffered by the TLS session of a connection, such as handshake messages
+    /// produced while reading.
+    fn flush_tls(&mut self, id: usize) -> Result<()> {
+        let registrar = &self.registrar;
+        if let Some(conn) = self.connections.get_mut(&id) {
+            let flushed = try!(conn.sock.flush_tls()
+                               .chain_err(|| ErrorKind::WriteError(id, conn.node.clone())));
+            if !flushed {
+                try!(conn.sock.reregister(id, registrar, Event::Both)
+                     .chain_err(|| ErrorKind::RegistrarError(Some(id), conn.node.clone())));
             }
         }
         Ok(())
     }
 
+    /// Check that the certificate of a TLS peer is valid for the node it announced itself as
+    fn verify_identity(&self, id: usize, node: &NodeId) -> Result<()> {
+        if let Some(ref tls) = self.tls {
+            let verified = self.connections.get(&id)
+                .and_then(|conn| conn.sock.tls())
+                .map_or(false, |session| tls.verify_identity(session, node));
+            if !verified {
+                return Err(ErrorKind::TlsIdentityError(id, node.clone()).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Pass a message received on a connection through the faults injected into its link
+    #[cfg(feature = "fault-injection")]
+    fn receive_with_faults(&mut self, id: usize, msg: ExternalMsg<T>) -> Result<()> {
+        let (partitioned, node) = self.connections.get(&id).map_or((false, None), |conn| {
+            (conn.is_partitioned(), conn.node.clone())
+        });
+        if partitioned {
+            return Ok(());
+        }
+        match node {
+            Some(node) => {
+                let now = self.clock.now();
+                for msg in self.faults.apply(&node, Direction::Incoming, msg, now) {
+                    try!(self.handle_decoded_message(id, msg));
+                }
+                Ok(())
+            },
+            None => self.handle_decoded_message(id, msg)
+        }
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn receive_with_faults(&mut self, id: usize, msg: ExternalMsg<T>) -> Result<()> {
+        self.handle_decoded_message(id, msg)
+    }
+
     fn handle_decoded_message(&mut self, id: usize, msg: ExternalMsg<T>) -> Result<()> {
+        if !self.is_authenticated(id) {
+            return self.handle_auth_message(id, msg);
+        }
+        // Until a verified `Members` establishes the connection, the peer's identity is unknown
+        match msg {
+            ExternalMsg::Members{..} | ExternalMsg::Ping => (),
+            _ => if !self.is_established(id) {
+                let node = self.connections.get(&id).and_then(|conn| conn.node.clone());
+                return Err(ErrorKind::UnestablishedError(id, node).into());
+            }
+        }
         match msg {
             ExternalMsg::Members{from, orset} => {
                 info!(self.logger, "Got Members"; "id" => id, "from" => from.to_string());
-                self.establish_connection(id, from, orset);
+                if self.is_partitioned(&from) {
+                    // The peer connected before it could be identified
+                    info!(self.logger, "Closing connection from partitioned peer";
+                          "id" => id, "peer" => from.to_string());
+                    self.close(id);
+                    return Ok(());
+                }
+                try!(self.verify_identity(id, &from));
+                // Publish any new members before the connection to them is established
+                let before = self.members.all();
+                self.members.join(orset);
+                try!(self.members_changed(before));
+                self.establish_connection(id, from.clone());
                 self.check_connections();
+                try!(self.retransmit(&from));
+                try!(self.flush_outbound(&from));
             },
             ExternalMsg::Ping => {
                 trace!(self.logger, "Got Ping"; "id" => id);
-                self.reset_timer(id);
+                self.heartbeat(id);
             }
             ExternalMsg::Envelope(envelope) => {
-                self.metrics.received_remote_envelopes += 1;
-                debug!(self.logger, "Got User Message";
-                       "from" => envelope.from.to_string(),
-                       "to" => envelope.to.to_string());
-                if let Err(mpsc::SendError(ExecutorMsg::Envelope(envelope)))
-                    = self.executor_tx.send(ExecutorMsg::Envelope(envelope))
-                {
-                    return Err(ErrorKind::SendError("ExecutorMsg::Enelope".to_string(),
-                                                    Some(envelope.to)).into());
+                try!(self.receive_remote(envelope));
+            },
+            ExternalMsg::Reliable {session, seq, acked, envelope} => {
+                let is_new = match self.connections.get(&id).and_then(|conn| conn.node.clone()) {
+                    Some(node) => self.reliable.receive(&node, session, seq, acked),
+                    None => {
+                        warn!(self.logger, "Got reliable envelope on unestablished connection";
+                              "id" => id);
+                        false
+                    }
+                };
+                if is_new {
+                    try!(self.receive_remote(envelope));
+                } else {
+                    trace!(self.logger, "Dropping duplicate envelope"; "id" => id, "seq" => seq);
+                    self.metrics.duplicate_envelopes += 1;
+                }
+            },
+            ExternalMsg::Ack {session, seq} => {
+                trace!(self.logger, "Got Ack"; "id" => id, "seq" => seq);
+                if let Some(node) = self.connections.get(&id).and_then(|conn| conn.node.clone()) {
+                    self.reliable.ack(&node, session, seq);
                 }
             },
             ExternalMsg::Delta(delta) => {
                 debug!(self.logger, "Got Delta mutator";
                        "id" => id, "delta" => format!("{:?}", delta));
+                let before = self.members.all();
                 if self.members.join_delta(delta.clone()) {
+                    try!(self.members_changed(before));
                     try!(self.broadcast_delta(delta));
                 }
+            },
+            ExternalMsg::Names(orset) => {
+                debug!(self.logger, "Got Names"; "id" => id);
+                self.registry.join(orset);
+            },
+            ExternalMsg::NameDelta(delta) => {
+                debug!(self.logger, "Got Name Delta mutator";
+                       "id" => id, "delta" => format!("{:?}", delta));
+                if self.registry.join_delta(delta.clone()) {
+                    try!(self.broadcast_name_delta(delta));
+                }
+            },
+            ExternalMsg::Groups(orset) => {
+                debug!(self.logger, "Got Groups"; "id" => id);
+                self.groups.join_state(orset);
+            },
+            ExternalMsg::GroupDelta(delta) => {
+                debug!(self.logger, "Got Group Delta mutator";
+                       "id" => id, "delta" => format!("{:?}", delta));
+                if self.groups.join_delta(delta.clone()) {
+                    try!(self.broadcast_group_delta(delta));
+                }
+            },
+            ExternalMsg::ShuttingDown => {
+                info!(self.logger, "Peer is shutting down"; "id" => id);
+                let node = self.connections.get(&id).and_then(|conn| conn.node.clone());
+                self.close(id);
+                // Remove the peer from the cluster, so that it isn't reconnected to or reported
+                // down by the failure detector. It must join again after restarting.
+                if let Some(node) = node {
+                    try!(self.leave(node));
+                }
+            },
+            ExternalMsg::Spawn(pid, spec, correlation_id) => {
+                debug!(self.logger, "Got Spawn request";
+                       "pid" => pid.to_string(), "factory" => spec.factory.clone());
+                self.metrics.spawn_requests += 1;
+                try!(self.spawn(pid, spec, correlation_id));
+            },
+            ExternalMsg::Challenge(_) | ExternalMsg::ChallengeResponse(_) => {
+                warn!(self.logger, "Got cookie challenge on authenticated connection"; "id" => id);
             }
         }
         Ok(())
     }
 
+    fn is_authenticated(&self, id: usize) -> bool {
+        self.connections.get(&id).map_or(false, |conn| conn.authenticated)
+    }
+
+    fn is_established(&self, id: usize) -> bool {
+        self.connections.get(&id)
+            .and_then(|conn| conn.node.as_ref())
+            .map_or(false, |node| self.established.get(node) == Some(&id))
+    }
+
+    /// Handle a message from a peer that hasn't proven it knows the cookie yet. Any message other
+    /// than a challenge or a correct response closes the connection.
+    fn handle_auth_message(&mut self, id: usize, msg: ExternalMsg<T>) -> Result<()> {
+        let result = match (msg, self.cookie.as_ref(), self.connections.get_mut(&id)) {
+            (ExternalMsg::Challenge(challenge), Some(cookie), Some(conn)) => {
+                trace!(self.logger, "Got cookie challenge"; "id" => id);
+                let response = ExternalMsg::ChallengeResponse::<T>(
+                    cookie.response(conn.is_client, &challenge));
+                let mut encoded = Vec::new();
+                try!(response.encode(&mut Encoder::new(&mut encoded))
+                     .chain_err(|| ErrorKind::EncodeError(Some(id), conn.node.clone())));
+                return conn_write(id, conn, Some(encoded), &self.registrar);
+            },
+            (ExternalMsg::ChallengeResponse(response), Some(cookie), Some(conn)) => {
+                // The response comes from the other side of the connection
+                conn.authenticated = conn.challenge.as_ref().map_or(false, |challenge| {
+                    cookie.verify(!conn.is_client, challenge, &response)
+                });
+                if conn.authenticated {
+                    Ok(())
+                } else {
+                    Err(ErrorKind::AuthError(id, conn.node.clone()))
+                }
+            },
+            (_, _, conn) => Err(ErrorKind::AuthError(id, conn.and_then(|c| c.node.clone())))
+        };
+        match result {
+            Ok(()) => {
+                info!(self.logger, "Peer authenticated"; "id" => id);
+                self.send_members(id)
+            },
+            Err(kind) => {
+                self.metrics.auth_failures += 1;
+                Err(kind.into())
+            }
+        }
+    }
+
+    /// Start the handshake on a new connection by sending a `Hello`, unless it was already sent
+    fn start_handshake(&mut self, id: usize) -> Result<()> {
+        if let Some(conn) = self.connections.get_mut(&id) {
+            if conn.hello_sent {
+                return Ok(());
+            }
+            let mut encoded = Vec::new();
+            try!(Hello::new(self.config.msg_version).encode(&mut Encoder::new(&mut encoded))
+                 .chain_err(|| ErrorKind::EncodeError(Some(id), conn.node.clone())));
+            conn.hello_sent = true;
+            try!(conn_write(id, conn, Some(encoded), &self.registrar));
+        }
+        Ok(())
+    }
+
+    /// Continue the handshake once the peer sent a compatible `Hello`
+    fn handle_hello(&mut self, id: usize) -> Result<()> {
+        debug!(self.logger, "Got compatible Hello"; "id" => id);
+        if let Some(conn) = self.connections.get_mut(&id) {
+            conn.hello_received = true;
+        }
+        self.send_challenge(id)
+    }
+
+    /// Send a cookie challenge if a cookie is configured, or the membership state otherwise. The
+    /// members are sent once the peer is authenticated.
+    fn send_challenge(&mut self, id: usize) -> Result<()> {
+        let challenge = match self.cookie {
+            Some(ref cookie) => try!(cookie.challenge()),
+            None => return self.send_members(id)
+        };
+        if let Some(conn) = self.connections.get_mut(&id) {
+            let mut encoded = Vec::new();
+            try!(ExternalMsg::Challenge::<T>(challenge.clone())
+                 .encode(&mut Encoder::new(&mut encoded))
+                 .chain_err(|| ErrorKind::EncodeError(Some(id), conn.node.clone())));
+            conn.challenge = Some(challenge);
+            try!(conn_write(id, conn, Some(encoded), &self.registrar));
+        }
+        Ok(())
+    }
+
+    /// Deliver an envelope received from another node to the executor
+    fn receive_remote(&mut self, envelope: Envelope<T>) -> Result<()> {
+        self.metrics.received_remote_envelopes += 1;
+        debug!(self.logger, "Got User Message";
+               "from" => envelope.from.to_string(),
+               "to" => envelope.to.to_string());
+        if let Err(mpsc::SendError(ExecutorMsg::Envelope(envelope)))
+            = self.executors.send_envelope(envelope)
+        {
+            return Err(ErrorKind::SendError("ExecutorMsg::Enelope".to_string(),
+                                            Some(envelope.to)).into());
+        }
+        Ok(())
+    }
+
     fn write(&mut self, id: usize, msg: Option<Vec<u8>>) -> Result<()> {
         trace!(self.logger, "write"; "id" => id);
         let registrar = &self.registrar;
@@ -301,7 +1209,7 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
                     // The socket has just became writable. We need to re-register it as only
                     // readable, or it the event will keep firing indefinitely even if there is
                     // no data to write.
-                    try!(registrar.reregister(id, &conn.sock, Event::Read)
+                    try!(conn.sock.reregister(id, registrar, Event::Read)
                          .chain_err(|| ErrorKind::RegistrarError(Some(id), conn.node.clone())));
                 }
 
@@ -313,34 +1221,43 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
         Ok(())
     }
 
-    fn reset_timer(&mut self, id: usize) {
+    fn heartbeat(&mut self, id: usize) {
         if let Some(conn) = self.connections.get_mut(&id) {
-            self.timer_wheel.remove(&id, conn.timer_wheel_index);
-            conn.timer_wheel_index = self.timer_wheel.insert(id)
+            conn.detector.heartbeat(&self.config.failure_detector, self.clock.now());
         }
     }
 
     /// Transition a connection from unestablished to established. If there is already an
     /// established connection between these two nodes, determine which one should be closed.
-    fn establish_connection(&mut self, id: usize, from: NodeId, orset: ORSet<NodeId>) {
-        self.members.join(orset);
+    fn establish_connection(&mut self, id: usize, from: NodeId) {
+        let mut replaced = false;
         if let Some(close_id) = self.choose_connection_to_close(id, &from) {
             debug!(self.logger,
                    "Two connections between nodes. Closing the connection where \
                     the peer that sorts lower was the connecting client";
                     "peer" => from.to_string(), "id" => close_id);
-            self.close(close_id);
+            self.close_connection(close_id);
             if close_id == id {
                 return;
             }
+            replaced = true;
         }
         debug!(self.logger, "Trying to establish connection"; "peer" => from.to_string(), "id" => id);
         if let Some(conn) = self.connections.get_mut(&id) {
             info!(self.logger, "Establish connection"; "peer" => from.to_string(), "id" => id);
+            if conn.is_client {
+                if let Some(ref node) = conn.node {
+                    self.transport.established(node);
+                }
+            }
             conn.node = Some(from.clone());
-            self.timer_wheel.remove(&id, conn.timer_wheel_index);
-            conn.timer_wheel_index = self.timer_wheel.insert(id);
-            self.established.insert(from, id);
+            self.established.insert(from.clone(), id);
+        } else {
+            return;
+        }
+        // Replacing a duplicate connection doesn't change connectivity
+        if !replaced {
+            self.publish(ClusterEvent::ConnectionEstablished(from));
         }
     }
 
@@ -363,80 +1280,192 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
         None
     }
 
-    fn decode_messages(&mut self, id: usize) -> Result<Vec<ExternalMsg<T>>> {
+    /// Read and decode all available messages from a connection.
+    ///
+    /// The first frame received on a connection is the peer's `Hello`. Returns true along with the
+    /// messages if it was received and is compatible, and an error if it's incompatible.
+    ///
+    /// Messages that fail to decode are skipped and reported as dead letters. Each message is in a
+    /// separate frame, so the rest of the stream is unaffected.
+    fn decode_messages(&mut self, id: usize) -> Result<(bool, Vec<ExternalMsg<T>>)> {
+        let mut hello_received = false;
         let mut output = Vec::new();
+        let mut failures = Vec::new();
+        let msg_version = self.config.msg_version;
         if let Some(conn) = self.connections.get_mut(&id) {
             let node = conn.node.clone();
             try!(conn.reader.read(&mut conn.sock)
                  .chain_err(|| ErrorKind::ReadError(id, node.clone())));
 
+            let mut expect_hello = !conn.hello_received;
             for frame in conn.reader.iter_mut() {
                 let mut decoder = Decoder::new(&frame[..]);
-                let msg = try!(Decodable::decode(&mut decoder)
-                               .chain_err(|| ErrorKind::DecodeError(id, node.clone())));
-                output.push(msg);
+                if expect_hello {
+                    expect_hello = false;
+                    let result: ::std::result::Result<Hello, msgpack::decode::Error>
+                        = Decodable::decode(&mut decoder);
+                    let incompatibility = match result {
+                        Ok(hello) => hello.incompatibility(msg_version),
+                        Err(e) => Some(format!("failed to decode Hello: {}", e))
+                    };
+                    if let Some(reason) = incompatibility {
+                        self.metrics.incompatible_peers += 1;
+                        return Err(ErrorKind::IncompatiblePeer(id, node, reason).into());
+                    }
+                    hello_received = true;
+                    continue;
+                }
+                match Decodable::decode(&mut decoder) {
+                    Ok(msg) => output.push(msg),
+                    Err(e) => {
+                        let e: msgpack::decode::Error = e;
+                        failures.push((node.clone(), e.to_string()))
+                    }
+                }
             }
         }
-        Ok(output)
+        for (node, error) in failures {
+            warn!(self.logger, "Failed to decode message";
+                  "id" => id, "peer" => format!("{:?}", node), "error" => error);
+            self.drop_envelope(None, DeliveryFailure::DecodeFailure(node));
+        }
+        Ok((hello_received, output))
     }
 
     fn join(&mut self, node: NodeId) -> Result<()> {
+        let before = self.members.all();
         let delta = self.members.add(node.clone());
+        try!(self.members_changed(before));
         try!(self.broadcast_delta(delta));
         self.metrics.connection_attempts += 1;
         self.connect(node)
     }
 
     fn leave(&mut self, node: NodeId) -> Result<()> {
+        let before = self.members.all();
         if let Some(delta) = self.members.leave(node.clone()) {
+            try!(self.members_changed(before));
             try!(self.broadcast_delta(delta));
         }
         Ok(())
     }
 
+    /// Publish events for nodes that were added to or removed from the cluster membership.
+    ///
+    /// Tell all executor workers about any nodes that are no longer members of the cluster, so
+    /// that they can notify processes monitoring or linked to processes on those nodes. Also remove
+    /// any names, group members and subscribers on those nodes, and report envelopes still queued
+    /// for them as undeliverable.
+    fn members_changed(&mut self, before: BTreeSet<NodeId>) -> Result<()> {
+        let after = self.members.all();
+        for node in after.difference(&before) {
+            self.publish(ClusterEvent::NodeJoined(node.clone()));
+        }
+        for node in before.difference(&after) {
+            self.subscribers.retain(|pid| pid.node != *node);
+            self.publish(ClusterEvent::NodeLeft(node.clone()));
+            info!(self.logger, "Node left the cluster"; "peer" => node.to_string());
+            self.drop_outbound(node);
+            self.transport.node_left(node);
+            if let Err(_) = self.executors.broadcast(|| ExecutorMsg::NodeDown(node.clone())) {
+                return Err(ErrorKind::SendError("ExecutorMsg::NodeDown".to_string(), None).into());
+            }
+            for delta in self.registry.remove_node(node) {
+                try!(self.broadcast_name_delta(delta));
+            }
+            for delta in self.groups.remove_node(node) {
+                try!(self.broadcast_group_delta(delta));
+            }
+        }
+        Ok(())
+    }
+
     fn connect(&mut self, node: NodeId) -> Result<()> {
+        if self.is_partitioned(&node) {
+            trace!(self.logger, "Not connecting to partitioned peer"; "peer" => node.to_string());
+            return Ok(());
+        }
         debug!(self.logger, "connect"; "to" => node.to_string());
-        let sock = try!(TcpBuilder::new_v4().chain_err(|| "Failed to create a IPv4 socket"));
-        let sock = try!(sock.to_tcp_stream().chain_err(|| "Failed to create TcpStream"));
-        try!(sock.set_nonblocking(true).chain_err(|| "Failed to make socket nonblocking"));
-        if let Err(e) = sock.connect(&node.addr[..]) {
-            if e.raw_os_error().is_some() && *e.raw_os_error().as_ref().unwrap() != EINPROGRESS {
-                return Err(e).chain_err(|| ErrorKind::ConnectError(node));
+        let sock = match self.transport.connect(&node, &self.registrar) {
+            Ok(sock) => sock,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                // The transport returns the node from `ready` once it can be connected to
+                debug!(self.logger, "Waiting for the transport to connect";
+                       "to" => node.to_string(), "reason" => e.to_string());
+                return Ok(());
+            },
+            Err(e) => return Err(e).chain_err(|| ErrorKind::ConnectError(node))
+        };
+        try!(self.init_connection(sock, Some(node)));
+        Ok(())
+    }
+
+    /// Connect to the members of the cluster that the transport was waiting for, unless a
+    /// connection to them was made in the meantime
+    fn connect_ready(&mut self) -> Result<()> {
+        let all = self.members.all();
+        for node in self.transport.ready() {
+            let connected = self.connections.values().any(|conn| conn.node.as_ref() == Some(&node));
+            if !all.contains(&node) || connected {
+                continue;
             }
+            self.metrics.connection_attempts += 1;
+            try!(self.connect(node));
         }
-        try!(self.init_connection(sock, Some(node)));
         Ok(())
     }
 
     fn accept_connection(&mut self) -> Result<()> {
-        while let Ok((sock, _)) = self.listener.accept() {
+        let socks = try!(self.transport.accept().chain_err(|| "Failed to accept connections"));
+        for sock in socks {
             self.metrics.accepted_connections += 1;
             debug!(self.logger, "accepted connection");
-            try!(sock.set_nonblocking(true).chain_err(|| "Failed to make socket nonblocking"));
             let id = try!(self.init_connection(sock, None));
-            try!(self.send_members(id));
+            try!(self.start_handshake(id));
         }
         Ok(())
     }
 
-    fn init_connection(&mut self, sock: TcpStream, node: Option<NodeId>) -> Result<usize> {
-        let id = try!(self.registrar.register(&sock, Event::Read)
+    fn init_connection(&mut self, sock: Box<Stream>, node: Option<NodeId>) -> Result<usize> {
+        let is_client = node.is_some();
+        let sock = match self.tls {
+            None => Socket::Plain(sock),
+            Some(ref tls) => {
+                let session = match node {
+                    Some(ref node) => try!(tls.client(node)),
+                    None => try!(tls.server())
+                };
+                Socket::Tls(sock, session)
+            }
+        };
+        // A TLS client starts the handshake, so it must write as soon as it's connected
+        let event = if is_client && sock.tls().is_some() { Event::Both } else { Event::Read };
+        let id = try!(sock.register(&self.registrar, event)
                       .chain_err(|| ErrorKind::RegistrarError(None, None)));
         debug!(self.logger, "init_connection()";
-               "id" => id, "is_client" => node.is_some(), "peer" => format!("{:?}", node));
-        let is_client = node.is_some();
-        let mut conn = Conn::new(sock, node, is_client);
-        conn.timer_wheel_index = self.timer_wheel.insert(id);
+               "id" => id, "is_client" => is_client, "peer" => format!("{:?}", node));
+        let conn = Conn::new(sock,
+                             node,
+                             is_client,
+                             self.cookie.is_none(),
+                             self.config.max_frame_size,
+                             self.clock.now());
         self.connections.insert(id, conn);
         Ok(id)
     }
 
+    /// Send the membership state to a new connection, followed by the name registry and the
+    /// process groups
     fn send_members(&mut self, id: usize) -> Result<()> {
         let encoded = try!(self.encode_members(id));
+        let names = try!(self.encode_names(id));
+        let groups = try!(self.encode_groups(id));
         let registrar = &self.registrar;
         if let Some(mut conn) = self.connections.get_mut(&id) {
             info!(self.logger, "Send members"; "id" => id);
             try!(conn_write(id, &mut conn, Some(encoded), &registrar));
+            try!(conn_write(id, &mut conn, Some(names), &registrar));
+            try!(conn_write(id, &mut conn, Some(groups), &registrar));
             conn.members_sent = true;
         }
         Ok(())
@@ -445,8 +1474,9 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
     fn tick(&mut self) -> Result<()> {
         trace!(self.logger, "tick");
         self.timer.arm();
-        let expired = self.timer_wheel.expire();
-        self.deregister(expired);
+        self.expire_outbound();
+        let suspected = self.suspected();
+        self.deregister(suspected);
         try!(self.broadcast_pings());
         self.check_connections();
         Ok(())
@@ -456,8 +1486,8 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
         trace!(self.logger, "tick_executor");
         self.executor_timer.arm();
         // Panic if the executor is down.
-        self.executor_tx.send(ExecutorMsg::Tick).unwrap() ;
-        Ok(())
+        self.executors.broadcast(|| ExecutorMsg::Tick).unwrap();
+        self.release_held_messages()
     }
 
     fn encode_members(&self, id: usize) -> Result<Vec<u8>> {
@@ -469,25 +1499,66 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
         Ok(encoded)
     }
 
-    fn deregister(&mut self, expired: HashSet<usize>) {
-        for id in expired.iter() {
-            warn!(self.logger, "Connection timeout"; "id" => *id);
-            self.close(*id);
+    fn encode_names(&self, id: usize) -> Result<Vec<u8>> {
+        let mut encoded = Vec::new();
+        let msg = ExternalMsg::Names::<T>(self.registry.get_orset());
+        try!(msg.encode(&mut Encoder::new(&mut encoded))
+             .chain_err(|| ErrorKind::EncodeError(Some(id), None)));
+        Ok(encoded)
+    }
+
+    fn encode_groups(&self, id: usize) -> Result<Vec<u8>> {
+        let mut encoded = Vec::new();
+        let msg = ExternalMsg::Groups::<T>(self.groups.get_orset());
+        try!(msg.encode(&mut Encoder::new(&mut encoded))
+             .chain_err(|| ErrorKind::EncodeError(Some(id), None)));
+        Ok(encoded)
+    }
+
+    /// Return the ids and suspicion levels of all connections whose peer is suspected to have
+    /// failed.
+    ///
+    /// Connections that have not completed the handshake yet are treated as if the connection was
+    /// established at creation time, so a peer that never responds is eventually suspected as well.
+    fn suspected(&self) -> Vec<(usize, f64)> {
+        let now = self.clock.now();
+        self.connections.iter().filter_map(|(id, conn)| {
+            let phi = conn.detector.phi(&self.config.failure_detector, now);
+            if phi > self.config.failure_detector.threshold {
+                Some((*id, phi))
+            } else {
+                None
+            }
+        }).collect()
+    }
+
+    fn deregister(&mut self, suspected: Vec<(usize, f64)>) {
+        for (id, phi) in suspected {
+            warn!(self.logger, "Connection timeout"; "id" => id, "phi" => phi);
+            self.close(id);
         }
     }
 
     /// Close an existing connection and remove all related state.
     fn close(&mut self, id: usize) {
+        if let Some(node) = self.close_connection(id) {
+            self.publish(ClusterEvent::ConnectionLost(node));
+        }
+    }
+
+    /// Close an existing connection without publishing an event.
+    ///
+    /// Return the node of the connection if it was established.
+    fn close_connection(&mut self, id: usize) -> Option<NodeId> {
         if let Some(conn) = self.connections.remove(&id) {
-            let _ = self.registrar.deregister(conn.sock);
-            self.timer_wheel.remove(&id, conn.timer_wheel_index);
+            let _ = conn.sock.deregister(&self.registrar);
             if let Some(node) = conn.node {
                 // Remove established connection if it matches this id
                 if let Some(established_id) = self.established.remove(&node) {
                     if established_id == id {
                         info!(self.logger, "Closing established connection";
                               "id" => id,"peer" => node.to_string());
-                        return;
+                        return Some(node);
                     }
                     // The established node didn't correspond to this id, so put it back
                     self.established.insert(node, established_id);
@@ -495,6 +1566,7 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
             }
             info!(self.logger, "Closing unestablished connection"; "id" => id);
         }
+        None
     }
 
     fn broadcast_delta(&mut self, delta: Delta<NodeId>) -> Result<()> {
@@ -506,6 +1578,32 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
         self.broadcast(encoded)
     }
 
+    fn broadcast_name_delta(&mut self, delta: Delta<registry::Entry>) -> Result<()> {
+        debug!(self.logger, "Broadcasting name delta"; "delta" => format!("{:?}", delta));
+        let mut encoded = Vec::new();
+        let msg = ExternalMsg::NameDelta::<T>(delta);
+        try!(msg.encode(&mut Encoder::new(&mut encoded))
+             .chain_err(|| ErrorKind::EncodeError(None, None)));
+        self.broadcast(encoded)
+    }
+
+    fn broadcast_group_delta(&mut self, delta: Delta<groups::Member>) -> Result<()> {
+        debug!(self.logger, "Broadcasting group delta"; "delta" => format!("{:?}", delta));
+        let mut encoded = Vec::new();
+        let msg = ExternalMsg::GroupDelta::<T>(delta);
+        try!(msg.encode(&mut Encoder::new(&mut encoded))
+             .chain_err(|| ErrorKind::EncodeError(None, None)));
+        self.broadcast(encoded)
+    }
+
+    fn broadcast_shutting_down(&mut self) -> Result<()> {
+        let mut encoded = Vec::new();
+        let msg = ExternalMsg::ShuttingDown::<T>;
+        try!(msg.encode(&mut Encoder::new(&mut encoded))
+             .chain_err(|| ErrorKind::EncodeError(None, None)));
+        self.broadcast(encoded)
+    }
+
     fn broadcast_pings(&mut self) -> Result<()> {
         let mut encoded = Vec::new();
         let msg = ExternalMsg::Ping::<T>;
@@ -543,7 +1641,7 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
         }
 
         // Pending, Client connected, or established server side connections
-        let known_peer_conns: HashSet<NodeId> =
+        let known_peer_conns: BTreeSet<NodeId> =
             self.connections.iter().filter_map(|(_, conn)| conn.node.clone()).collect();
 
         let to_connect: Vec<NodeId> = all.difference(&known_peer_conns)
@@ -566,10 +1664,12 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
     }
 
     fn disconnect_all(&mut self) {
-        self.established = HashMap::new();
-        for (id, conn) in self.connections.drain() {
-            self.timer_wheel.remove(&id, conn.timer_wheel_index);
-            if let Err(e) = self.registrar.deregister(conn.sock) {
+        let established = mem::replace(&mut self.established, BTreeMap::new());
+        for (node, _) in established {
+            self.publish(ClusterEvent::ConnectionLost(node));
+        }
+        for (id, conn) in mem::replace(&mut self.connections, BTreeMap::new()) {
+            if let Err(e) = conn.sock.deregister(&self.registrar) {
                 error!(self.logger, "Failed to deregister socket";
                        "id" => id, "peer" => format!("{:?}", conn.node),
                        "error" => e.to_string());
@@ -581,48 +1681,59 @@ impl<T: Encodable + Decodable + Debug + Clone> ClusterServer<T> {
         for node in to_disconnect {
             if let Some(id) = self.established.remove(&node) {
                 let conn = self.connections.remove(&id).unwrap();
-                self.timer_wheel.remove(&id, conn.timer_wheel_index);
-                if let Err(e) = self.registrar.deregister(conn.sock) {
+                if let Err(e) = conn.sock.deregister(&self.registrar) {
                     error!(self.logger, "Failed to deregister socket";
-                           "id" => id, "peer" => conn.node.unwrap().to_string(),
+                           "id" => id, "peer" => node.to_string(),
                            "error" => e.to_string());
                 }
+                self.publish(ClusterEvent::ConnectionLost(node));
             }
         }
     }
 
-    fn send_metrics(&mut self, envelope: Envelope<T>) {
-        if let Msg::GetMetrics = envelope.msg {
-            let new_envelope = Envelope {
-                to: envelope.from,
-                from: self.pid.clone(),
-                msg: Msg::Metrics(self.metrics.data()),
-                correlation_id: envelope.correlation_id
-            };
-            // Route the response through the executor since it knows how to contact all Pids
-            if let Err(mpsc::SendError(ExecutorMsg::Envelope(new_envelope))) =
-                self.executor_tx.send(ExecutorMsg::Envelope(new_envelope))
-            {
-                error!(self.logger, "Failed to send to executor";
-                    "envelope" => format!("{:?}", new_envelope));
-            }
-        } else {
-            error!(self.logger, "Received Unknown Msg";
-                   "envelope" => format!("{:?}", envelope));
+    fn send_metrics(&mut self, to: Pid, correlation_id: Option<CorrelationId>) {
+        let new_envelope = Envelope {
+            to: to,
+            from: self.pid.clone(),
+            msg: Msg::Metrics(self.metrics.data()),
+            correlation_id: correlation_id
+        };
+        // Route the response through the executor since it knows how to contact all Pids
+        if let Err(mpsc::SendError(ExecutorMsg::Envelope(new_envelope))) =
+            self.executors.send_envelope(new_envelope)
+        {
+            error!(self.logger, "Failed to send to executor";
+                "envelope" => format!("{:?}", new_envelope));
         }
     }
 }
 
+/// Return the pid of the cluster server on `node`
+pub fn cluster_server_pid(node: NodeId) -> Pid {
+    Pid {
+        group: Some("rabble".to_string()),
+        name: "cluster_server".to_string(),
+        node: node
+    }
+}
+
 fn conn_write(id: usize,
               conn: &mut Conn,
               msg: Option<Vec<u8>>,
               registrar: &Registrar) -> Result<()>
 {
+        if conn.is_partitioned() && msg.is_some() {
+            // The message is lost in the partition
+            return Ok(());
+        }
         let writable = try!(conn.writer.write(&mut conn.sock, msg).chain_err(|| {
             ErrorKind::WriteError(id, conn.node.clone())
         }));
-        if !writable {
-            return registrar.reregister(id, &conn.sock, Event::Both)
+        let flushed = try!(conn.sock.flush_tls().chain_err(|| {
+            ErrorKind::WriteError(id, conn.node.clone())
+        }));
+        if !writable || !flushed {
+            return conn.sock.reregister(id, registrar, Event::Both)
                 .chain_err(|| ErrorKind::RegistrarError(Some(id), conn.node.clone()));
         }
         Ok(())
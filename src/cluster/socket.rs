@@ -0,0 +1,109 @@
+use std::io::{self, Read, Write};
+use amy::{Registrar, Event};
+use rustls;
+use transport::Stream;
+
+/// A non-blocking connection to another node, optionally encrypted with TLS
+///
+/// TLS records are read from and written to the stream as plaintext is read and written, so the
+/// socket can be used with a `FrameReader` and `FrameWriter` just like a plain stream. However, a
+/// TLS session also sends data on its own, such as during the handshake, and buffers encrypted
+/// data that couldn't be written yet. `flush_tls` must be called to send this data after reading
+/// and whenever the socket becomes writable.
+pub enum Socket {
+    Plain(Box<Stream>),
+    Tls(Box<Stream>, rustls::Connection)
+}
+
+impl Socket {
+    /// Return the TLS session if the connection is encrypted
+    pub fn tls(&self) -> Option<&rustls::Connection> {
+        match *self {
+            Socket::Plain(_) => None,
+            Socket::Tls(_, ref session) => Some(session)
+        }
+    }
+
+    fn stream(&self) -> &Stream {
+        match *self {
+            Socket::Plain(ref sock) | Socket::Tls(ref sock, _) => &**sock
+        }
+    }
+
+    pub fn register(&self, registrar: &Registrar, event: Event) -> io::Result<usize> {
+        self.stream().register(registrar, event)
+    }
+
+    pub fn reregister(&self, id: usize, registrar: &Registrar, event: Event) -> io::Result<()> {
+        self.stream().reregister(id, registrar, event)
+    }
+
+    pub fn deregister(&self, registrar: &Registrar) -> io::Result<()> {
+        self.stream().deregister(registrar)
+    }
+
+    /// Write as much buffered TLS data as possible. Return `Ok(false)` if some data is still
+    /// buffered because the socket isn't writable.
+    pub fn flush_tls(&mut self) -> io::Result<bool> {
+        match *self {
+            Socket::Plain(_) => Ok(true),
+            Socket::Tls(ref mut sock, ref mut session) => flush_session(sock, session)
+        }
+    }
+}
+
+fn flush_session(sock: &mut Box<Stream>, session: &mut rustls::Connection) -> io::Result<bool> {
+    while session.wants_write() {
+        match session.write_tls(sock) {
+            Ok(_) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(true)
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Socket::Plain(ref mut sock) => sock.read(buf),
+            Socket::Tls(ref mut sock, ref mut session) => loop {
+                match session.reader().read(buf) {
+                    // No plaintext is available until more TLS records are read
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                    result => return result
+                }
+                if try!(session.read_tls(sock)) == 0 {
+                    return Ok(0);
+                }
+                if let Err(e) = session.process_new_packets() {
+                    // Try to send the alert describing the error to the peer
+                    let _ = session.write_tls(sock);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                }
+            }
+        }
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Socket::Plain(ref mut sock) => sock.write(buf),
+            Socket::Tls(ref mut sock, ref mut session) => {
+                // Don't buffer more data in the session until earlier data was written, so that the
+                // `FrameWriter` waits for the socket to become writable.
+                if !try!(flush_session(sock, session)) {
+                    return Err(io::Error::new(io::ErrorKind::WouldBlock, "TLS data is buffered"));
+                }
+                let n = try!(session.writer().write(buf));
+                try!(flush_session(sock, session));
+                Ok(n)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_tls().map(|_| ())
+    }
+}
@@ -1,9 +1,16 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use node_id::NodeId;
 
 #[derive(Debug, Clone, Eq, PartialEq, RustcEncodable, RustcDecodable)]
 pub struct ClusterStatus {
     pub members: HashSet<NodeId>,
     pub established: HashSet<NodeId>,
-    pub num_connections: usize
+    pub num_connections: usize,
+
+    /// The suspicion level of each peer with an established connection, as computed by the phi
+    /// accrual failure detector. Connections are closed once this exceeds the configured threshold.
+    ///
+    /// Phi is reported in thousandths, so that statuses can be compared. A phi of 1.5 is reported
+    /// as 1500.
+    pub phi: HashMap<NodeId, u64>
 }
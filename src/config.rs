@@ -0,0 +1,256 @@
+use std::net::ToSocketAddrs;
+use pid::Pid;
+use node_id;
+use cluster::FailureDetectorConfig;
+use tls::TlsConfig;
+use errors::*;
+
+/// Configuration for a rabble node, passed to `NodeBuilder::config` or `rabble::rouse_with_config`
+///
+/// All fields have sensible defaults, so only the fields that need to change have to be set:
+///
+/// ```
+/// let config = rabble::RabbleConfig::new()
+///     .num_workers(4)
+///     .listen_addr("0.0.0.0:11000");
+/// ```
+///
+/// All times are in milliseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RabbleConfig {
+    /// The number of executor worker threads
+    pub num_workers: usize,
+
+    /// The address the cluster server listens on. If `None`, the address of the `NodeId` is used.
+    /// This allows listening on a different address than the one advertised to other nodes, such
+    /// as `0.0.0.0` or an address behind NAT.
+    pub listen_addr: Option<String>,
+
+    /// The maximum size of a message frame sent between nodes
+    pub max_frame_size: u32,
+
+    /// Encrypt and authenticate connections between nodes with TLS. All nodes in a cluster must
+    /// use the same setting.
+    pub tls: Option<TlsConfig>,
+
+    /// A secret shared by all nodes in the cluster. If set, peers must prove they know the same
+    /// cookie before any other messages are exchanged. All nodes in a cluster must use the same
+    /// cookie.
+    pub cookie: Option<String>,
+
+    /// The version of the user message type. Peers with a different version can't connect, so all
+    /// nodes in a cluster must use the same version. Defaults to 0.
+    ///
+    /// Rabble can't derive the version from the message type, because rustc-serialize doesn't
+    /// describe the encoding of a type. Keeping it up to date is up to the application: it must be
+    /// changed whenever the encoding of the message type changes, such as when a field or a variant
+    /// is added, removed or reordered. If it isn't, nodes with incompatible message types connect
+    /// and fail to decode each other's messages.
+    pub msg_version: u32,
+
+    /// How often the cluster server pings peers and checks connections
+    pub tick_time: usize,
+
+    /// How often executor timers are checked. This is the granularity of process timers.
+    pub executor_tick_time: usize,
+
+    /// The maximum time the cluster poller blocks waiting for network events
+    pub poll_timeout: usize,
+
+    /// The maximum time a `Service` waits for network events or messages before polling again
+    pub service_poll_timeout: usize,
+
+    /// The maximum number of envelopes queued for a member of the cluster that there is no
+    /// established connection to yet, such as while joining or reconnecting
+    pub outbound_queue_size: usize,
+
+    /// The maximum time an envelope stays queued waiting for a connection before it is dropped
+    pub outbound_queue_timeout: usize,
+
+    /// The maximum number of envelopes sent with `Node::send_reliable` to a single peer that
+    /// haven't been acknowledged yet. Further reliable envelopes to that peer are dropped.
+    pub max_unacked: usize,
+
+    /// Whether to send a `Msg::Undeliverable` to the sender of an envelope that could not be
+    /// delivered. Only envelopes with a correlation id are reported.
+    pub notify_undeliverable: bool,
+
+    /// A process or service that receives a `Msg::DeadLetter` for every envelope that could not be
+    /// delivered
+    pub dead_letters: Option<Pid>,
+
+    /// The maximum time to spend flushing messages to other nodes when shutting down
+    pub shutdown_timeout: usize,
+
+    /// Configuration of the failure detector used to close connections to unresponsive peers
+    pub failure_detector: FailureDetectorConfig
+}
+
+impl Default for RabbleConfig {
+    fn default() -> RabbleConfig {
+        RabbleConfig {
+            num_workers: 1,
+            listen_addr: None,
+            max_frame_size: 100*1024*1024, // 100 MB
+            tls: None,
+            cookie: None,
+            msg_version: 0,
+            tick_time: 1000,
+            executor_tick_time: 100,
+            poll_timeout: 5000,
+            service_poll_timeout: 1000,
+            outbound_queue_size: 1000,
+            outbound_queue_timeout: 5000,
+            max_unacked: 1000,
+            notify_undeliverable: false,
+            dead_letters: None,
+            shutdown_timeout: 5000,
+            failure_detector: FailureDetectorConfig::default()
+        }
+    }
+}
+
+impl RabbleConfig {
+    /// Create a config with default values
+    pub fn new() -> RabbleConfig {
+        RabbleConfig::default()
+    }
+
+    pub fn num_workers(mut self, num_workers: usize) -> RabbleConfig {
+        self.num_workers = num_workers;
+        self
+    }
+
+    pub fn listen_addr(mut self, addr: &str) -> RabbleConfig {
+        self.listen_addr = Some(addr.to_string());
+        self
+    }
+
+    pub fn max_frame_size(mut self, max_frame_size: u32) -> RabbleConfig {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    pub fn tls(mut self, tls: TlsConfig) -> RabbleConfig {
+        self.tls = Some(tls);
+        self
+    }
+
+    pub fn cookie(mut self, cookie: &str) -> RabbleConfig {
+        self.cookie = Some(cookie.to_string());
+        self
+    }
+
+    pub fn msg_version(mut self, version: u32) -> RabbleConfig {
+        self.msg_version = version;
+        self
+    }
+
+    pub fn tick_time(mut self, ms: usize) -> RabbleConfig {
+        self.tick_time = ms;
+        self
+    }
+
+    pub fn executor_tick_time(mut self, ms: usize) -> RabbleConfig {
+        self.executor_tick_time = ms;
+        self
+    }
+
+    pub fn poll_timeout(mut self, ms: usize) -> RabbleConfig {
+        self.poll_timeout = ms;
+        self
+    }
+
+    pub fn service_poll_timeout(mut self, ms: usize) -> RabbleConfig {
+        self.service_poll_timeout = ms;
+        self
+    }
+
+    pub fn outbound_queue_size(mut self, size: usize) -> RabbleConfig {
+        self.outbound_queue_size = size;
+        self
+    }
+
+    pub fn outbound_queue_timeout(mut self, ms: usize) -> RabbleConfig {
+        self.outbound_queue_timeout = ms;
+        self
+    }
+
+    pub fn max_unacked(mut self, max_unacked: usize) -> RabbleConfig {
+        self.max_unacked = max_unacked;
+        self
+    }
+
+    pub fn notify_undeliverable(mut self, notify: bool) -> RabbleConfig {
+        self.notify_undeliverable = notify;
+        self
+    }
+
+    pub fn dead_letters(mut self, pid: Pid) -> RabbleConfig {
+        self.dead_letters = Some(pid);
+        self
+    }
+
+    pub fn shutdown_timeout(mut self, ms: usize) -> RabbleConfig {
+        self.shutdown_timeout = ms;
+        self
+    }
+
+    pub fn failure_detector(mut self, config: FailureDetectorConfig) -> RabbleConfig {
+        self.failure_detector = config;
+        self
+    }
+
+    /// Return an `ErrorKind::InvalidConfig` describing the first invalid value, if any
+    pub fn validate(&self) -> Result<()> {
+        if self.num_workers == 0 {
+            return invalid("num_workers must be at least 1");
+        }
+        if self.max_frame_size == 0 {
+            return invalid("max_frame_size must be greater than 0");
+        }
+        let times = [("tick_time", self.tick_time),
+                     ("executor_tick_time", self.executor_tick_time),
+                     ("poll_timeout", self.poll_timeout),
+                     ("service_poll_timeout", self.service_poll_timeout)];
+        for &(name, ms) in times.iter() {
+            if ms == 0 {
+                return invalid(&format!("{} must be greater than 0 ms", name));
+            }
+        }
+        let threshold = self.failure_detector.threshold;
+        if !threshold.is_finite() || threshold <= 0.0 {
+            return invalid(&format!("failure_detector.threshold must be a positive number, got {}",
+                                    threshold));
+        }
+        if self.failure_detector.max_samples == 0 {
+            return invalid("failure_detector.max_samples must be at least 1");
+        }
+        if self.failure_detector.min_std_deviation == 0 {
+            return invalid("failure_detector.min_std_deviation must be greater than 0 ms");
+        }
+        // Peers ping every tick, so a smaller estimate makes every new peer look late
+        if self.failure_detector.first_heartbeat_estimate < self.tick_time as u64 {
+            return invalid(&format!("failure_detector.first_heartbeat_estimate ({} ms) must be at \
+                                     least tick_time ({} ms)",
+                                    self.failure_detector.first_heartbeat_estimate,
+                                    self.tick_time));
+        }
+        if self.cookie.as_ref().map_or(false, |cookie| cookie.is_empty()) {
+            return invalid("cookie must not be empty");
+        }
+        if let Some(ref addr) = self.listen_addr {
+            if node_id::unix_socket_path(addr).is_some() {
+                // Unix domain sockets are checked when the node binds them
+            } else if let Err(e) = addr.to_socket_addrs() {
+                return invalid(&format!("listen_addr {} is not a valid socket address: {}",
+                                        addr, e));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid(reason: &str) -> Result<()> {
+    Err(ErrorKind::InvalidConfig(reason.to_string()).into())
+}
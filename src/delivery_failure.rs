@@ -0,0 +1,66 @@
+use std::fmt::Debug;
+use rustc_serialize::{Encodable, Decodable};
+use node_id::NodeId;
+use pid::Pid;
+use envelope::Envelope;
+use msg::Msg;
+use config::RabbleConfig;
+
+/// The reason an envelope could not be delivered
+///
+/// Delivered to the configured dead letter pid in `Msg::DeadLetter`, and to the sender in
+/// `Msg::Undeliverable` if `RabbleConfig::notify_undeliverable` is set and the envelope had a
+/// correlation id.
+#[derive(Debug, Clone, Eq, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum DeliveryFailure {
+    /// The envelope was addressed to a node without an established connection, and the queue of
+    /// envelopes waiting for that connection was full
+    QueueFull,
+
+    /// The envelope was queued waiting for a connection to its node for longer than
+    /// `RabbleConfig::outbound_queue_timeout`
+    Expired,
+
+    /// There is no process or service with the pid the envelope was addressed to
+    NoSuchPid,
+
+    /// The envelope was addressed to a node that isn't a member of the cluster, or that left the
+    /// cluster before the envelope could be sent
+    NodeUnreachable,
+
+    /// A message received from the given node could not be decoded. The node is `None` if the
+    /// connection wasn't established yet.
+    DecodeFailure(Option<NodeId>)
+}
+
+/// Return the envelopes reporting that `envelope` could not be delivered: a `Msg::DeadLetter` to
+/// the configured dead letter pid, and a `Msg::Undeliverable` reply to the sender if enabled.
+///
+/// `envelope` is `None` if it could not be decoded. Failures to deliver these reports are never
+/// reported themselves, so that they can't loop.
+pub fn reports<T>(envelope: Option<Envelope<T>>,
+                  failure: DeliveryFailure,
+                  from: &Pid,
+                  config: &RabbleConfig) -> Vec<Envelope<T>>
+    where T: Encodable + Decodable + Debug + Clone
+{
+    let mut reports = Vec::new();
+    if let Some(ref envelope) = envelope {
+        match envelope.msg {
+            Msg::DeadLetter(..) | Msg::Undeliverable(..) => return reports,
+            _ => ()
+        }
+        if config.notify_undeliverable && envelope.correlation_id.is_some() {
+            let msg = Msg::Undeliverable(envelope.to.clone(), failure.clone());
+            reports.push(Envelope::new(envelope.from.clone(),
+                                       from.clone(),
+                                       msg,
+                                       envelope.correlation_id.clone()));
+        }
+    }
+    if let Some(ref dead_letters) = config.dead_letters {
+        let msg = Msg::DeadLetter(envelope.map(Box::new), failure);
+        reports.push(Envelope::new(dead_letters.clone(), from.clone(), msg, None));
+    }
+    reports
+}
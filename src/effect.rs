@@ -0,0 +1,34 @@
+use std::fmt::Debug;
+use rustc_serialize::{Encodable, Decodable};
+use pid::Pid;
+use process::Process;
+use envelope::Envelope;
+
+/// Requests from a process to its executor that can't be sent in an envelope
+///
+/// Effects are returned from `Process::effects` and only act on the local node. They allow
+/// processes to manage other processes without holding a `Node`.
+pub enum Effect<T: Encodable + Decodable + Debug + Clone> {
+    /// Start a new process on the local node
+    Spawn(Pid, Box<Process<Msg=T>>),
+
+    /// Stop a process on the local node
+    Stop(Pid),
+
+    /// Register a name for a local process in the cluster wide registry. The name is removed when
+    /// the process exits.
+    ///
+    /// This is the same as the process sending `Msg::RegisterName` to the cluster server, except
+    /// that any local pid can be registered. Names are looked up by sending `Msg::WhereIsName` to
+    /// the cluster server.
+    Register(String, Pid),
+
+    /// Remove the name registered for a local process
+    Unregister(String, Pid),
+
+    /// Send an envelope with at-least-once delivery if it's addressed to a process on another
+    /// node. See `Node::send_reliable`.
+    ///
+    /// Effects are performed before the envelopes returned from the same callback are sent.
+    SendReliable(Envelope<T>)
+}
@@ -24,3 +24,20 @@ impl<T: Encodable + Decodable + Debug + Clone> Envelope<T> {
         }
     }
 }
+
+/// The delivery guarantee of an envelope sent to a process on another node
+///
+/// Envelopes are sent `AtMostOnce` by default, and `AtLeastOnce` when sent with
+/// `Node::send_reliable` or `Effect::SendReliable`. Envelopes between processes on the same node
+/// are always delivered as long as the destination exists.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Delivery {
+    /// The envelope is written to the connection once. It is lost if the connection drops before
+    /// the peer reads it.
+    AtMostOnce,
+
+    /// The envelope is retained until the peer acknowledges it, and resent whenever a new
+    /// connection to the peer is established. The peer drops duplicates, so the envelope is only
+    /// delivered more than once if the peer restarts.
+    AtLeastOnce
+}
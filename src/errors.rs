@@ -50,6 +50,51 @@ error_chain! {
             description("Failed to send")
             display("Failed to send {} to {:?}", msg, pid)
         }
+        BindError(addr: String) {
+            description("Failed to bind listen address")
+            display("Failed to bind listen address {}", addr)
+        }
+        PollerError {
+            description("Failed to create poller")
+            display("Failed to create poller")
+        }
+        SpawnError(thread: String) {
+            description("Failed to spawn thread")
+            display("Failed to spawn thread {}", thread)
+        }
+        JoinError(threads: Vec<String>) {
+            description("Threads panicked")
+            display("Threads panicked: {:?}", threads)
+        }
+        NodeShutdown(node: NodeId) {
+            description("Node is shutting down")
+            display("Node {} is shutting down", node)
+        }
+        InvalidConfig(reason: String) {
+            description("Invalid configuration")
+            display("Invalid configuration: {}", reason)
+        }
+        TlsError(reason: String) {
+            description("TLS configuration error")
+            display("TLS configuration error: {}", reason)
+        }
+        TlsIdentityError(id: usize, node: NodeId) {
+            description("Peer certificate does not match its node")
+            display("Peer certificate is not valid for {}, id={}", node, id)
+        }
+        AuthError(id: usize, node: Option<NodeId>) {
+            description("Peer failed cookie authentication")
+            display("Peer failed cookie authentication: id={}, peer={:?}", id, node)
+        }
+        UnestablishedError(id: usize, node: Option<NodeId>) {
+            description("Peer sent a message before the connection was established")
+            display("Peer sent a message before the connection was established: id={}, peer={:?}",
+                    id, node)
+        }
+        IncompatiblePeer(id: usize, node: Option<NodeId>, reason: String) {
+            description("Peer speaks an incompatible protocol")
+            display("Incompatible peer: {}, id={}, peer={:?}", reason, id, node)
+        }
         Shutdown(pid: Pid) {
             description("Shutting down")
             display("Shutting down {}", pid)
@@ -66,6 +111,10 @@ impl ErrorKind {
             ErrorKind::RegistrarError(id, _) => id.map_or(vec![], |id| vec![id]),
             ErrorKind::WriteError(id, _) => vec![id],
             ErrorKind::ReadError(id, _) => vec![id],
+            ErrorKind::TlsIdentityError(id, _) => vec![id],
+            ErrorKind::AuthError(id, _) => vec![id],
+            ErrorKind::UnestablishedError(id, _) => vec![id],
+            ErrorKind::IncompatiblePeer(id, ..) => vec![id],
             ErrorKind::BroadcastError(ref errors) =>
                 errors.iter().flat_map(|e| e.kind().get_ids()).collect(),
             ErrorKind::PollNotificationErrors(ref errors) =>
@@ -2,6 +2,8 @@ use rustc_serialize::{Encodable, Decodable};
 use std::fmt::Debug;
 use std::sync::mpsc::{Sender, Receiver};
 use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::any::Any;
 use amy;
 use slog;
 use time::Duration;
@@ -9,47 +11,66 @@ use ferris::{Wheel, CopyWheel, Resolution};
 use envelope::Envelope;
 use pid::Pid;
 use process::Process;
+use effect::Effect;
 use node_id::NodeId;
 use msg::Msg;
-use cluster::ClusterMsg;
+use reason::Reason;
+use delivery_failure::{self, DeliveryFailure};
+use config::RabbleConfig;
+use cluster::{ClusterMsg, cluster_server_pid};
 use correlation_id::CorrelationId;
 use metrics::Metrics;
-use super::{ExecutorStatus, ExecutorMetrics, ExecutorMsg};
+use super::{ExecutorStatus, ExecutorMetrics, ExecutorMsg, Workers};
+use super::monitors::Monitors;
 
+/// A single executor worker thread
+///
+/// A node runs one or more executor workers. Each process is owned by exactly one worker,
+/// determined by hashing its pid. Services are registered with every worker.
 pub struct Executor<T: Encodable + Decodable + Send + Debug + Clone> {
     pid: Pid,
     node: NodeId,
+    index: usize,
     processes: HashMap<Pid, Box<Process<Msg=T>>>,
     service_senders: HashMap<Pid, amy::Sender<Envelope<T>>>,
-    tx: Sender<ExecutorMsg<T>>,
+    workers: Workers<T>,
     rx: Receiver<ExecutorMsg<T>>,
     cluster_tx: Sender<ClusterMsg<T>>,
+    config: RabbleConfig,
     timer_wheel: CopyWheel<(Pid, Option<CorrelationId>)>,
+    monitors: Monitors,
+
+    /// The exit reasons of processes owned by this worker whose `init` panicked. Monitors and links
+    /// created after the panic get this reason instead of `Reason::NoProc`, so that a process
+    /// monitoring a pid right after spawning it finds out why it exited. An entry is removed when
+    /// its pid is started or stopped again.
+    init_panics: HashMap<Pid, Reason>,
     logger: slog::Logger,
     metrics: ExecutorMetrics
 }
 
 impl<T: Encodable + Decodable + Send + Debug + Clone> Executor<T> {
     pub fn new(node: NodeId,
-               tx: Sender<ExecutorMsg<T>>,
+               index: usize,
+               workers: Workers<T>,
                rx: Receiver<ExecutorMsg<T>>,
                cluster_tx: Sender<ClusterMsg<T>>,
+               config: RabbleConfig,
                logger: slog::Logger) -> Executor<T> {
-        let pid = Pid {
-            group: Some("rabble".to_string()),
-            name: "executor".to_string(),
-            node: node.clone()
-        };
         Executor {
-            pid: pid,
+            pid: executor_pid(node.clone()),
             node: node,
+            index: index,
             processes: HashMap::new(),
             service_senders: HashMap::new(),
-            tx: tx,
+            workers: workers,
             rx: rx,
             cluster_tx: cluster_tx,
+            config: config,
             timer_wheel: CopyWheel::new(vec![Resolution::TenMs, Resolution::Sec, Resolution::Min]),
-            logger: logger.new(o!("component" => "executor")),
+            monitors: Monitors::new(),
+            init_panics: HashMap::new(),
+            logger: logger.new(o!("component" => "executor", "worker" => index)),
             metrics: ExecutorMetrics::new()
         }
     }
@@ -59,42 +80,121 @@ impl<T: Encodable + Decodable + Send + Debug + Clone> Executor<T> {
     ///This call blocks the current thread indefinitely.
     pub fn run(mut self) {
         while let Ok(msg) = self.rx.recv() {
-            match msg {
-                ExecutorMsg::Envelope(envelope) => {
-                    self.metrics.received_envelopes += 1;
-                    self.route(envelope);
-                },
-                ExecutorMsg::Start(pid, process) => self.start(pid, process),
-                ExecutorMsg::Stop(pid) => self.stop(pid),
-                ExecutorMsg::RegisterService(pid, tx) => {
-                    self.service_senders.insert(pid, tx);
-                },
-                ExecutorMsg::GetStatus(correlation_id) => self.get_status(correlation_id),
-                ExecutorMsg::Tick => self.tick(),
+            if !self.handle(msg) {
+                return;
+            }
+        }
+    }
 
-                // Just return so the thread exits
-                ExecutorMsg::Shutdown => return
+    /// Handle a single message. Return false once the executor has shut down.
+    ///
+    /// `run` calls this for every received message. A `Simulation` calls it directly, so that it
+    /// controls the order in which the executors of all simulated nodes handle their messages.
+    pub(crate) fn handle(&mut self, msg: ExecutorMsg<T>) -> bool {
+        match msg {
+            ExecutorMsg::Envelope(envelope) => {
+                self.metrics.received_envelopes += 1;
+                self.route(envelope);
+            },
+            ExecutorMsg::ReliableEnvelope(envelope) => {
+                self.metrics.received_envelopes += 1;
+                self.route_reliable(envelope);
+            },
+            ExecutorMsg::Start(pid, process) => self.start(pid, process),
+            ExecutorMsg::Stop(pid) => self.stop(pid, Reason::Normal),
+            ExecutorMsg::RegisterService(pid, tx) => {
+                self.service_senders.insert(pid, tx);
+            },
+            ExecutorMsg::GetStatus(correlation_id) => {
+                let status = ExecutorStatus {
+                    workers: self.workers.len(),
+                    total_processes: 0,
+                    services: self.service_senders.keys().cloned().collect()
+                };
+                self.collect_status(correlation_id, status);
+            },
+            ExecutorMsg::WorkerStatus(correlation_id, status) => {
+                self.collect_status(correlation_id, status)
+            },
+            ExecutorMsg::WorkerMetrics(to, correlation_id, metrics, remaining) => {
+                self.collect_metrics(to, correlation_id, metrics, remaining)
+            },
+            ExecutorMsg::Tick => self.tick(),
+            ExecutorMsg::NodeDown(node) => self.node_down(node),
+            ExecutorMsg::Monitor(target, watcher) => self.monitor(target, watcher),
+            ExecutorMsg::Demonitor(target, watcher) => {
+                self.monitors.demonitor(&self.node, &target, &watcher)
+            },
+            ExecutorMsg::Link(pid, peer) => self.link(pid, peer),
+            ExecutorMsg::Unlink(pid, peer) => self.monitors.unlink(&pid, &peer),
+
+            ExecutorMsg::Shutdown => {
+                self.shutdown();
+                return false;
             }
         }
+        true
     }
 
-    fn get_status(&self, correlation_id: CorrelationId) {
-        let status = ExecutorStatus {
-            total_processes: self.processes.len(),
-            services: self.service_senders.keys().cloned().collect()
-        };
+    /// Return the next received message without blocking, if there is one
+    pub(crate) fn try_recv(&self) -> Option<ExecutorMsg<T>> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Tell services to shutdown and let the cluster server know this worker stopped.
+    ///
+    /// Any envelopes to remote processes were already sent to the cluster server, so it can flush
+    /// them before closing its connections.
+    fn shutdown(&mut self) {
+        info!(self.logger, "Shutting down");
+        // Services are registered with every worker, so only the first worker notifies them
+        if self.index == 0 {
+            for (pid, tx) in self.service_senders.iter() {
+                let envelope = Envelope::new(pid.clone(), self.pid.clone(), Msg::Shutdown, None);
+                let _ = tx.send(envelope);
+            }
+        }
+        let _ = self.cluster_tx.send(ClusterMsg::ExecutorStopped);
+    }
+
+    /// Add the number of processes on this worker to the status and pass it on to the next worker.
+    ///
+    /// Status requests always start at worker 0, so the last worker sends the aggregated reply.
+    fn collect_status(&mut self, correlation_id: CorrelationId, mut status: ExecutorStatus) {
+        status.total_processes += self.processes.len();
+        if self.index + 1 < self.workers.len() {
+            let msg = ExecutorMsg::WorkerStatus(correlation_id, status);
+            // This won't ever fail because we hold a ref to both ends of the channel
+            self.workers.send_to(self.index + 1, msg).unwrap();
+            return;
+        }
         let envelope = Envelope {
             to: correlation_id.pid.clone(),
             from: self.pid.clone(),
             msg: Msg::ExecutorStatus(status),
             correlation_id: Some(correlation_id)
         };
-        self.route_to_service(envelope);
+        self.route(envelope);
     }
 
     fn start(&mut self, pid: Pid, mut process: Box<Process<Msg=T>>) {
-        let envelopes = process.init(self.pid.clone());
+        self.init_panics.remove(&pid);
+        let executor_pid = self.pid.clone();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let envelopes = process.init(executor_pid);
+            (envelopes, drain_effects(&mut *process))
+        }));
+        let (envelopes, effects) = match result {
+            Ok(result) => result,
+            Err(payload) => {
+                // The process was never added, so there is nobody monitoring it yet
+                let reason = self.panicked(&pid, "init", payload);
+                self.init_panics.insert(pid, reason);
+                return;
+            }
+        };
         self.processes.insert(pid, process);
+        self.perform_effects(effects);
         for envelope in envelopes {
             if envelope.to == self.pid {
                 self.handle_executor_envelope(envelope);
@@ -104,8 +204,162 @@ impl<T: Encodable + Decodable + Send + Debug + Clone> Executor<T> {
         }
     }
 
-    fn stop(&mut self, pid: Pid) {
-        self.processes.remove(&pid);
+    /// Log and count a panic in a process callback and return the exit reason for the process
+    fn panicked(&mut self, pid: &Pid, callback: &str, payload: Box<Any + Send>) -> Reason {
+        let description = panic_description(payload);
+        error!(self.logger, "Process panicked";
+               "pid" => pid.to_string(), "callback" => callback, "panic" => description.clone());
+        self.metrics.panics += 1;
+        Reason::Panic(description)
+    }
+
+    /// Remove a process and notify any processes monitoring or linked to it
+    fn stop(&mut self, pid: Pid, reason: Reason) {
+        if self.processes.remove(&pid).is_none() {
+            self.init_panics.remove(&pid);
+            return;
+        }
+        let (watchers, links, watched) = self.monitors.exit(&pid);
+        for watcher in watchers {
+            let msg = Msg::Down(pid.clone(), reason.clone());
+            let envelope = Envelope::new(watcher, self.pid.clone(), msg, None);
+            self.route(envelope);
+        }
+        for peer in links {
+            let msg = Msg::Exit(pid.clone(), reason.clone());
+            let envelope = Envelope::new(peer, pid.clone(), msg, None);
+            self.route(envelope);
+        }
+        // Remove the monitors the process held on other workers and nodes
+        for target in watched {
+            if target.node != self.node {
+                self.forward_request(pid.clone(), &target, Msg::Demonitor(target.clone()));
+            } else if self.workers.index(&target) != self.index {
+                // This won't ever fail because we hold a ref to both ends of the channel
+                self.workers.send(&target, ExecutorMsg::Demonitor(target.clone(), pid.clone()))
+                    .unwrap();
+            }
+        }
+    }
+
+    /// Perform effects requested by a process on this worker
+    ///
+    /// Effects on pids owned by this worker are performed immediately. All others are sent to the
+    /// owning worker, so that they are ordered before any envelopes later sent to the same pid.
+    fn perform_effects(&mut self, effects: Vec<Effect<T>>) {
+        for effect in effects {
+            match effect {
+                Effect::Spawn(pid, process) => {
+                    if self.is_remote(&pid, "spawn") { continue; }
+                    if self.workers.index(&pid) == self.index {
+                        self.start(pid, process);
+                    } else {
+                        // This won't ever fail because we hold a ref to both ends of the channel
+                        self.workers.send(&pid, ExecutorMsg::Start(pid.clone(), process)).unwrap();
+                    }
+                },
+                Effect::Stop(pid) => {
+                    if self.is_remote(&pid, "stop") { continue; }
+                    if self.workers.index(&pid) == self.index {
+                        self.stop(pid, Reason::Normal);
+                    } else {
+                        self.workers.send(&pid, ExecutorMsg::Stop(pid.clone())).unwrap();
+                    }
+                },
+                Effect::Register(name, pid) => {
+                    if self.is_remote(&pid, "register") { continue; }
+                    self.send_to_cluster_server(pid, Msg::RegisterName(name));
+                },
+                Effect::Unregister(name, pid) => {
+                    if self.is_remote(&pid, "unregister") { continue; }
+                    self.send_to_cluster_server(pid, Msg::UnregisterName(name));
+                },
+                Effect::SendReliable(envelope) => self.route_reliable(envelope)
+            }
+        }
+    }
+
+    /// Return true and log an error if an effect targets a pid on another node
+    fn is_remote(&self, pid: &Pid, effect: &str) -> bool {
+        if pid.node == self.node {
+            return false;
+        }
+        error!(self.logger, "Effects can only be performed on local processes";
+               "effect" => effect, "pid" => pid.to_string());
+        true
+    }
+
+    /// Send a request to the cluster server on behalf of a local pid
+    ///
+    /// The cluster server registers and unregisters names for the sender of the request.
+    fn send_to_cluster_server(&self, from: Pid, msg: Msg<T>) {
+        let envelope = Envelope::new(cluster_server_pid(self.node.clone()), from, msg, None);
+        self.cluster_tx.send(ClusterMsg::Envelope(envelope)).unwrap();
+    }
+
+    /// Record that `watcher` monitors `target`. If `target` is local and is neither a process nor
+    /// a service, notify `watcher` immediately.
+    fn monitor(&mut self, target: Pid, watcher: Pid) {
+        if target.node == self.node &&
+            !self.processes.contains_key(&target) &&
+            !self.service_senders.contains_key(&target)
+        {
+            let msg = Msg::Down(target.clone(), self.exit_reason(&target));
+            let envelope = Envelope::new(watcher, self.pid.clone(), msg, None);
+            return self.route(envelope);
+        }
+        self.monitors.monitor(&self.node, target, watcher);
+    }
+
+    /// Record that local process `pid` is linked with `peer`. If `pid` doesn't exist, send an exit
+    /// signal to `peer` immediately.
+    fn link(&mut self, pid: Pid, peer: Pid) {
+        if !self.processes.contains_key(&pid) {
+            let msg = Msg::Exit(pid.clone(), self.exit_reason(&pid));
+            let envelope = Envelope::new(peer, pid, msg, None);
+            return self.route(envelope);
+        }
+        self.monitors.link(pid, peer);
+    }
+
+    /// Return why a local process that doesn't exist has exited
+    fn exit_reason(&self, pid: &Pid) -> Reason {
+        self.init_panics.get(pid).cloned().unwrap_or(Reason::NoProc)
+    }
+
+    /// Handle an exit signal sent to a process from a process linked to it
+    ///
+    /// Exit signals are sent from the pid of the exited process. Signals that aren't from a process
+    /// linked to the target, or from this executor, are dropped, so that a process can't stop
+    /// processes it isn't linked with by sending them `Msg::Exit`.
+    fn exit_signal(&mut self, envelope: Envelope<T>) {
+        if let Msg::Exit(exited, reason) = envelope.msg {
+            let linked = envelope.from == exited && self.monitors.is_linked(&envelope.to, &exited);
+            if !linked && envelope.from != self.pid {
+                warn!(self.logger, "Dropping exit signal from a process that isn't linked";
+                      "to" => envelope.to.to_string(),
+                      "from" => envelope.from.to_string(),
+                      "exited" => exited.to_string());
+                return;
+            }
+            self.monitors.unlink(&envelope.to, &exited);
+            if reason != Reason::Normal {
+                self.stop(envelope.to, Reason::Linked(exited));
+            }
+        }
+    }
+
+    /// Notify local processes monitoring or linked to processes on a node that left the cluster
+    fn node_down(&mut self, node: NodeId) {
+        let (down, linked) = self.monitors.node_down(&node);
+        for (target, watcher) in down {
+            let msg = Msg::Down(target, Reason::NodeDown);
+            let envelope = Envelope::new(watcher, self.pid.clone(), msg, None);
+            self.route(envelope);
+        }
+        for (pid, peer) in linked {
+            self.stop(pid, Reason::Linked(peer));
+        }
     }
 
     fn tick(&mut self) {
@@ -120,15 +374,40 @@ impl<T: Encodable + Decodable + Send + Debug + Clone> Executor<T> {
     /// Retrieve any envelopes from processes handling local messages and put them on either the
     /// executor or the cluster channel depending upon whether they are local or remote.
     ///
-    /// Note that all envelopes sent to an executor are sent from the local cluster server and must
-    /// be addressed to local processes.
+    /// Envelopes addressed to a local pid owned by another worker are forwarded to that worker's
+    /// channel, so that all envelopes to a given pid travel through the same channel.
     fn route(&mut self, envelope: Envelope<T>) {
         if self.node != envelope.to.node {
-            self.cluster_tx.send(ClusterMsg::Envelope(envelope)).unwrap();
+            if let Err(_) = self.cluster_tx.send(ClusterMsg::Envelope(envelope)) {
+                // The cluster server only exits when the node shuts down
+                warn!(self.logger, "Failed to send envelope to cluster server");
+            }
+            return;
+        }
+        if envelope.to != self.pid && self.workers.index(&envelope.to) != self.index {
+            // This only fails if the other worker already exited during shutdown
+            if let Err(_) = self.workers.send_envelope(envelope) {
+                warn!(self.logger, "Failed to send envelope to executor worker");
+            }
             return;
         }
         if let Err(envelope) = self.route_to_process(envelope) {
-            self.route_to_service(envelope);
+            if let Err(envelope) = self.route_to_service(envelope) {
+                self.undeliverable(envelope, DeliveryFailure::NoSuchPid);
+            }
+        }
+    }
+
+    /// Route an envelope that should be delivered at least once to processes on other nodes
+    ///
+    /// Local envelopes don't need acknowledgements, so they are routed like any other envelope.
+    fn route_reliable(&mut self, envelope: Envelope<T>) {
+        if self.node == envelope.to.node {
+            return self.route(envelope);
+        }
+        if let Err(_) = self.cluster_tx.send(ClusterMsg::ReliableEnvelope(envelope)) {
+            // The cluster server only exits when the node shuts down
+            warn!(self.logger, "Failed to send envelope to cluster server");
         }
     }
 
@@ -148,13 +427,40 @@ impl<T: Encodable + Decodable + Send + Debug + Clone> Executor<T> {
             return Ok(());
         }
 
-        let envelopes: Vec<_> = if let Some(process) = self.processes.get_mut(&envelope.to) {
+        if let Msg::Exit(..) = envelope.msg {
+            self.exit_signal(envelope);
+            return Ok(());
+        }
+
+        if let Msg::Down(ref target, _) = envelope.msg {
+            self.monitors.demonitor(&self.node, target, &envelope.to);
+            self.monitors.unwatch(&envelope.to, target);
+        }
+
+        let result = if let Some(process) = self.processes.get_mut(&envelope.to) {
             let Envelope {from, msg, correlation_id, ..} = envelope;
-            process.handle(msg, from, correlation_id).drain(..).collect()
+            // A panicking process must not take down the other processes on this worker
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                let envelopes: Vec<_> =
+                    process.handle(msg, from, correlation_id).drain(..).collect();
+                (envelopes, drain_effects(&mut **process))
+            }))
         } else {
             return Err(envelope);
         };
 
+        let envelopes = match result {
+            Ok((envelopes, effects)) => {
+                self.perform_effects(effects);
+                envelopes
+            },
+            Err(payload) => {
+                let reason = self.panicked(&envelope.to, "handle", payload);
+                self.stop(envelope.to, reason);
+                return Ok(());
+            }
+        };
+
         for envelope in envelopes {
             if envelope.to == self.pid {
                 self.handle_executor_envelope(envelope);
@@ -162,7 +468,7 @@ impl<T: Encodable + Decodable + Send + Debug + Clone> Executor<T> {
             }
             if envelope.to.node == self.node {
                 // This won't ever fail because we hold a ref to both ends of the channel
-                self.tx.send(ExecutorMsg::Envelope(envelope)).unwrap();
+                self.workers.send_envelope(envelope).unwrap();
             } else {
                 self.cluster_tx.send(ClusterMsg::Envelope(envelope)).unwrap();
             }
@@ -170,12 +476,25 @@ impl<T: Encodable + Decodable + Send + Debug + Clone> Executor<T> {
         Ok(())
     }
 
-    /// Route an envelope to a service on this node
-    fn route_to_service(&self, envelope: Envelope<T>) {
+    /// Route an envelope to a service if it is registered on this node.
+    ///
+    /// Return Ok(()) if the service exists, Err(envelope) otherwise.
+    fn route_to_service(&self, envelope: Envelope<T>) -> Result<(), Envelope<T>> {
         if let Some(tx) = self.service_senders.get(&envelope.to) {
             tx.send(envelope).unwrap();
-        } else {
-            warn!(self.logger, "Failed to find service"; "pid" => envelope.to.to_string());
+            return Ok(());
+        }
+        Err(envelope)
+    }
+
+    /// Report an envelope that could not be delivered to the dead letter pid and the sender
+    fn undeliverable(&mut self, envelope: Envelope<T>, failure: DeliveryFailure) {
+        warn!(self.logger, "Undeliverable envelope";
+              "to" => envelope.to.to_string(), "reason" => format!("{:?}", failure));
+        self.metrics.undeliverable_envelopes += 1;
+        let pid = self.pid.clone();
+        for report in delivery_failure::reports(Some(envelope), failure, &pid, &self.config) {
+            self.route(report);
         }
     }
 
@@ -192,21 +511,116 @@ impl<T: Encodable + Decodable + Send + Debug + Clone> Executor<T> {
                 self.metrics.timers_cancelled += 1;
             }
             Msg::GetMetrics => self.send_metrics(from, correlation_id),
+            Msg::Monitor(target) => {
+                if self.processes.contains_key(&from) {
+                    self.monitors.watch(from.clone(), target.clone());
+                }
+                let owner = if target.node == self.node { target.clone() } else { from.clone() };
+                // This won't ever fail because we hold a ref to both ends of the channel
+                self.workers.send(&owner, ExecutorMsg::Monitor(target.clone(), from.clone())).unwrap();
+                self.forward_request(from, &target, Msg::Monitor(target.clone()));
+            },
+            Msg::Demonitor(target) => {
+                self.monitors.unwatch(&from, &target);
+                let owner = if target.node == self.node { target.clone() } else { from.clone() };
+                self.workers.send(&owner, ExecutorMsg::Demonitor(target.clone(), from.clone())).unwrap();
+                self.forward_request(from, &target, Msg::Demonitor(target.clone()));
+            },
+            Msg::Link(target) => {
+                if from.node == self.node {
+                    self.workers.send(&from, ExecutorMsg::Link(from.clone(), target.clone())).unwrap();
+                }
+                if target.node == self.node {
+                    self.workers.send(&target, ExecutorMsg::Link(target.clone(), from.clone())).unwrap();
+                }
+                self.forward_request(from, &target, Msg::Link(target.clone()));
+            },
+            Msg::Unlink(target) => {
+                if from.node == self.node {
+                    self.workers.send(&from, ExecutorMsg::Unlink(from.clone(), target.clone())).unwrap();
+                }
+                if target.node == self.node {
+                    self.workers.send(&target, ExecutorMsg::Unlink(target.clone(), from.clone())).unwrap();
+                }
+                self.forward_request(from, &target, Msg::Unlink(target.clone()));
+            },
             _ => error!(self.logger, "Invalid message sent to executor";
                         "from" => from.to_string(), "msg" => format!("{:?}", msg))
         }
     }
 
+    /// Forward a monitor or link request from a local process about a remote pid to the executor on
+    /// the remote node, so that it can record the request as well.
+    fn forward_request(&self, from: Pid, target: &Pid, msg: Msg<T>) {
+        if target.node == self.node || from.node != self.node {
+            return;
+        }
+        let envelope = Envelope::new(executor_pid(target.node.clone()), from, msg, None);
+        self.cluster_tx.send(ClusterMsg::Envelope(envelope)).unwrap();
+    }
+
+    /// Start collecting metrics from all workers, beginning with this one.
     fn send_metrics(&mut self, from: Pid, correlation_id: Option<CorrelationId>) {
+        let remaining = self.workers.len();
+        self.collect_metrics(from, correlation_id, ExecutorMetrics::new(), remaining);
+    }
+
+    /// Add this worker's metrics to `metrics` and pass them on to the next worker. Once all workers
+    /// have added their metrics, reply to `to`.
+    fn collect_metrics(&mut self,
+                       to: Pid,
+                       correlation_id: Option<CorrelationId>,
+                       mut metrics: ExecutorMetrics,
+                       remaining: usize)
+    {
         self.metrics.processes = self.processes.len() as i64;
         self.metrics.services = self.service_senders.len() as i64;
+        metrics.add(&self.metrics);
+        metrics.services = self.metrics.services;
+        if remaining > 1 {
+            let next = (self.index + 1) % self.workers.len();
+            let msg = ExecutorMsg::WorkerMetrics(to, correlation_id, metrics, remaining - 1);
+            // This won't ever fail because we hold a ref to both ends of the channel
+            self.workers.send_to(next, msg).unwrap();
+            return;
+        }
         let envelope = Envelope {
-            to: from,
+            to: to,
             from: self.pid.clone(),
-            msg: Msg::Metrics(self.metrics.data()),
+            msg: Msg::Metrics(metrics.data()),
             correlation_id: correlation_id
         };
         self.route(envelope);
     }
 }
 
+
+/// Return the pid of the executor on `node`
+pub fn executor_pid(node: NodeId) -> Pid {
+    Pid {
+        group: Some("rabble".to_string()),
+        name: "executor".to_string(),
+        node: node
+    }
+}
+
+/// Drain the effects of a process after a call to `init` or `handle`
+pub fn drain_effects<T>(process: &mut Process<Msg=T>) -> Vec<Effect<T>>
+    where T: Encodable + Decodable + Debug + Clone
+{
+    match process.effects() {
+        Some(effects) => effects.drain(..).collect(),
+        None => Vec::new()
+    }
+}
+
+/// Return a description of a panic from its payload
+pub fn panic_description(payload: Box<Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        return s.to_string();
+    }
+    if let Some(s) = payload.downcast_ref::<String>() {
+        return s.clone();
+    }
+    "Unknown panic".to_string()
+}
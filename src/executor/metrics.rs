@@ -5,5 +5,21 @@ metrics!(ExecutorMetrics {
     services: i64,
     received_envelopes: u64,
     timers_started: u64,
-    timers_cancelled: u64
+    timers_cancelled: u64,
+    panics: u64,
+    undeliverable_envelopes: u64
 });
+
+impl ExecutorMetrics {
+    /// Add the metrics of another executor worker to these metrics
+    ///
+    /// Services are registered with every worker, so they are not summed.
+    pub fn add(&mut self, other: &ExecutorMetrics) {
+        self.processes += other.processes;
+        self.received_envelopes += other.received_envelopes;
+        self.timers_started += other.timers_started;
+        self.timers_cancelled += other.timers_cancelled;
+        self.panics += other.panics;
+        self.undeliverable_envelopes += other.undeliverable_envelopes;
+    }
+}
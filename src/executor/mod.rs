@@ -2,8 +2,11 @@ mod executor;
 mod status;
 mod msg;
 mod metrics;
+mod workers;
+mod monitors;
 
-pub use self::executor::Executor;
+pub use self::executor::{Executor, executor_pid, drain_effects, panic_description};
 pub use self::status::ExecutorStatus;
 pub use self::msg::ExecutorMsg;
 pub use self::metrics::ExecutorMetrics;
+pub use self::workers::Workers;
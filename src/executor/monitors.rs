@@ -0,0 +1,134 @@
+use std::collections::{BTreeMap, BTreeSet};
+use pid::Pid;
+use node_id::NodeId;
+
+/// The monitors and links of the processes owned by a single executor worker
+///
+/// Monitors are one-directional. A watcher is notified with a `Msg::Down` when the process it
+/// monitors exits. Links are bidirectional. When a linked process exits abnormally, the processes
+/// linked to it are stopped as well.
+///
+/// Ordered collections are used so that exit notifications are always sent in the same order.
+pub struct Monitors {
+    /// Local processes mapped to the pids monitoring them
+    watchers: BTreeMap<Pid, BTreeSet<Pid>>,
+
+    /// Remote processes mapped to the local pids monitoring them
+    remote: BTreeMap<Pid, BTreeSet<Pid>>,
+
+    /// Local processes mapped to the pids they are linked with
+    links: BTreeMap<Pid, BTreeSet<Pid>>,
+
+    /// Local processes mapped to the pids they monitor, so that their monitors can be removed when
+    /// they exit. This is kept by the worker owning the watcher, which may not be the worker
+    /// holding the monitor.
+    watching: BTreeMap<Pid, BTreeSet<Pid>>
+}
+
+impl Monitors {
+    pub fn new() -> Monitors {
+        Monitors {
+            watchers: BTreeMap::new(),
+            remote: BTreeMap::new(),
+            links: BTreeMap::new(),
+            watching: BTreeMap::new()
+        }
+    }
+
+    /// Record that `watcher` monitors `target`.
+    ///
+    /// Whether `target` is a local or a remote process is determined by `local`.
+    pub fn monitor(&mut self, local: &NodeId, target: Pid, watcher: Pid) {
+        let map = if target.node == *local { &mut self.watchers } else { &mut self.remote };
+        map.entry(target).or_insert_with(BTreeSet::new).insert(watcher);
+    }
+
+    pub fn demonitor(&mut self, local: &NodeId, target: &Pid, watcher: &Pid) {
+        let map = if target.node == *local { &mut self.watchers } else { &mut self.remote };
+        remove(map, target, watcher);
+    }
+
+    /// Record that `watcher`, a process owned by this worker, monitors `target`
+    pub fn watch(&mut self, watcher: Pid, target: Pid) {
+        self.watching.entry(watcher).or_insert_with(BTreeSet::new).insert(target);
+    }
+
+    pub fn unwatch(&mut self, watcher: &Pid, target: &Pid) {
+        remove(&mut self.watching, watcher, target);
+    }
+
+    /// Record that local process `pid` is linked with `peer`
+    pub fn link(&mut self, pid: Pid, peer: Pid) {
+        self.links.entry(pid).or_insert_with(BTreeSet::new).insert(peer);
+    }
+
+    pub fn unlink(&mut self, pid: &Pid, peer: &Pid) {
+        remove(&mut self.links, pid, peer);
+    }
+
+    /// Return true if local process `pid` is linked with `peer`
+    pub fn is_linked(&self, pid: &Pid, peer: &Pid) -> bool {
+        self.links.get(pid).map_or(false, |peers| peers.contains(peer))
+    }
+
+    /// Remove all state for a local process that exited.
+    ///
+    /// Return the pids monitoring the process, the pids linked with it and the pids it monitored.
+    /// The monitors held by the process are only removed from this worker, so the caller must
+    /// remove the ones held by other workers and nodes.
+    pub fn exit(&mut self, pid: &Pid) -> (BTreeSet<Pid>, BTreeSet<Pid>, BTreeSet<Pid>) {
+        let watchers = self.watchers.remove(pid).unwrap_or_else(BTreeSet::new);
+        let links = self.links.remove(pid).unwrap_or_else(BTreeSet::new);
+        let watched = self.watching.remove(pid).unwrap_or_else(BTreeSet::new);
+        for target in watched.iter() {
+            remove(&mut self.watchers, target, pid);
+            remove(&mut self.remote, target, pid);
+        }
+        (watchers, links, watched)
+    }
+
+    /// Remove all state related to processes on a node that left the cluster.
+    ///
+    /// Return a list of `(target, watcher)` pairs, where `watcher` is a local process monitoring
+    /// `target` on the departed node, and a list of `(pid, peer)` pairs, where `pid` is a local
+    /// process linked with `peer` on the departed node.
+    pub fn node_down(&mut self, node: &NodeId) -> (Vec<(Pid, Pid)>, Vec<(Pid, Pid)>) {
+        for watchers in self.watchers.values_mut() {
+            watchers.retain(|watcher| watcher.node != *node);
+        }
+        for targets in self.watching.values_mut() {
+            targets.retain(|target| target.node != *node);
+        }
+
+        let targets: Vec<Pid> = self.remote.keys().filter(|pid| pid.node == *node).cloned().collect();
+        let mut down = Vec::new();
+        for target in targets {
+            for watcher in self.remote.remove(&target).unwrap() {
+                down.push((target.clone(), watcher));
+            }
+        }
+
+        let mut linked = Vec::new();
+        for (pid, peers) in self.links.iter_mut() {
+            for peer in peers.iter().filter(|peer| peer.node == *node) {
+                linked.push((pid.clone(), peer.clone()));
+            }
+            peers.retain(|peer| peer.node != *node);
+        }
+        (down, linked)
+    }
+}
+
+/// Remove `val` from the set stored at `key`, and remove the set if it is empty
+fn remove(map: &mut BTreeMap<Pid, BTreeSet<Pid>>, key: &Pid, val: &Pid) {
+    let empty = match map.get_mut(key) {
+        Some(set) => {
+            set.remove(val);
+            set.is_empty()
+        },
+        None => false
+    };
+    if empty {
+        map.remove(key);
+    }
+}
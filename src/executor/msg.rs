@@ -3,15 +3,40 @@ use rustc_serialize::{Encodable, Decodable};
 use envelope::Envelope;
 use process::Process;
 use pid::Pid;
+use node_id::NodeId;
 use correlation_id::CorrelationId;
 use amy;
+use super::{ExecutorStatus, ExecutorMetrics};
 
 pub enum ExecutorMsg<T: Encodable + Decodable + Debug + Clone> {
     Start(Pid, Box<Process<Msg=T>>),
     Stop(Pid),
     Envelope(Envelope<T>),
+
+    /// An envelope sent with `Node::send_reliable`
+    ReliableEnvelope(Envelope<T>),
+
     RegisterService(Pid, amy::Sender<Envelope<T>>),
     GetStatus(CorrelationId),
     Shutdown,
-    Tick
+    Tick,
+
+    /// A node left the cluster. Sent to every worker by the cluster server.
+    NodeDown(NodeId),
+
+    /// `Monitor(target, watcher)` is sent to the worker that owns whichever of the two pids is
+    /// local. `Demonitor` removes the monitor.
+    Monitor(Pid, Pid),
+    Demonitor(Pid, Pid),
+
+    /// `Link(pid, peer)` is sent to the worker that owns `pid`. `Unlink` removes the link.
+    Link(Pid, Pid),
+    Unlink(Pid, Pid),
+
+    /// A partially aggregated status passed from one executor worker to the next
+    WorkerStatus(CorrelationId, ExecutorStatus),
+
+    /// Partially aggregated metrics passed from one executor worker to the next.
+    /// The last field is the number of workers that have yet to add their metrics.
+    WorkerMetrics(Pid, Option<CorrelationId>, ExecutorMetrics, usize)
 }
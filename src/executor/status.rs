@@ -2,6 +2,7 @@ use pid::Pid;
 
 #[derive(Debug, Clone, Eq, PartialEq, RustcEncodable, RustcDecodable)]
 pub struct ExecutorStatus {
+    pub workers: usize,
     pub total_processes: usize,
     pub services: Vec<Pid>,
     //... Some stats
@@ -0,0 +1,65 @@
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
+use std::sync::mpsc::{Sender, SendError};
+use rustc_serialize::{Encodable, Decodable};
+use pid::Pid;
+use envelope::Envelope;
+use super::ExecutorMsg;
+
+/// The senders for all executor worker threads on a single node.
+///
+/// Every process is pinned to exactly one worker by hashing its pid. All envelopes destined for a
+/// local pid are delivered through the channel of the worker that owns that pid, which preserves
+/// the order of messages sent between any two pids.
+#[derive(Clone)]
+pub struct Workers<T: Encodable + Decodable + Debug + Clone> {
+    senders: Vec<Sender<ExecutorMsg<T>>>
+}
+
+impl<T: Encodable + Decodable + Debug + Clone> Workers<T> {
+    pub fn new(senders: Vec<Sender<ExecutorMsg<T>>>) -> Workers<T> {
+        assert!(senders.len() > 0, "There must be at least one executor worker");
+        Workers {
+            senders: senders
+        }
+    }
+
+    /// Return the total number of workers
+    pub fn len(&self) -> usize {
+        self.senders.len()
+    }
+
+    /// Return the index of the worker that owns `pid`
+    pub fn index(&self, pid: &Pid) -> usize {
+        let mut hasher = DefaultHasher::new();
+        pid.hash(&mut hasher);
+        (hasher.finish() % self.senders.len() as u64) as usize
+    }
+
+    /// Send a message to the worker at `index`
+    pub fn send_to(&self, index: usize, msg: ExecutorMsg<T>) -> Result<(), SendError<ExecutorMsg<T>>> {
+        self.senders[index].send(msg)
+    }
+
+    /// Send a message to the worker that owns `pid`
+    pub fn send(&self, pid: &Pid, msg: ExecutorMsg<T>) -> Result<(), SendError<ExecutorMsg<T>>> {
+        self.send_to(self.index(pid), msg)
+    }
+
+    /// Send an envelope to the worker that owns the destination pid
+    pub fn send_envelope(&self, envelope: Envelope<T>) -> Result<(), SendError<ExecutorMsg<T>>> {
+        let index = self.index(&envelope.to);
+        self.send_to(index, ExecutorMsg::Envelope(envelope))
+    }
+
+    /// Send a message created by `f` to every worker
+    pub fn broadcast<F>(&self, f: F) -> Result<(), SendError<ExecutorMsg<T>>>
+        where F: Fn() -> ExecutorMsg<T>
+    {
+        for tx in &self.senders {
+            try!(tx.send(f()));
+        }
+        Ok(())
+    }
+}
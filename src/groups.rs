@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use orset::{ORSet, Delta};
+use node_id::NodeId;
+use pid::Pid;
+
+pub type Member = (String, Pid);
+
+/// Named groups of processes spanning the cluster
+///
+/// Group membership is replicated to all nodes in the cluster as an ORSet of `(group, pid)` pairs,
+/// in the same manner as `Members`.
+pub struct Groups {
+    orset: ORSet<Member>,
+
+    /// The number of sends to any member of each group, used to choose the next member
+    round_robin: HashMap<String, usize>
+}
+
+impl Groups {
+    pub fn new(node: NodeId) -> Groups {
+        Groups {
+            orset: ORSet::new(node.to_string()),
+            round_robin: HashMap::new()
+        }
+    }
+
+    /// Returns None if `pid` is already a member of `group`
+    pub fn join(&mut self, group: String, pid: Pid) -> Option<Delta<Member>> {
+        let member = (group, pid);
+        if self.orset.contains(&member) {
+            return None;
+        }
+        Some(self.orset.add(member))
+    }
+
+    /// Returns None if `pid` isn't a member of `group`
+    pub fn leave(&mut self, group: String, pid: Pid) -> Option<Delta<Member>> {
+        let member = (group, pid);
+        if let Some(dots) = self.orset.seen(&member) {
+            return Some(self.orset.remove(member, dots));
+        }
+        None
+    }
+
+    /// Remove `pid` from all groups
+    pub fn remove_pid(&mut self, pid: &Pid) -> Vec<Delta<Member>> {
+        self.remove_where(|member| member.1 == *pid)
+    }
+
+    /// Remove all pids on `node` from all groups
+    pub fn remove_node(&mut self, node: &NodeId) -> Vec<Delta<Member>> {
+        self.remove_where(|member| member.1.node == *node)
+    }
+
+    fn remove_where<F>(&mut self, f: F) -> Vec<Delta<Member>> where F: Fn(&Member) -> bool {
+        let mut members: Vec<Member> = self.orset.elements().into_iter().filter(|m| f(m)).collect();
+        members.sort();
+        members.into_iter().filter_map(|(group, pid)| self.leave(group, pid)).collect()
+    }
+
+    /// Return the members of `group` in sorted order
+    pub fn members(&self, group: &str) -> Vec<Pid> {
+        let mut pids: Vec<Pid> = self.orset.elements().into_iter()
+            .filter(|member| member.0 == group)
+            .map(|member| member.1)
+            .collect();
+        pids.sort();
+        pids
+    }
+
+    /// Choose a member of `group` in round robin order
+    pub fn any(&mut self, group: &str) -> Option<Pid> {
+        let mut members = self.members(group);
+        if members.is_empty() {
+            return None;
+        }
+        let count = self.round_robin.entry(group.to_string()).or_insert(0);
+        let index = *count % members.len();
+        *count += 1;
+        Some(members.swap_remove(index))
+    }
+
+    pub fn join_state(&mut self, other: ORSet<Member>) {
+        self.orset.join_state(other);
+    }
+
+    pub fn join_delta(&mut self, delta: Delta<Member>) -> bool {
+        self.orset.join(delta)
+    }
+
+    pub fn get_orset(&self) -> ORSet<Member> {
+        self.orset.clone()
+    }
+}
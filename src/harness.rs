@@ -0,0 +1,295 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Debug;
+use rustc_serialize::{Encodable, Decodable};
+use node_id::NodeId;
+use pid::Pid;
+use process::Process;
+use effect::Effect;
+use envelope::Envelope;
+use msg::Msg;
+use correlation_id::CorrelationId;
+use executor::{executor_pid, drain_effects};
+
+/// Drive processes directly in unit tests, without starting a node
+///
+/// The harness stands in for the executor. Envelopes sent to a process under test are queued and
+/// only delivered when the test calls `step`, `run` or `advance`, so the test controls exactly
+/// when each process runs. Envelopes sent by the processes to each other are queued the same way,
+/// and every envelope a process sends is recorded so that the test can make assertions on it.
+///
+/// Timers started with `Msg::StartTimer` run on a clock that only moves when the test calls
+/// `advance`, and fire exactly when they are due. Processes started or stopped with
+/// `Effect::Spawn` and `Effect::Stop` are added to or removed from the harness. All other
+/// requests to the executor, such as monitors and links, are only recorded.
+///
+/// ```
+/// # use rabble::{ProcessHarness, Process, Pid, NodeId, Envelope, Msg, CorrelationId};
+/// // Replies to every message with the same message
+/// struct Echo {
+///     pid: Pid,
+///     output: Vec<Envelope<u64>>
+/// }
+///
+/// impl Process for Echo {
+///     type Msg = u64;
+///
+///     fn handle(&mut self, msg: Msg<u64>, from: Pid, _: Option<CorrelationId>)
+///         -> &mut Vec<Envelope<u64>>
+///     {
+///         self.output.push(Envelope::new(from, self.pid.clone(), msg, None));
+///         &mut self.output
+///     }
+/// }
+///
+/// let node = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11000".to_string()};
+/// let echo = Pid {name: "echo".to_string(), group: None, node: node.clone()};
+/// let test = Pid {name: "test".to_string(), group: None, node: node.clone()};
+///
+/// let mut harness = ProcessHarness::new(node);
+/// harness.spawn(echo.clone(), Box::new(Echo {pid: echo.clone(), output: Vec::new()}));
+/// harness.send(Envelope::new(echo, test.clone(), Msg::User(1), None));
+/// harness.run();
+/// harness.assert_sent(&test, &Msg::User(1));
+/// ```
+pub struct ProcessHarness<T: Encodable + Decodable + Debug + Clone> {
+    executor_pid: Pid,
+    processes: BTreeMap<Pid, Box<Process<Msg=T>>>,
+
+    /// Envelopes waiting to be delivered to processes under test, in the order they were sent
+    pending: VecDeque<Envelope<T>>,
+
+    /// Every envelope sent by the processes under test, in the order they were sent
+    sent: Vec<Envelope<T>>,
+
+    /// Running timers ordered by the time they are due, and by the order they were started in
+    timers: BTreeMap<(usize, u64), (Pid, Option<CorrelationId>)>,
+    next_timer: u64,
+    names: BTreeMap<String, Pid>,
+    now: usize
+}
+
+impl<T: Encodable + Decodable + Debug + Clone> ProcessHarness<T> {
+    /// Create a harness standing in for the executor of `node`
+    pub fn new(node: NodeId) -> ProcessHarness<T> {
+        ProcessHarness {
+            executor_pid: executor_pid(node),
+            processes: BTreeMap::new(),
+            pending: VecDeque::new(),
+            sent: Vec::new(),
+            timers: BTreeMap::new(),
+            next_timer: 0,
+            names: BTreeMap::new(),
+            now: 0
+        }
+    }
+
+    /// Return the pid of the executor the harness stands in for, which is passed to
+    /// `Process::init`
+    pub fn executor_pid(&self) -> &Pid {
+        &self.executor_pid
+    }
+
+    /// Add a process to the harness and call its `init` callback
+    pub fn spawn(&mut self, pid: Pid, mut process: Box<Process<Msg=T>>) {
+        let envelopes = process.init(self.executor_pid.clone());
+        let effects = drain_effects(&mut *process);
+        self.processes.insert(pid, process);
+        self.perform_effects(effects);
+        for envelope in envelopes {
+            self.route(envelope);
+        }
+    }
+
+    /// Return true if the process with `pid` is under test and wasn't stopped
+    pub fn is_running(&self, pid: &Pid) -> bool {
+        self.processes.contains_key(pid)
+    }
+
+    /// Queue an envelope for delivery to a process under test
+    pub fn send(&mut self, envelope: Envelope<T>) {
+        self.pending.push_back(envelope);
+    }
+
+    /// Return the envelopes waiting to be delivered, in the order they will be delivered
+    ///
+    /// Tests can drop, reorder or duplicate them to check how the processes cope with an
+    /// unreliable network.
+    pub fn pending(&mut self) -> &mut VecDeque<Envelope<T>> {
+        &mut self.pending
+    }
+
+    /// Deliver the next pending envelope. Return false if nothing was pending.
+    ///
+    /// Envelopes to pids that aren't processes under test are dropped.
+    pub fn step(&mut self) -> bool {
+        let envelope = match self.pending.pop_front() {
+            Some(envelope) => envelope,
+            None => return false
+        };
+        let Envelope {to, from, msg, correlation_id, ..} = envelope;
+        let (envelopes, effects) = match self.processes.get_mut(&to) {
+            Some(process) => {
+                let envelopes: Vec<_> =
+                    process.handle(msg, from, correlation_id).drain(..).collect();
+                (envelopes, drain_effects(&mut **process))
+            },
+            None => return true
+        };
+        self.perform_effects(effects);
+        for envelope in envelopes {
+            self.route(envelope);
+        }
+        true
+    }
+
+    /// Deliver pending envelopes until there are none left, including any sent while handling
+    /// them. Return the number of envelopes delivered.
+    ///
+    /// This never returns if the processes keep sending each other envelopes.
+    pub fn run(&mut self) -> usize {
+        let mut delivered = 0;
+        while self.step() {
+            delivered += 1;
+        }
+        delivered
+    }
+
+    /// Move the clock forward by `ms`, delivering pending envelopes, and firing timers in the order
+    /// they are due
+    ///
+    /// Each timer's `Msg::Timeout` is delivered at the time the timer is due, after all envelopes
+    /// sent before then, so timers started while handling a timeout fire during the same call if
+    /// they are due before the clock reaches its new time.
+    pub fn advance(&mut self, ms: usize) {
+        let end = self.now + ms;
+        self.run();
+        while let Some(&(due, seq)) = self.timers.keys().next() {
+            if due > end {
+                break;
+            }
+            let (pid, correlation_id) = self.timers.remove(&(due, seq)).unwrap();
+            self.now = due;
+            let envelope = Envelope::new(pid, self.executor_pid.clone(), Msg::Timeout,
+                                         correlation_id);
+            self.pending.push_back(envelope);
+            self.run();
+        }
+        self.now = end;
+    }
+
+    /// Return the number of ms the clock was advanced since the harness was created
+    pub fn now(&self) -> usize {
+        self.now
+    }
+
+    /// Return the pid and correlation id of each running timer, along with the time it is due, in
+    /// the order the timers will fire
+    pub fn timers(&self) -> Vec<(Pid, Option<CorrelationId>, usize)> {
+        self.timers.iter().map(|(&(due, _), &(ref pid, ref correlation_id))| {
+            (pid.clone(), correlation_id.clone(), due)
+        }).collect()
+    }
+
+    /// Return the pid registered under `name` with `Effect::Register`
+    pub fn whereis(&self, name: &str) -> Option<&Pid> {
+        self.names.get(name)
+    }
+
+    /// Return all envelopes sent by the processes under test, in the order they were sent
+    ///
+    /// This includes envelopes sent to the executor, such as timer requests, and envelopes sent
+    /// between the processes under test.
+    pub fn sent(&self) -> &[Envelope<T>] {
+        &self.sent
+    }
+
+    /// Return the messages sent to `pid`, in the order they were sent
+    pub fn sent_to(&self, pid: &Pid) -> Vec<&Msg<T>> {
+        self.sent.iter().filter(|envelope| envelope.to == *pid).map(|e| &e.msg).collect()
+    }
+
+    /// Remove and return all envelopes sent so far, so that later assertions only see envelopes
+    /// sent afterwards
+    pub fn take_sent(&mut self) -> Vec<Envelope<T>> {
+        self.sent.drain(..).collect()
+    }
+
+    /// Record an envelope sent by a process, and queue it if it's addressed to a process under
+    /// test or handle it if it's a timer request
+    fn route(&mut self, envelope: Envelope<T>) {
+        self.sent.push(envelope.clone());
+        if envelope.to == self.executor_pid {
+            let Envelope {from, msg, correlation_id, ..} = envelope;
+            match msg {
+                Msg::StartTimer(ms) => {
+                    let due = self.now + ms;
+                    self.timers.insert((due, self.next_timer), (from, correlation_id));
+                    self.next_timer += 1;
+                },
+                Msg::CancelTimer(correlation_id) => {
+                    // Like the executor, cancel all timers the process started with this id
+                    let key = (from, correlation_id);
+                    let cancelled: Vec<_> = self.timers.iter()
+                        .filter(|&(_, timer)| *timer == key)
+                        .map(|(due_seq, _)| *due_seq)
+                        .collect();
+                    for due_seq in cancelled {
+                        self.timers.remove(&due_seq);
+                    }
+                },
+                _ => ()
+            }
+        } else if self.processes.contains_key(&envelope.to) {
+            self.pending.push_back(envelope);
+        }
+    }
+
+    fn perform_effects(&mut self, effects: Vec<Effect<T>>) {
+        for effect in effects {
+            match effect {
+                Effect::Spawn(pid, process) => self.spawn(pid, process),
+                Effect::Stop(pid) => {
+                    self.processes.remove(&pid);
+                    self.names.retain(|_, registered| *registered != pid);
+                },
+                Effect::Register(name, pid) => {
+                    if self.processes.contains_key(&pid) && !self.names.contains_key(&name) {
+                        self.names.insert(name, pid);
+                    }
+                },
+                Effect::Unregister(name, pid) => {
+                    if self.names.get(&name) == Some(&pid) {
+                        self.names.remove(&name);
+                    }
+                },
+                // There is no network to lose the envelope
+                Effect::SendReliable(envelope) => self.route(envelope)
+            }
+        }
+    }
+}
+
+impl<T: Encodable + Decodable + Debug + Clone + PartialEq> ProcessHarness<T> {
+    /// Panic unless `msg` was sent to `to`
+    pub fn assert_sent(&self, to: &Pid, msg: &Msg<T>) {
+        if !self.sent_to(to).contains(&msg) {
+            panic!("{:?} was not sent to {}. Sent envelopes: {:#?}", msg, to, self.sent);
+        }
+    }
+
+    /// Panic if `msg` was sent to `to`
+    pub fn assert_not_sent(&self, to: &Pid, msg: &Msg<T>) {
+        if self.sent_to(to).contains(&msg) {
+            panic!("{:?} was sent to {}", msg, to);
+        }
+    }
+
+    /// Panic unless exactly `msgs` were sent to `to`, in order
+    pub fn assert_sent_exactly(&self, to: &Pid, msgs: &[Msg<T>]) {
+        let sent = self.sent_to(to);
+        let expected: Vec<&Msg<T>> = msgs.iter().collect();
+        if sent != expected {
+            panic!("Expected {:?} to be sent to {}, but {:?} were sent", msgs, to, sent);
+        }
+    }
+}
@@ -12,6 +12,10 @@ extern crate time;
 extern crate net2;
 extern crate libc;
 extern crate ferris;
+extern crate ring;
+extern crate rustls;
+extern crate rustls_pemfile;
+extern crate webpki;
 //extern crate hdrsample;
 
 #[macro_use]
@@ -23,9 +27,14 @@ mod metrics;
 
 mod node_id;
 mod node;
+mod node_builder;
 mod members;
+mod registry;
+mod groups;
 mod pid;
 mod process;
+mod effect;
+mod process_spec;
 mod envelope;
 mod executor;
 mod cluster;
@@ -33,29 +42,60 @@ mod msg;
 mod timer_wheel;
 mod service;
 mod correlation_id;
+mod reason;
+mod delivery_failure;
+mod supervisor;
 mod serialize;
+mod config;
+mod tls;
+mod transport;
+mod clock;
+mod rng;
+mod harness;
+mod simulation;
 
 pub mod errors;
 
 pub use errors::Result;
+pub use config::RabbleConfig;
+pub use tls::TlsConfig;
+pub use transport::{Transport, Stream, TcpTransport, MemoryNetwork, MemoryTransport};
 pub use node_id::NodeId;
 pub use node::Node;
+pub use node_builder::{NodeBuilder, NodeHandle};
+pub use simulation::Simulation;
+pub use harness::ProcessHarness;
 pub use pid::Pid;
 pub use process::Process;
+pub use effect::Effect;
+pub use process_spec::{ProcessSpec, Factory};
 pub use envelope::Envelope;
 pub use correlation_id::CorrelationId;
 pub use msg::Msg;
+pub use reason::Reason;
+pub use delivery_failure::DeliveryFailure;
+pub use supervisor::{
+    Supervisor,
+    ChildSpec,
+    Strategy
+};
 pub use metrics::Metric;
 
 pub use cluster::{
     ClusterServer,
     ClusterStatus,
+    ClusterEvent,
+    FailureDetectorConfig
 };
 
+#[cfg(feature = "fault-injection")]
+pub use cluster::LinkFaults;
+
 pub use executor::{
     Executor,
     ExecutorStatus,
-    ExecutorMetrics
+    ExecutorMetrics,
+    Workers
 };
 
 pub use service::{
@@ -73,60 +113,34 @@ pub use serialize::{
     ProtobufSerializer
 };
 
-use std::thread::{self, JoinHandle};
-use std::sync::mpsc::channel;
+use std::thread::JoinHandle;
 use std::fmt::Debug;
 use rustc_serialize::{Encodable, Decodable};
-use amy::Poller;
-use slog::DrainExt;
-use cluster::ClusterMsg;
-
-const TIMEOUT: usize = 5000; // ms
 
 /// Start a node in the rabble cluster and return it along with the handles to all threads started
 /// by rabble.
 ///
 /// All nodes in a cluster must be parameterized by the same type.
+///
+/// The default config always passes validation, so this only panics if the node fails to start,
+/// such as when its address can't be bound. Use `NodeBuilder` or `rouse_with_config` to handle
+/// startup errors instead.
 pub fn rouse<T>(node_id: NodeId, logger: Option<slog::Logger>) -> (Node<T>, Vec<JoinHandle<()>>)
   where T: Encodable + Decodable + Send + 'static + Clone + Debug,
 {
-    let logger = match logger {
-        Some(logger) => logger.new(o!("node_id" => node_id.to_string())),
-        None => slog::Logger::root(slog_stdlog::StdLog.fuse(), o!("node_id" => node_id.to_string()))
-    };
-
-    let mut poller = Poller::new().unwrap();
-    let (exec_tx, exec_rx) = channel();
-    let (cluster_tx, cluster_rx) = channel();
-    let cluster_server = ClusterServer::new(node_id.clone(),
-                                            cluster_rx,
-                                            exec_tx.clone(),
-                                            poller.get_registrar(),
-                                            logger.clone());
-    let executor = Executor::new(node_id.clone(),
-                                 exec_tx.clone(),
-                                 exec_rx,
-                                 cluster_tx.clone(),
-                                 logger.clone());
-
-    let h1 = thread::Builder::new().name(format!("cluster_server::{}", node_id)).spawn(move || {
-        cluster_server.run()
-    }).unwrap();
-
-    let h2 = thread::Builder::new().name(format!("executor::{}", node_id)).spawn(move || {
-        executor.run()
-    }).unwrap();
-
-    let _cluster_tx = cluster_tx.clone();
-    let h3 = thread::Builder::new().name(format!("poller::{}", node_id)).spawn(move || {
-        loop {
-            let notifications = poller.wait(TIMEOUT).unwrap();
-            if let Err(_) = _cluster_tx.send(ClusterMsg::PollNotifications(notifications)) {
-                // The process is exiting
-                return;
-            }
-        }
-    }).unwrap();
+    rouse_with_config(node_id, RabbleConfig::default(), logger).unwrap()
+}
 
-    (Node::new(node_id, exec_tx, cluster_tx, logger), vec![h1, h2, h3])
+/// Start a node in the rabble cluster using the given config and return it along with the handles
+/// to all threads started by rabble.
+///
+/// The config is validated before anything is started, and an `ErrorKind::InvalidConfig` is
+/// returned if it is invalid. Other startup failures are returned as errors as well.
+pub fn rouse_with_config<T>(node_id: NodeId,
+                            config: RabbleConfig,
+                            logger: Option<slog::Logger>)
+    -> Result<(Node<T>, Vec<JoinHandle<()>>)>
+  where T: Encodable + Decodable + Send + 'static + Clone + Debug,
+{
+    node_builder::start(node_id, config, Box::new(TcpTransport::new()), logger)
 }
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 use std::fmt::{Display, Formatter, Error};
 use orset::{ORSet, Delta};
 use node_id::NodeId;
@@ -30,7 +30,8 @@ impl Members {
         }
     }
 
-    pub fn all(&self) -> HashSet<NodeId> {
+    /// Return all members in sorted order
+    pub fn all(&self) -> BTreeSet<NodeId> {
         self.orset.elements().into_iter().collect()
     }
 
@@ -1,9 +1,13 @@
 use std::fmt::Debug;
 use rustc_serialize::{Encodable, Decodable};
-use cluster::ClusterStatus;
+use cluster::{ClusterStatus, ClusterEvent};
 use executor::ExecutorStatus;
 use correlation_id::CorrelationId;
 use metrics::Metric;
+use pid::Pid;
+use reason::Reason;
+use delivery_failure::DeliveryFailure;
+use envelope::Envelope;
 
 type Name = String;
 
@@ -17,5 +21,68 @@ pub enum Msg<T: Encodable + Decodable + Debug + Clone> {
     Timeout,
     Shutdown,
     GetMetrics,
-    Metrics(Vec<(Name, Metric)>)
+    Metrics(Vec<(Name, Metric)>),
+
+    // Requests sent by a process to its executor to be notified when another process exits
+    Monitor(Pid),
+    Demonitor(Pid),
+    Link(Pid),
+    Unlink(Pid),
+
+    /// Sent by the executor to a monitoring process when the monitored process exits
+    Down(Pid, Reason),
+
+    /// An exit signal sent to a linked process. It is handled by the executor and never delivered
+    /// to the process itself. Exit signals from pids the target isn't linked with are dropped.
+    Exit(Pid, Reason),
+
+    /// Sent to the cluster server to look up a pid in the cluster wide registry
+    WhereIsName(Name),
+
+    /// The reply to a `WhereIsName` request
+    NamedPid(Name, Option<Pid>),
+
+    /// Replies to `Node::spawn_remote`. `Spawned` only means the process was queued on its
+    /// executor; its `init` may not have run yet.
+    Spawned(Pid),
+    SpawnFailed(Pid, String),
+
+    // Requests sent to the cluster server to register or unregister a name for the sending pid in
+    // the cluster wide registry
+    RegisterName(Name),
+    UnregisterName(Name),
+
+    /// Sent to the cluster server to forward a message to the pid registered under a name
+    SendNamed(Name, Box<Msg<T>>),
+
+    // Requests sent to the cluster server to add or remove the sending pid from a process group
+    JoinGroup(Name),
+    LeaveGroup(Name),
+
+    /// Sent to the cluster server to forward a message to every member of a group
+    Multicast(Name, Box<Msg<T>>),
+
+    /// Sent to the cluster server to forward a message to one member of a group, chosen in round
+    /// robin order
+    SendAny(Name, Box<Msg<T>>),
+
+    /// Sent to the cluster server to get the members of a group. The reply is a `GroupMembers`.
+    GetGroupMembers(Name),
+    GroupMembers(Name, Vec<Pid>),
+
+    // Requests sent to the cluster server to start or stop publishing cluster events to the
+    // sending pid
+    SubscribeClusterEvents,
+    UnsubscribeClusterEvents,
+
+    /// Sent by the cluster server to subscribers when the cluster changes
+    ClusterEvent(ClusterEvent),
+
+    /// Sent to the sender of an envelope that could not be delivered to the given pid, if
+    /// `RabbleConfig::notify_undeliverable` is set and the envelope had a correlation id
+    Undeliverable(Pid, DeliveryFailure),
+
+    /// Sent to the dead letter pid configured with `RabbleConfig::dead_letters` for every envelope
+    /// that could not be delivered. The envelope is `None` if it could not be decoded.
+    DeadLetter(Option<Box<Envelope<T>>>, DeliveryFailure)
 }
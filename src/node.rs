@@ -1,20 +1,27 @@
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::fmt::Debug;
 use rustc_serialize::{Encodable, Decodable};
 use node_id::NodeId;
-use executor::ExecutorMsg;
-use cluster::ClusterMsg;
+use executor::{ExecutorMsg, Workers};
+use cluster::{ClusterMsg, cluster_server_pid};
+#[cfg(feature = "fault-injection")]
+use cluster::LinkFaults;
 use pid::Pid;
 use correlation_id::CorrelationId;
 use process::Process;
+use process_spec::ProcessSpec;
 use envelope::Envelope;
+use msg::Msg;
 use amy;
 use errors::*;
 use slog;
+use config::RabbleConfig;
 
 macro_rules! send {
-    ($s:ident.$t:ident, $msg:expr, $pid:expr, $errmsg:expr) => {
-        if let Err(_) = $s.$t.send($msg) {
+    ($send:expr, $pid:expr, $errmsg:expr) => {
+        if let Err(_) = $send {
             return Err(ErrorKind::SendError($errmsg, $pid.cloned()).into())
         } else {
             return Ok(());
@@ -30,22 +37,27 @@ macro_rules! send {
 pub struct Node<T: Encodable + Decodable + Debug + Clone> {
     pub id: NodeId,
     pub logger: slog::Logger,
-    executor_tx: Sender<ExecutorMsg<T>>,
-    cluster_tx: Sender<ClusterMsg<T>>
+    pub config: RabbleConfig,
+    executors: Workers<T>,
+    cluster_tx: Sender<ClusterMsg<T>>,
+    stopping: Arc<AtomicBool>
 }
 
 impl<T: Encodable + Decodable + Debug + Clone> Node<T> {
-    /// Create a new node. This function should not be called by the user directly. It is called by
-    /// by the user call to `rabble::rouse(..)` that initializes a rabble system for a single node.
+    /// Create a new node. This function should not be called by the user directly. It is called
+    /// when starting a node with `rabble::NodeBuilder` or `rabble::rouse(..)`.
     pub fn new(id: NodeId,
-               executor_tx: Sender<ExecutorMsg<T>>,
+               executors: Workers<T>,
                cluster_tx: Sender<ClusterMsg<T>>,
+               config: RabbleConfig,
                logger: slog::Logger) -> Node<T> {
         Node {
             id: id,
-            executor_tx: executor_tx,
+            config: config,
+            executors: executors,
             cluster_tx: cluster_tx,
-            logger: logger
+            logger: logger,
+            stopping: Arc::new(AtomicBool::new(false))
         }
     }
 
@@ -58,75 +70,247 @@ impl<T: Encodable + Decodable + Debug + Clone> Node<T> {
     /// continuously try to connect to the remote node so that they can exchange membership
     /// information and participate in peer operations.
     pub fn join(&self, node_id: &NodeId) -> Result<()> {
-        send!(self.cluster_tx,
-              ClusterMsg::Join(node_id.clone()),
+        try!(self.check_running());
+        send!(self.cluster_tx.send(ClusterMsg::Join(node_id.clone())),
               None,
               format!("ClusterMsg::Join({:?})", *node_id))
     }
 
     pub fn leave(&self, node_id: &NodeId) -> Result<()> {
-        send!(self.cluster_tx,
-              ClusterMsg::Leave(node_id.clone()),
+        try!(self.check_running());
+        send!(self.cluster_tx.send(ClusterMsg::Leave(node_id.clone())),
               None,
               format!("ClusterMsg::Leave({:?})", *node_id))
     }
 
-    /// Add a process to the executor that can be sent Envelopes addressed to its pid
+    /// Add a process to the executor worker that owns `pid`, so that it can be sent Envelopes
+    /// addressed to its pid
     pub fn spawn(&self, pid: &Pid, process: Box<Process<Msg=T>>) -> Result<()> {
-        send!(self.executor_tx,
-              ExecutorMsg::Start(pid.clone(), process),
+        try!(self.check_running());
+        send!(self.executors.send(pid, ExecutorMsg::Start(pid.clone(), process)),
               Some(pid),
               format!("ExecutorMsg::Start({}, ..)", pid))
     }
 
     /// Remove a process from the executor
     pub fn stop(&self, pid: &Pid) -> Result<()> {
-        send!(self.executor_tx,
-              ExecutorMsg::Stop(pid.clone()),
+        try!(self.check_running());
+        send!(self.executors.send(pid, ExecutorMsg::Stop(pid.clone())),
               Some(pid),
-              format!("ExecutorMsg::Start({}, ..)", pid))
+              format!("ExecutorMsg::Stop({})", pid))
+    }
+
+    /// Register a named factory used to start processes on this node from other nodes
+    ///
+    /// See `Node::spawn_remote`.
+    pub fn register_factory<F>(&self, name: &str, factory: F) -> Result<()>
+        where F: Fn(Pid, Option<T>) -> Box<Process<Msg=T>> + Send + 'static
+    {
+        try!(self.check_running());
+        let msg = ClusterMsg::RegisterFactory(name.to_string(), Box::new(factory));
+        send!(self.cluster_tx.send(msg),
+              None,
+              format!("ClusterMsg::RegisterFactory({}, ..)", name))
+    }
+
+    /// Start a process on the node in `pid`, using a factory registered on that node.
+    ///
+    /// The cluster server on the remote node replies to `correlation_id.pid` with either a
+    /// `Msg::Spawned(pid)` or a `Msg::SpawnFailed(pid, reason)`. `Msg::Spawned` is sent once the
+    /// process is handed to its executor, before its `init` runs. Monitor the pid to learn if
+    /// `init` panics.
+    pub fn spawn_remote(&self,
+                        pid: &Pid,
+                        spec: ProcessSpec<T>,
+                        correlation_id: CorrelationId) -> Result<()>
+    {
+        try!(self.check_running());
+        send!(self.cluster_tx.send(ClusterMsg::Spawn(pid.clone(), spec, correlation_id)),
+              Some(pid),
+              format!("ClusterMsg::Spawn({}, ..)", pid))
+    }
+
+    /// Inject faults into the link between this node and `peer`, replacing any faults set before.
+    /// This is only meant for testing, and requires the `fault-injection` feature.
+    ///
+    /// Faults only take effect on this node. A partition set on one node stops it from connecting
+    /// to the peer, but the peer may still connect and briefly establish a connection before this
+    /// node finds out who it is and closes it. Partition both nodes from each other to split them
+    /// cleanly.
+    #[cfg(feature = "fault-injection")]
+    pub fn set_faults(&self, peer: &NodeId, faults: LinkFaults) -> Result<()> {
+        try!(self.check_running());
+        send!(self.cluster_tx.send(ClusterMsg::SetFaults(peer.clone(), faults)),
+              None,
+              format!("ClusterMsg::SetFaults({}, ..)", peer))
     }
 
-    /// Register a Service's sender with the executor so that it can be sent messages addressed to
-    /// its pid
+    /// Remove all faults from the link between this node and `peer`
+    #[cfg(feature = "fault-injection")]
+    pub fn clear_faults(&self, peer: &NodeId) -> Result<()> {
+        self.set_faults(peer, LinkFaults::new())
+    }
+
+    /// Register a Service's sender with all executor workers so that it can be sent messages
+    /// addressed to its pid
     pub fn register_service(&self, pid: &Pid, tx: &amy::Sender<Envelope<T>>) -> Result<()>
     {
-        send!(self.executor_tx,
-              ExecutorMsg::RegisterService(pid.clone(), tx.clone()),
+        try!(self.check_running());
+        send!(self.executors.broadcast(|| ExecutorMsg::RegisterService(pid.clone(), tx.clone())),
               Some(pid),
               format!("ExecutorMsg::RegisterService({}, ..)", pid))
     }
 
     /// Send an envelope to the executor so it gets routed to the appropriate process or service
+    ///
+    /// Returns an `ErrorKind::NodeShutdown` once the node has started shutting down.
     pub fn send(&self, envelope: Envelope<T>) -> Result<()> {
+        try!(self.check_running());
         let to = envelope.to.clone();
-        send!(self.executor_tx,
-              ExecutorMsg::Envelope(envelope),
+        send!(self.executors.send_envelope(envelope),
               Some(&to),
               "ExecutorMsg::Envelope(envelope)".to_string())
     }
 
-    /// Get the status of the executor
+    /// Send an envelope like `send`, but with at-least-once delivery if it's addressed to a process
+    /// on another node
+    ///
+    /// The cluster server retains the envelope until the peer acknowledges it, and resends it
+    /// after reconnecting if necessary.
+    pub fn send_reliable(&self, envelope: Envelope<T>) -> Result<()> {
+        try!(self.check_running());
+        let to = envelope.to.clone();
+        send!(self.executors.send(&to, ExecutorMsg::ReliableEnvelope(envelope)),
+              Some(&to),
+              "ExecutorMsg::ReliableEnvelope(envelope)".to_string())
+    }
+
+    /// Get the status of the executor, aggregated over all of its workers
     pub fn executor_status(&self, correlation_id: CorrelationId) -> Result<()> {
+        try!(self.check_running());
         let to = correlation_id.pid.clone();
-        send!(self.executor_tx,
-              ExecutorMsg::GetStatus(correlation_id),
+        send!(self.executors.send_to(0, ExecutorMsg::GetStatus(correlation_id)),
               Some(&to),
               "ExecutorMsg::GetStatus".to_string())
     }
 
+    /// Register a name for a local pid in the cluster wide registry
+    ///
+    /// The name is removed when the process exits, or when its node leaves the cluster. The
+    /// registration is ignored if the name is already registered for another pid.
+    pub fn register_name(&self, name: &str, pid: &Pid) -> Result<()> {
+        self.send_to_cluster_server(pid.clone(), Msg::RegisterName(name.to_string()), None)
+    }
+
+    pub fn unregister_name(&self, name: &str, pid: &Pid) -> Result<()> {
+        self.send_to_cluster_server(pid.clone(), Msg::UnregisterName(name.to_string()), None)
+    }
+
+    /// Send a message to the pid registered for `name` in the cluster wide registry
+    ///
+    /// The message is dropped if no pid is registered for `name`.
+    pub fn send_named(&self,
+                      name: &str,
+                      from: Pid,
+                      msg: Msg<T>,
+                      correlation_id: Option<CorrelationId>) -> Result<()>
+    {
+        let msg = Msg::SendNamed(name.to_string(), Box::new(msg));
+        self.send_to_cluster_server(from, msg, correlation_id)
+    }
+
+    /// Add a local pid to a process group spanning the cluster
+    ///
+    /// Process groups are unrelated to the `group` field of a `Pid`, which is only a namespace that
+    /// is part of the pid's identity. A pid can join and leave process groups at any time without
+    /// changing its identity, and can be a member of many groups at once.
+    ///
+    /// The pid is removed from the group when the process exits, or when its node leaves the
+    /// cluster.
+    pub fn join_group(&self, group: &str, pid: &Pid) -> Result<()> {
+        self.send_to_cluster_server(pid.clone(), Msg::JoinGroup(group.to_string()), None)
+    }
+
+    pub fn leave_group(&self, group: &str, pid: &Pid) -> Result<()> {
+        self.send_to_cluster_server(pid.clone(), Msg::LeaveGroup(group.to_string()), None)
+    }
+
+    /// Send a message to every member of a process group
+    pub fn multicast(&self,
+                     group: &str,
+                     from: Pid,
+                     msg: Msg<T>,
+                     correlation_id: Option<CorrelationId>) -> Result<()>
+    {
+        let msg = Msg::Multicast(group.to_string(), Box::new(msg));
+        self.send_to_cluster_server(from, msg, correlation_id)
+    }
+
+    /// Send a message to one member of a process group, chosen in round robin order
+    pub fn send_any(&self,
+                    group: &str,
+                    from: Pid,
+                    msg: Msg<T>,
+                    correlation_id: Option<CorrelationId>) -> Result<()>
+    {
+        let msg = Msg::SendAny(group.to_string(), Box::new(msg));
+        self.send_to_cluster_server(from, msg, correlation_id)
+    }
+
+    /// Publish all future `ClusterEvent`s to `pid` as `Msg::ClusterEvent`s
+    pub fn subscribe_cluster_events(&self, pid: &Pid) -> Result<()> {
+        self.send_to_cluster_server(pid.clone(), Msg::SubscribeClusterEvents, None)
+    }
+
+    pub fn unsubscribe_cluster_events(&self, pid: &Pid) -> Result<()> {
+        self.send_to_cluster_server(pid.clone(), Msg::UnsubscribeClusterEvents, None)
+    }
+
+    fn send_to_cluster_server(&self,
+                              from: Pid,
+                              msg: Msg<T>,
+                              correlation_id: Option<CorrelationId>) -> Result<()>
+    {
+        try!(self.check_running());
+        let to = cluster_server_pid(self.id.clone());
+        let envelope = Envelope::new(to.clone(), from, msg, correlation_id);
+        match self.cluster_tx.send(ClusterMsg::Envelope(envelope)) {
+            // Only format the message on failure, since every send to a name or group goes here
+            Err(mpsc::SendError(ClusterMsg::Envelope(envelope))) => {
+                Err(ErrorKind::SendError(format!("{:?}", envelope.msg), Some(to)).into())
+            },
+            _ => Ok(())
+        }
+    }
+
     /// Get the status of the cluster server
     pub fn cluster_status(&self, correlation_id: CorrelationId) -> Result<()> {
+        try!(self.check_running());
         let to = correlation_id.pid.clone();
-        send!(self.cluster_tx,
-              ClusterMsg::GetStatus(correlation_id),
+        send!(self.cluster_tx.send(ClusterMsg::GetStatus(correlation_id)),
               Some(&to),
               "ClusterMsg::GetStatus".to_string())
     }
 
-    /// Shutdown the node
+    /// Gracefully shutdown the node
+    ///
+    /// The node stops accepting envelopes and other requests immediately, and they return an
+    /// `ErrorKind::NodeShutdown`. The cluster server then stops the executor
+    /// workers, which tell all registered services to shutdown, flushes messages to other nodes for
+    /// up to `RabbleConfig::shutdown_timeout` ms, and tells the other nodes that this node is
+    /// shutting down. All threads exit afterwards. This call does not wait for the threads to exit.
     pub fn shutdown(&self) {
-        self.executor_tx.send(ExecutorMsg::Shutdown).unwrap();
-        self.cluster_tx.send(ClusterMsg::Shutdown).unwrap();
+        self.stopping.store(true, Ordering::SeqCst);
+        if let Err(_) = self.cluster_tx.send(ClusterMsg::Shutdown) {
+            // The cluster server already exited, so stop the executor workers directly
+            let _ = self.executors.broadcast(|| ExecutorMsg::Shutdown);
+        }
+    }
+
+    fn check_running(&self) -> Result<()> {
+        if self.stopping.load(Ordering::SeqCst) {
+            return Err(ErrorKind::NodeShutdown(self.id.clone()).into());
+        }
+        Ok(())
     }
 }
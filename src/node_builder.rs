@@ -0,0 +1,237 @@
+use std::thread::{self, JoinHandle};
+use std::sync::mpsc::channel;
+use std::fmt::Debug;
+use std::time::Duration;
+use rustc_serialize::{Encodable, Decodable};
+use amy::Poller;
+use slog::{self, DrainExt};
+use slog_stdlog;
+use node_id::NodeId;
+use node::Node;
+use config::RabbleConfig;
+use clock::Clock;
+use transport::{Transport, TcpTransport};
+use cluster::{ClusterServer, ClusterMsg};
+use executor::{Executor, Workers};
+use errors::*;
+
+/// Configure and start a rabble node
+///
+/// ```no_run
+/// let node_id = rabble::NodeId {name: "node1".to_string(), addr: "127.0.0.1:11000".to_string()};
+/// let (node, handle) = rabble::NodeBuilder::new(node_id)
+///     .config(rabble::RabbleConfig::new().num_workers(4))
+///     .build::<()>()
+///     .unwrap();
+/// // Use the node...
+/// handle.shutdown_and_join().unwrap();
+/// ```
+pub struct NodeBuilder {
+    node_id: NodeId,
+    config: RabbleConfig,
+    transport: Option<Box<Transport>>,
+    logger: Option<slog::Logger>
+}
+
+impl NodeBuilder {
+    pub fn new(node_id: NodeId) -> NodeBuilder {
+        NodeBuilder {
+            node_id: node_id,
+            config: RabbleConfig::default(),
+            transport: None,
+            logger: None
+        }
+    }
+
+    pub fn config(mut self, config: RabbleConfig) -> NodeBuilder {
+        self.config = config;
+        self
+    }
+
+    /// Connect to other nodes with the given transport instead of TCP
+    pub fn transport<U: Transport + 'static>(mut self, transport: U) -> NodeBuilder {
+        self.transport = Some(Box::new(transport));
+        self
+    }
+
+    /// Use the given logger instead of logging to the `log` crate
+    pub fn logger(mut self, logger: slog::Logger) -> NodeBuilder {
+        self.logger = Some(logger);
+        self
+    }
+
+    /// Start the node and return it along with a handle to all threads started by rabble.
+    ///
+    /// An error is returned if the config is invalid, the listen address cannot be bound, or any
+    /// resources or threads cannot be created. Any threads already started are shut down before
+    /// the error is returned.
+    pub fn build<T>(self) -> Result<(Node<T>, NodeHandle)>
+        where T: Encodable + Decodable + Send + 'static + Clone + Debug
+    {
+        let transport = self.transport.unwrap_or_else(|| Box::new(TcpTransport::new()));
+        let (node, handles) = try!(start(self.node_id, self.config, transport, self.logger));
+        let shutdown_node = node.clone();
+        let handle = NodeHandle {
+            handles: handles,
+            shutdown: Box::new(move || shutdown_node.shutdown())
+        };
+        Ok((node, handle))
+    }
+}
+
+/// Owns the threads of a running node
+pub struct NodeHandle {
+    handles: Vec<JoinHandle<()>>,
+    shutdown: Box<Fn() + Send>
+}
+
+impl NodeHandle {
+    /// Shutdown the node and wait for all its threads to exit
+    pub fn shutdown_and_join(self) -> Result<()> {
+        (self.shutdown)();
+        self.join()
+    }
+
+    /// Wait for all threads of the node to exit, without shutting it down.
+    ///
+    /// Return an `ErrorKind::JoinError` naming any threads that panicked.
+    pub fn join(self) -> Result<()> {
+        join_all(self.handles)
+    }
+}
+
+/// Start all threads of a node
+pub fn start<T>(node_id: NodeId,
+                config: RabbleConfig,
+                mut transport: Box<Transport>,
+                logger: Option<slog::Logger>) -> Result<(Node<T>, Vec<JoinHandle<()>>)>
+    where T: Encodable + Decodable + Send + 'static + Clone + Debug
+{
+    try!(config.validate());
+    let num_workers = config.num_workers;
+    let poll_timeout = config.poll_timeout;
+    let logger = match logger {
+        Some(logger) => logger.new(o!("node_id" => node_id.to_string())),
+        None => slog::Logger::root(slog_stdlog::StdLog.fuse(), o!("node_id" => node_id.to_string()))
+    };
+
+    let mut poller = try!(Poller::new().chain_err(|| ErrorKind::PollerError));
+    let (stop_poller_tx, stop_poller_rx) = try!(poller.get_registrar().channel()
+                                                .chain_err(|| ErrorKind::PollerError));
+    let (exec_txs, exec_rxs): (Vec<_>, Vec<_>) = (0..num_workers).map(|_| channel()).unzip();
+    let workers = Workers::new(exec_txs);
+    let (cluster_tx, cluster_rx) = channel();
+    let name = format!("transport::{}", node_id);
+    let transport_handles = try!(transport.start(&node_id, &poller.get_registrar())
+                                 .chain_err(|| ErrorKind::SpawnError(name)));
+    let cluster_server = match ClusterServer::new(node_id.clone(),
+                                                  cluster_rx,
+                                                  workers.clone(),
+                                                  poller.get_registrar(),
+                                                  stop_poller_tx,
+                                                  config.clone(),
+                                                  transport,
+                                                  Clock::system(),
+                                                  logger.clone()) {
+        Ok(cluster_server) => cluster_server,
+        Err(e) => {
+            // The threads of the transport exit once it's dropped
+            let _ = join_all(transport_handles);
+            return Err(e);
+        }
+    };
+    let node = Node::new(node_id.clone(),
+                         workers.clone(),
+                         cluster_tx.clone(),
+                         config.clone(),
+                         logger.clone());
+
+    let mut handles = Vec::with_capacity(num_workers + 2 + transport_handles.len());
+    handles.extend(transport_handles);
+    let name = format!("cluster_server::{}", node_id);
+    let handle = try!(thread::Builder::new().name(name.clone()).spawn(move || {
+        cluster_server.run()
+    }).chain_err(|| ErrorKind::SpawnError(name)));
+    handles.push(handle);
+
+    for (i, exec_rx) in exec_rxs.into_iter().enumerate() {
+        let executor = Executor::new(node_id.clone(),
+                                     i,
+                                     workers.clone(),
+                                     exec_rx,
+                                     cluster_tx.clone(),
+                                     config.clone(),
+                                     logger.clone());
+        let name = format!("executor{}::{}", i, node_id);
+        match thread::Builder::new().name(name.clone()).spawn(move || executor.run()) {
+            Ok(handle) => handles.push(handle),
+            Err(e) => {
+                abort(&node, handles);
+                return Err(e).chain_err(|| ErrorKind::SpawnError(name));
+            }
+        }
+    }
+
+    let _cluster_tx = cluster_tx.clone();
+    let poller_node = node.clone();
+    let poller_logger = logger.new(o!("component" => "poller"));
+    let name = format!("poller::{}", node_id);
+    let spawned = thread::Builder::new().name(name.clone()).spawn(move || {
+        loop {
+            let notifications = match poller.wait(poll_timeout) {
+                Ok(notifications) => notifications,
+                Err(e) => {
+                    error!(poller_logger, "Failed to poll. Shutting down the node";
+                           "error" => e.to_string());
+                    poller_node.shutdown();
+                    // Keep the cluster server checking its shutdown deadline, since no more
+                    // timer notifications will arrive
+                    let interval = Duration::from_millis(poll_timeout as u64);
+                    while let Ok(_) = _cluster_tx.send(ClusterMsg::PollNotifications(Vec::new())) {
+                        thread::sleep(interval);
+                    }
+                    return;
+                }
+            };
+            // The cluster server notifies the poller when it exits
+            if notifications.iter().any(|n| n.id == stop_poller_rx.get_id()) {
+                return;
+            }
+            if let Err(_) = _cluster_tx.send(ClusterMsg::PollNotifications(notifications)) {
+                // The process is exiting
+                return;
+            }
+        }
+    });
+    match spawned {
+        Ok(handle) => handles.push(handle),
+        Err(e) => {
+            abort(&node, handles);
+            return Err(e).chain_err(|| ErrorKind::SpawnError(name));
+        }
+    }
+
+    Ok((node, handles))
+}
+
+/// Shutdown the threads of a partially started node
+fn abort<T>(node: &Node<T>, handles: Vec<JoinHandle<()>>)
+    where T: Encodable + Decodable + Clone + Debug
+{
+    node.shutdown();
+    let _ = join_all(handles);
+}
+
+fn join_all(handles: Vec<JoinHandle<()>>) -> Result<()> {
+    let mut panicked = Vec::new();
+    for h in handles {
+        let name = h.thread().name().unwrap_or("unnamed").to_string();
+        if h.join().is_err() {
+            panicked.push(name);
+        }
+    }
+    if panicked.len() != 0 {
+        return Err(ErrorKind::JoinError(panicked).into());
+    }
+    Ok(())
+}
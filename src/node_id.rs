@@ -1,12 +1,32 @@
 use std::fmt::{Display, Error, Formatter};
+use std::io;
+use std::net::{Ipv6Addr, SocketAddr, ToSocketAddrs};
 use std::str::FromStr;
 
+/// The identity of a node in the cluster
+///
+/// `addr` is the address other nodes connect to, of the form `host:port`. The host can be an IPv4
+/// address, an IPv6 address in brackets such as `[::1]:11000`, or a hostname. Nodes on the same
+/// host can also use Unix domain sockets, with addresses of the form `unix:/path/to/sock`.
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Ord, PartialOrd, RustcEncodable, RustcDecodable)]
 pub struct NodeId {
     pub name: String,
     pub addr: String
 }
 
+impl NodeId {
+    /// Resolve the address of the node to all of its socket addresses. Hostnames are looked up on
+    /// every call, which blocks until the lookup completes.
+    ///
+    /// Unix domain socket addresses don't resolve to any socket addresses.
+    pub fn resolve(&self) -> io::Result<Vec<SocketAddr>> {
+        if unix_socket_path(&self.addr).is_some() {
+            return Ok(Vec::new());
+        }
+        self.addr.to_socket_addrs().map(|addrs| addrs.collect())
+    }
+}
+
 impl Display for NodeId {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         try!(fmt.write_fmt(format_args!("{}@{}", self.name, self.addr)));
@@ -17,14 +37,51 @@ impl Display for NodeId {
 impl FromStr for NodeId {
     type Err = String;
 
+    /// Parse a `NodeId` of the form `name@host:port` or `name@unix:/path`. Addresses never contain
+    /// an `@`, so the name is everything before the last one.
     fn from_str(s: &str) -> Result<NodeId, String> {
-        let v: Vec<&str> = s.split("@").collect();
-        if v.len() != 2 {
-            return Err("Invalid Pid format - Must be of form 'name@addr'".to_string())
+        let (name, addr) = match s.rfind('@') {
+            Some(i) => (&s[..i], &s[i+1..]),
+            None => return Err(invalid_node_id(s))
+        };
+        if name.is_empty() || !is_valid_addr(addr) {
+            return Err(invalid_node_id(s));
         }
         Ok(NodeId {
-            name: v[0].to_string(),
-            addr: v[1].to_string()
+            name: name.to_string(),
+            addr: addr.to_string()
         })
     }
 }
+
+fn invalid_node_id(s: &str) -> String {
+    format!("Invalid NodeId format {} - Must be of form 'name@host:port', with IPv6 hosts in \
+             brackets, or 'name@unix:/path'", s)
+}
+
+/// Return the path of the Unix domain socket if `addr` is of the form `unix:/path`
+pub fn unix_socket_path(addr: &str) -> Option<&str> {
+    if addr.starts_with("unix:") && addr.len() > 5 {
+        return Some(&addr[5..]);
+    }
+    None
+}
+
+/// Return true if `addr` is of the form `host:port` or `unix:/path`. IPv6 hosts must be in
+/// brackets, since the port couldn't be told apart from the address otherwise.
+fn is_valid_addr(addr: &str) -> bool {
+    if unix_socket_path(addr).is_some() {
+        return true;
+    }
+    let (host, port) = match addr.rfind(':') {
+        Some(i) => (&addr[..i], &addr[i+1..]),
+        None => return false
+    };
+    if port.parse::<u16>().is_err() || host.is_empty() {
+        return false;
+    }
+    if host.starts_with('[') && host.ends_with(']') {
+        return host[1..host.len()-1].parse::<Ipv6Addr>().is_ok();
+    }
+    !host.contains(':')
+}
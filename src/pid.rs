@@ -25,23 +25,32 @@ impl Display for Pid {
 impl FromStr for Pid {
     type Err = String;
 
+    /// The address of the node may contain `::` if it's an IPv6 address, so only the part before
+    /// the `@` of the node is split into the group, name and node name.
     fn from_str(s: &str) -> Result<Pid, String> {
-        let v: Vec<&str> = s.split("::").collect();
+        let prefix = match s.rfind('@') {
+            Some(i) => &s[..i],
+            None => return Err(invalid_pid(s))
+        };
+        let v: Vec<&str> = prefix.split("::").collect();
+        let node_start = prefix.len() - v[v.len() - 1].len();
+        let node = try!(NodeId::from_str(&s[node_start..]));
         match v.len() {
             2 => Ok(Pid {
                 group: None,
                 name: v[0].to_string(),
-                node: try!(NodeId::from_str(v[1]))
+                node: node
             }),
             3 => Ok(Pid {
                 group: Some(v[0].to_string()),
                 name: v[1].to_string(),
-                node: try!(NodeId::from_str(v[2]))
+                node: node
             }),
-            _ => return Err(
-                "Invalid Pid format - Must be of form 'name::node' or \
-                'group::name::node'".to_string()
-            )
+            _ => Err(invalid_pid(s))
         }
     }
 }
+
+fn invalid_pid(s: &str) -> String {
+    format!("Invalid Pid format {} - Must be of form 'name::node' or 'group::name::node'", s)
+}
@@ -2,6 +2,7 @@ use std::fmt::Debug;
 use rustc_serialize::{Encodable, Decodable};
 use pid::Pid;
 use msg::Msg;
+use effect::Effect;
 use envelope::Envelope;
 use correlation_id::CorrelationId;
 
@@ -19,4 +20,13 @@ pub trait Process : Send {
               from: Pid,
               correlation_id: Option<CorrelationId>)
         -> &mut Vec<Envelope<Self::Msg>>;
+
+    /// Return any effects the executor should perform on behalf of the process
+    ///
+    /// This is called after every call to `init` and `handle`. The returned effects are drained and
+    /// performed before any returned envelopes are routed, so a process can spawn a child and send
+    /// it a message in the same call.
+    fn effects(&mut self) -> Option<&mut Vec<Effect<Self::Msg>>> {
+        None
+    }
 }
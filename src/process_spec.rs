@@ -0,0 +1,28 @@
+use std::fmt::Debug;
+use rustc_serialize::{Encodable, Decodable};
+use pid::Pid;
+use process::Process;
+
+/// A function registered with a node under a name, that creates processes from a `ProcessSpec`
+///
+/// The function is called with the pid of the new process and the argument in the spec.
+pub type Factory<T> = Box<Fn(Pid, Option<T>) -> Box<Process<Msg=T>> + Send>;
+
+/// A serializable description of a process, used to start processes on other nodes
+///
+/// Since processes themselves can't be sent between nodes, a spec names a `Factory` registered on
+/// the remote node with `Node::register_factory`, along with an optional argument for it.
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct ProcessSpec<T: Encodable + Decodable + Debug + Clone> {
+    pub factory: String,
+    pub arg: Option<T>
+}
+
+impl<T: Encodable + Decodable + Debug + Clone> ProcessSpec<T> {
+    pub fn new(factory: &str, arg: Option<T>) -> ProcessSpec<T> {
+        ProcessSpec {
+            factory: factory.to_string(),
+            arg: arg
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use pid::Pid;
+
+/// The reason a process exited
+///
+/// Reasons are delivered to monitoring processes in `Msg::Down` and to linked processes in
+/// `Msg::Exit`.
+#[derive(Debug, Clone, Eq, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum Reason {
+    /// The process was stopped via `Node::stop`
+    Normal,
+
+    /// The process did not exist when the monitor or link was created
+    NoProc,
+
+    /// The node the process was running on left the cluster
+    NodeDown,
+
+    /// The process was stopped because a process it was linked to exited abnormally
+    Linked(Pid),
+
+    /// The process panicked. The panic message is included.
+    Panic(String)
+}
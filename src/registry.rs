@@ -0,0 +1,76 @@
+use orset::{ORSet, Delta};
+use node_id::NodeId;
+use pid::Pid;
+
+pub type Entry = (String, Pid);
+
+/// A cluster wide registry of names for pids
+///
+/// The registry is replicated to all nodes in the cluster as an ORSet of `(name, pid)` pairs, in
+/// the same manner as `Members`. If the same name is registered concurrently on different nodes,
+/// all nodes resolve the name to the smallest registered pid.
+pub struct Registry {
+    orset: ORSet<Entry>
+}
+
+impl Registry {
+    pub fn new(node: NodeId) -> Registry {
+        Registry {
+            orset: ORSet::new(node.to_string())
+        }
+    }
+
+    /// Register `name` for `pid`.
+    ///
+    /// Returns None if the name is already registered for another pid, or if it is already
+    /// registered for `pid`.
+    pub fn register(&mut self, name: String, pid: Pid) -> Option<Delta<Entry>> {
+        if self.whereis(&name).is_some() {
+            return None;
+        }
+        Some(self.orset.add((name, pid)))
+    }
+
+    /// Returns None if `name` isn't registered for `pid`
+    pub fn unregister(&mut self, name: String, pid: Pid) -> Option<Delta<Entry>> {
+        let entry = (name, pid);
+        if let Some(dots) = self.orset.seen(&entry) {
+            return Some(self.orset.remove(entry, dots));
+        }
+        None
+    }
+
+    /// Remove all names registered for `pid`
+    pub fn remove_pid(&mut self, pid: &Pid) -> Vec<Delta<Entry>> {
+        self.remove_where(|entry| entry.1 == *pid)
+    }
+
+    /// Remove all names registered for pids on `node`
+    pub fn remove_node(&mut self, node: &NodeId) -> Vec<Delta<Entry>> {
+        self.remove_where(|entry| entry.1.node == *node)
+    }
+
+    fn remove_where<F>(&mut self, f: F) -> Vec<Delta<Entry>> where F: Fn(&Entry) -> bool {
+        let mut entries: Vec<Entry> =
+            self.orset.elements().into_iter().filter(|e| f(e)).collect();
+        entries.sort();
+        entries.into_iter().filter_map(|(name, pid)| self.unregister(name, pid)).collect()
+    }
+
+    /// Return the pid registered for `name` if there is one
+    pub fn whereis(&self, name: &str) -> Option<Pid> {
+        self.orset.elements().into_iter().filter(|entry| entry.0 == name).map(|entry| entry.1).min()
+    }
+
+    pub fn join(&mut self, other: ORSet<Entry>) {
+        self.orset.join_state(other);
+    }
+
+    pub fn join_delta(&mut self, delta: Delta<Entry>) -> bool {
+        self.orset.join(delta)
+    }
+
+    pub fn get_orset(&self) -> ORSet<Entry> {
+        self.orset.clone()
+    }
+}
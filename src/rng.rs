@@ -0,0 +1,49 @@
+/// A small seeded pseudo random number generator (xorshift64*)
+///
+/// A simulation must make the same choices every time it's run with the same seed, on every
+/// platform, so it can't rely on an external source of randomness. Injected network faults use it
+/// for the same reason.
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // Scramble the seed with splitmix64, since xorshift gets stuck on a state of 0 and
+        // produces similar sequences for similar seeds
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z = z ^ (z >> 31);
+        Rng {
+            state: if z == 0 { 1 } else { z }
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Return a number in `[0, n)`. `n` must not be 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Return a number in `[min, max]`
+    pub fn between(&mut self, min: usize, max: usize) -> usize {
+        if max <= min {
+            return min;
+        }
+        min + self.below(max - min + 1)
+    }
+
+    /// Return true with probability `p`
+    #[cfg(feature = "fault-injection")]
+    pub fn chance(&mut self, p: f64) -> bool {
+        // The top 53 bits fill the mantissa of an f64 in [0, 1)
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+}
@@ -30,7 +30,7 @@ impl<T, H> Service<T, H>
           H: ServiceHandler<T>
 {
     pub fn new(pid: Pid, node: Node<T>, mut handler: H) -> Result<Service<T, H>> {
-        let poller = Poller::new().unwrap();
+        let poller = try!(Poller::new().chain_err(|| ErrorKind::PollerError));
         let registrar = poller.get_registrar();
         let (tx, rx) = registrar.channel().unwrap();
         try!(node.register_service(&pid, &tx));
@@ -50,8 +50,8 @@ impl<T, H> Service<T, H>
 
     pub fn wait(&mut self) {
         loop {
-            // TODO: Configurable timeout?
-            for notification in self.poller.wait(1000).unwrap() {
+            let timeout = self.node.config.service_poll_timeout;
+            for notification in self.poller.wait(timeout).unwrap() {
                 if notification.id == self.rx.get_id() {
                     if let Err(e) = self.handle_envelopes() {
                         if let ErrorKind::Shutdown(_) = *e.kind() {
@@ -0,0 +1,366 @@
+//! Deterministic simulation of whole clusters
+//!
+//! A `Simulation` runs the cluster servers and executor workers of several nodes on the calling
+//! thread, connected by a simulated network, under a virtual clock. Messages waiting to be handled
+//! are chosen by a seeded random number generator, and the clock only moves forward when nothing
+//! is left to handle at the current time. Running a simulation twice with the same seed handles
+//! every message and fires every timer in the same order, at the same virtual time.
+
+mod network;
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::sync::mpsc::{channel, Sender};
+use rustc_serialize::{Encodable, Decodable};
+use amy::{self, Poller, Notification, Event};
+use slog::{self, DrainExt};
+use slog_stdlog;
+use node_id::NodeId;
+use node::Node;
+use pid::Pid;
+use envelope::Envelope;
+use config::RabbleConfig;
+use clock::Clock;
+use cluster::{ClusterServer, ClusterMsg};
+use executor::{Executor, ExecutorMsg, Workers};
+use errors::*;
+use rng::Rng;
+use self::network::SimNetwork;
+
+/// A cluster of nodes running on a single thread under a virtual clock
+///
+/// Nodes are added with `add_node`, which returns a regular `Node`. All calls on the node only
+/// queue messages. Nothing runs until the simulation is driven by `run_for` or `run_until`.
+///
+/// Services don't run in a simulation. Use `service` to get a receiver for the envelopes sent to a
+/// pid instead.
+///
+/// ```no_run
+/// use rabble::{Simulation, NodeId, RabbleConfig};
+///
+/// let mut sim = Simulation::<u64>::new(42);
+/// let node1 = NodeId {name: "node1".to_string(), addr: "node1".to_string()};
+/// let node2 = NodeId {name: "node2".to_string(), addr: "node2".to_string()};
+/// let node = sim.add_node(node1, RabbleConfig::new()).unwrap();
+/// sim.add_node(node2.clone(), RabbleConfig::new()).unwrap();
+/// node.join(&node2).unwrap();
+///
+/// // Ten seconds pass on the virtual clock, in a fraction of that time
+/// sim.run_for(10_000);
+/// ```
+pub struct Simulation<T: Encodable + Decodable + Send + Debug + Clone> {
+    clock: Clock,
+    network: SimNetwork,
+    rng: Rng,
+    nodes: Vec<SimNode<T>>,
+    logger: slog::Logger
+}
+
+/// The parts of a node that run in a thread outside of a simulation
+struct SimNode<T: Encodable + Decodable + Send + Debug + Clone> {
+    id: NodeId,
+
+    /// The address the node listens on in the simulated network
+    addr: String,
+    node: Node<T>,
+    server: ClusterServer<T>,
+
+    /// The executor workers of the node. A worker is removed once it shuts down.
+    executors: Vec<Option<Executor<T>>>,
+
+    /// Messages received by the cluster server and each executor worker, in the order they were
+    /// sent, that weren't handled yet
+    server_queue: VecDeque<ClusterMsg<T>>,
+    executor_queues: Vec<VecDeque<ExecutorMsg<T>>>,
+
+    /// Used to deliver poll notifications to the cluster server, as the poller thread would
+    cluster_tx: Sender<ClusterMsg<T>>,
+
+    /// The poller is never polled, but provides the registrar the node registers with
+    poller: Poller,
+    tick_time: usize,
+    executor_tick_time: usize,
+    next_tick: usize,
+    next_executor_tick: usize
+}
+
+/// A cluster server or executor worker of a node with messages to handle
+#[derive(Clone, Copy)]
+enum Task {
+    Server(usize),
+    Executor(usize, usize)
+}
+
+impl<T: Encodable + Decodable + Send + Debug + Clone> SimNode<T> {
+    /// Move all messages received by the cluster server and executor workers to their queues
+    fn collect(&mut self) {
+        while let Some(msg) = self.server.try_recv() {
+            self.server_queue.push_back(msg);
+        }
+        for (executor, queue) in self.executors.iter().zip(self.executor_queues.iter_mut()) {
+            if let Some(ref executor) = *executor {
+                while let Some(msg) = executor.try_recv() {
+                    queue.push_back(msg);
+                }
+            }
+        }
+    }
+
+    /// Send timer notifications to the cluster server for all timers due at `now`
+    fn tick(&mut self, now: usize) {
+        let (timer_id, executor_timer_id) = self.server.timer_ids();
+        let mut notifications = Vec::new();
+        if self.next_tick <= now {
+            notifications.push(Notification {id: timer_id, event: Event::Read});
+            self.next_tick += self.tick_time;
+        }
+        if self.next_executor_tick <= now {
+            notifications.push(Notification {id: executor_timer_id, event: Event::Read});
+            self.next_executor_tick += self.executor_tick_time;
+        }
+        if !notifications.is_empty() {
+            let _ = self.cluster_tx.send(ClusterMsg::PollNotifications(notifications));
+        }
+    }
+}
+
+impl<T: Encodable + Decodable + Send + Debug + Clone + 'static> Simulation<T> {
+    /// Create a simulation whose choices are all determined by `seed`
+    ///
+    /// Data sent between nodes arrives 1 to 5 ms after it's sent, unless changed with `latency`.
+    pub fn new(seed: u64) -> Simulation<T> {
+        let clock = Clock::virtual_clock();
+        Simulation {
+            clock: clock.clone(),
+            // The network uses its own generator, so that the latency of messages doesn't depend
+            // on how many scheduling choices were made
+            network: SimNetwork::new(clock, seed.wrapping_add(1), 1, 5),
+            rng: Rng::new(seed),
+            nodes: Vec::new(),
+            logger: slog::Logger::root(slog_stdlog::StdLog.fuse(), o!("simulation" => seed))
+        }
+    }
+
+    /// Deliver data sent between nodes between `min` and `max` ms after it's sent
+    pub fn latency(self, min: usize, max: usize) -> Simulation<T> {
+        self.network.set_latency(min, max);
+        self
+    }
+
+    pub fn logger(mut self, logger: slog::Logger) -> Simulation<T> {
+        self.logger = logger;
+        self
+    }
+
+    /// Return the number of ms that passed on the virtual clock since the simulation was created
+    pub fn now(&self) -> usize {
+        self.clock.elapsed_ms()
+    }
+
+    /// Start a node in the simulation and return it
+    ///
+    /// The node listens on the `addr` of its `NodeId`, or on `listen_addr` if it is configured.
+    /// Addresses are just names in the simulated network. The cluster server ticks every
+    /// `tick_time` ms of virtual time, and the executor workers every `executor_tick_time` ms.
+    pub fn add_node(&mut self, node_id: NodeId, config: RabbleConfig) -> Result<Node<T>> {
+        try!(config.validate());
+        let logger = self.logger.new(o!("node_id" => node_id.to_string()));
+        let poller = try!(Poller::new().chain_err(|| ErrorKind::PollerError));
+        let (stop_poller_tx, _) = try!(poller.get_registrar().channel()
+                                       .chain_err(|| ErrorKind::PollerError));
+        let (exec_txs, exec_rxs): (Vec<_>, Vec<_>) =
+            (0..config.num_workers).map(|_| channel()).unzip();
+        let workers = Workers::new(exec_txs);
+        let (cluster_tx, cluster_rx) = channel();
+        let server = try!(ClusterServer::new(node_id.clone(),
+                                             cluster_rx,
+                                             workers.clone(),
+                                             poller.get_registrar(),
+                                             stop_poller_tx,
+                                             config.clone(),
+                                             Box::new(self.network.transport()),
+                                             self.clock.clone(),
+                                             logger.clone()));
+        let node = Node::new(node_id.clone(),
+                             workers.clone(),
+                             cluster_tx.clone(),
+                             config.clone(),
+                             logger.clone());
+        let executors = exec_rxs.into_iter().enumerate().map(|(i, exec_rx)| {
+            Some(Executor::new(node_id.clone(),
+                               i,
+                               workers.clone(),
+                               exec_rx,
+                               cluster_tx.clone(),
+                               config.clone(),
+                               logger.clone()))
+        }).collect();
+        let now = self.now();
+        self.nodes.push(SimNode {
+            addr: config.listen_addr.clone().unwrap_or(node_id.addr.clone()),
+            id: node_id,
+            node: node.clone(),
+            server: server,
+            executors: executors,
+            server_queue: VecDeque::new(),
+            executor_queues: (0..config.num_workers).map(|_| VecDeque::new()).collect(),
+            cluster_tx: cluster_tx,
+            poller: poller,
+            tick_time: config.tick_time,
+            executor_tick_time: config.executor_tick_time,
+            next_tick: now + config.tick_time,
+            next_executor_tick: now + config.executor_tick_time
+        });
+        Ok(node)
+    }
+
+    /// Register a service with `pid` on its node, and return the receiver of envelopes sent to it
+    ///
+    /// Services don't run in a simulation. Envelopes sent to the service are available from the
+    /// receiver as soon as the executor routes them.
+    pub fn service(&mut self, pid: &Pid) -> Result<amy::Receiver<Envelope<T>>> {
+        let node = match self.nodes.iter().find(|node| node.id == pid.node) {
+            Some(node) => node,
+            None => {
+                let msg = "ExecutorMsg::RegisterService".to_string();
+                return Err(ErrorKind::SendError(msg, Some(pid.clone())).into());
+            }
+        };
+        let (tx, rx) = try!(node.poller.get_registrar().channel()
+                            .chain_err(|| ErrorKind::PollerError));
+        try!(node.node.register_service(pid, &tx));
+        Ok(rx)
+    }
+
+    /// Stop a node immediately, as if its process crashed
+    ///
+    /// Messages the node didn't handle yet are lost, and its connections are closed. A node with
+    /// the same id can be added again afterwards, as if it restarted.
+    pub fn crash(&mut self, node: &NodeId) {
+        self.nodes.retain(|n| n.id != *node);
+    }
+
+    /// Run the simulation until `ms` passed on the virtual clock
+    pub fn run_for(&mut self, ms: usize) {
+        let end = self.now() + ms;
+        while self.step(end) {}
+    }
+
+    /// Run the simulation until `done` returns true, or until `timeout` ms passed on the virtual
+    /// clock. `done` is checked before every step. Return the last result of `done`.
+    pub fn run_until<F>(&mut self, timeout: usize, mut done: F) -> bool
+        where F: FnMut() -> bool
+    {
+        let end = self.now() + timeout;
+        loop {
+            if done() {
+                return true;
+            }
+            if !self.step(end) {
+                return done();
+            }
+        }
+    }
+
+    /// Handle a single message chosen at random, or if there are none, move the clock to the next
+    /// scheduled event and handle it.
+    ///
+    /// Return false if there is nothing left to do before `end`. The clock is at `end` then.
+    fn step(&mut self, end: usize) -> bool {
+        for node in self.nodes.iter_mut() {
+            node.collect();
+        }
+        let tasks = self.tasks();
+        if !tasks.is_empty() {
+            let task = tasks[self.rng.below(tasks.len())];
+            self.run(task);
+            return true;
+        }
+        match self.next_event_time() {
+            Some(time) if time <= end => {
+                self.advance_to(time);
+                self.fire_events();
+                true
+            },
+            _ => {
+                self.advance_to(end);
+                false
+            }
+        }
+    }
+
+    /// Return every cluster server and executor worker that has messages to handle
+    fn tasks(&self) -> Vec<Task> {
+        let mut tasks = Vec::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !node.server_queue.is_empty() {
+                tasks.push(Task::Server(i));
+            }
+            for (j, queue) in node.executor_queues.iter().enumerate() {
+                if !queue.is_empty() {
+                    tasks.push(Task::Executor(i, j));
+                }
+            }
+        }
+        tasks
+    }
+
+    /// Handle the next message of a cluster server or executor worker
+    ///
+    /// A node is removed once its cluster server exits, and an executor worker once it shuts down.
+    /// Messages to a worker that shut down are dropped, as they would be by its closed channel.
+    fn run(&mut self, task: Task) {
+        match task {
+            Task::Server(i) => {
+                let msg = self.nodes[i].server_queue.pop_front().unwrap();
+                if !self.nodes[i].server.handle(msg) {
+                    self.nodes.remove(i);
+                }
+            },
+            Task::Executor(i, j) => {
+                let node = &mut self.nodes[i];
+                let msg = node.executor_queues[j].pop_front().unwrap();
+                let running = match node.executors[j] {
+                    Some(ref mut executor) => executor.handle(msg),
+                    None => false
+                };
+                if !running {
+                    node.executors[j] = None;
+                    node.executor_queues[j].clear();
+                }
+            }
+        }
+    }
+
+    /// Return the time of the next timer tick or network event
+    fn next_event_time(&self) -> Option<usize> {
+        let ticks = self.nodes.iter()
+            .map(|node| cmp::min(node.next_tick, node.next_executor_tick))
+            .min();
+        match (ticks, self.network.next_event_time()) {
+            (Some(tick), Some(event)) => Some(cmp::min(tick, event)),
+            (tick, event) => tick.or(event)
+        }
+    }
+
+    fn advance_to(&mut self, time: usize) {
+        let now = self.now();
+        if time > now {
+            self.clock.advance(time - now);
+        }
+    }
+
+    /// Deliver timer ticks and network events due at the current time to the cluster servers
+    fn fire_events(&mut self) {
+        let now = self.now();
+        for node in self.nodes.iter_mut() {
+            node.tick(now);
+        }
+        while let Some((addr, notification)) = self.network.poll() {
+            if let Some(node) = self.nodes.iter().find(|node| node.addr == addr) {
+                let _ = node.cluster_tx.send(ClusterMsg::PollNotifications(vec![notification]));
+            }
+        }
+    }
+}
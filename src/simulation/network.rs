@@ -0,0 +1,362 @@
+use std::io;
+use std::cmp;
+use std::fmt;
+use std::collections::{HashMap, BTreeMap};
+use std::sync::{Arc, Mutex};
+use amy::{self, Registrar, Event, Notification};
+use node_id::NodeId;
+use clock::Clock;
+use transport::{Transport, Stream};
+use transport::memory::{MemoryStream, Pipe, Link};
+use rng::Rng;
+
+/// The network connecting the nodes of a `Simulation`
+///
+/// Connections are made of the same streams and pipes as those of a `MemoryNetwork`, but nothing is
+/// sent directly. Connection attempts and writes are scheduled as events on the virtual
+/// clock, after a latency chosen by a seeded random number generator, and take effect when the
+/// simulation reaches them. Data written to a stream is never reordered or lost, as with TCP.
+///
+/// Readiness of listeners and streams is reported as poll notifications for the node that owns
+/// them, which the simulation passes on to the cluster server of that node.
+#[derive(Clone)]
+pub struct SimNetwork {
+    state: Arc<Mutex<State>>
+}
+
+struct State {
+    clock: Clock,
+    rng: Rng,
+    min_latency: usize,
+    max_latency: usize,
+    listeners: HashMap<String, Listener>,
+
+    /// Each direction of a connection, removed once its reading end is dropped
+    pipes: HashMap<u64, SimPipe>,
+    next_pipe: u64,
+
+    /// Pending events ordered by time, and by the order they were scheduled in
+    events: BTreeMap<(usize, u64), NetEvent>,
+    next_seq: u64
+}
+
+/// A node listening on the network
+struct Listener {
+    id: usize,
+
+    /// The pipes of connections that arrived but weren't accepted yet, as (incoming, outgoing)
+    pending: Vec<(u64, u64)>,
+
+    /// Keeps the id of the listener's notifications reserved with the registrar of its node
+    _rx: amy::Receiver<()>
+}
+
+/// One direction of a connection
+struct SimPipe {
+    pipe: Arc<Mutex<Pipe>>,
+
+    /// The time of the last scheduled delivery, so that later writes are never delivered earlier
+    last_delivery: usize,
+
+    /// The registration of the reading end with the poller of its node
+    reader: Option<Reader>
+}
+
+struct Reader {
+    addr: String,
+    id: usize,
+
+    /// Keeps `id` reserved with the registrar of the node
+    _rx: amy::Receiver<()>
+}
+
+enum NetEvent {
+    /// Report a poll notification to the node listening on an address
+    Notify(String, Notification),
+
+    /// Deliver data written to a pipe, or close the pipe if there is no data
+    Deliver(u64, Option<Vec<u8>>),
+
+    /// A connection attempt reaches the listening address. The pipes are (to server, to client).
+    Connect(String, u64, u64)
+}
+
+impl SimPipe {
+    fn new() -> SimPipe {
+        SimPipe {
+            pipe: Pipe::new(),
+            last_delivery: 0,
+            reader: None
+        }
+    }
+}
+
+impl State {
+    fn schedule(&mut self, time: usize, event: NetEvent) {
+        self.events.insert((time, self.next_seq), event);
+        self.next_seq += 1;
+    }
+
+    fn notify(&mut self, addr: String, id: usize, event: Event) {
+        let now = self.clock.elapsed_ms();
+        self.schedule(now, NetEvent::Notify(addr, Notification {id: id, event: event}));
+    }
+
+    /// Schedule a delivery to a pipe after a random latency, but never before earlier deliveries
+    /// to the same pipe
+    fn send(&mut self, pipe_id: u64, data: Option<Vec<u8>>) {
+        let latency = self.rng.between(self.min_latency, self.max_latency);
+        let mut time = self.clock.elapsed_ms() + latency;
+        if let Some(pipe) = self.pipes.get_mut(&pipe_id) {
+            time = cmp::max(time, pipe.last_delivery);
+            pipe.last_delivery = time;
+        }
+        self.schedule(time, NetEvent::Deliver(pipe_id, data));
+    }
+
+    fn new_pipe(&mut self) -> u64 {
+        let id = self.next_pipe;
+        self.next_pipe += 1;
+        self.pipes.insert(id, SimPipe::new());
+        id
+    }
+
+    /// Close a connection that was never accepted
+    fn refuse(&mut self, to_server: u64, to_client: u64) {
+        self.pipes.remove(&to_server);
+        self.send(to_client, None);
+    }
+
+    /// Schedule a read notification for the reader of a pipe
+    fn readable(&mut self, pipe_id: u64) {
+        let reader = self.pipes.get(&pipe_id)
+            .and_then(|pipe| pipe.reader.as_ref())
+            .map(|reader| (reader.addr.clone(), reader.id));
+        if let Some((addr, id)) = reader {
+            self.notify(addr, id, Event::Read);
+        }
+    }
+}
+
+impl SimNetwork {
+    /// Create a network that delivers data between `min_latency` and `max_latency` ms after it was
+    /// written
+    pub fn new(clock: Clock, seed: u64, min_latency: usize, max_latency: usize) -> SimNetwork {
+        SimNetwork {
+            state: Arc::new(Mutex::new(State {
+                clock: clock,
+                rng: Rng::new(seed),
+                min_latency: min_latency,
+                max_latency: max_latency,
+                listeners: HashMap::new(),
+                pipes: HashMap::new(),
+                next_pipe: 0,
+                events: BTreeMap::new(),
+                next_seq: 0
+            }))
+        }
+    }
+
+    pub fn set_latency(&self, min_latency: usize, max_latency: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.min_latency = min_latency;
+        state.max_latency = max_latency;
+    }
+
+    /// Create a transport for a node on this network
+    pub fn transport(&self) -> SimTransport {
+        SimTransport {
+            network: self.clone(),
+            addr: None
+        }
+    }
+
+    /// Return the time of the next pending event
+    pub fn next_event_time(&self) -> Option<usize> {
+        self.state.lock().unwrap().events.keys().next().map(|&(time, _)| time)
+    }
+
+    /// Handle all events due by the current time until one produces a poll notification. Return
+    /// the notification along with the address of the node it's for.
+    pub fn poll(&self) -> Option<(String, Notification)> {
+        let mut state = self.state.lock().unwrap();
+        let now = state.clock.elapsed_ms();
+        loop {
+            let key = match state.events.keys().next() {
+                Some(&(time, seq)) if time <= now => (time, seq),
+                _ => return None
+            };
+            match state.events.remove(&key).unwrap() {
+                NetEvent::Notify(addr, notification) => return Some((addr, notification)),
+                NetEvent::Deliver(pipe_id, data) => {
+                    if let Some(pipe) = state.pipes.get(&pipe_id) {
+                        let mut pipe = pipe.pipe.lock().unwrap();
+                        match data {
+                            Some(data) => pipe.push(&data),
+                            None => pipe.close()
+                        }
+                    }
+                    state.readable(pipe_id);
+                },
+                NetEvent::Connect(addr, to_server, to_client) => {
+                    let listener_id = match state.listeners.get_mut(&addr) {
+                        Some(listener) => {
+                            listener.pending.push((to_server, to_client));
+                            Some(listener.id)
+                        },
+                        None => None
+                    };
+                    match listener_id {
+                        Some(id) => state.notify(addr, id, Event::Read),
+                        None => state.refuse(to_server, to_client)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The transport of a single node in a `Simulation`
+pub struct SimTransport {
+    network: SimNetwork,
+    addr: Option<String>
+}
+
+impl SimTransport {
+    fn stream(&self, state: &State, incoming: u64, outgoing: u64) -> Box<Stream> {
+        // A pipe that was already removed was closed along with the connection
+        let pipe = match state.pipes.get(&incoming) {
+            Some(pipe) => pipe.pipe.clone(),
+            None => {
+                let pipe = Pipe::new();
+                pipe.lock().unwrap().close();
+                pipe
+            }
+        };
+        Box::new(MemoryStream::new(pipe, SimLink {
+            network: self.network.clone(),
+            addr: self.addr.clone().unwrap_or_else(String::new),
+            incoming: incoming,
+            outgoing: outgoing
+        }))
+    }
+}
+
+impl Transport for SimTransport {
+    fn listen(&mut self, addr: &str, registrar: &Registrar) -> io::Result<usize> {
+        let mut state = self.network.state.lock().unwrap();
+        if state.listeners.contains_key(addr) {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse,
+                                      format!("{} is already in use", addr)));
+        }
+        let (_, rx) = try!(registrar.channel());
+        let id = rx.get_id();
+        state.listeners.insert(addr.to_string(), Listener {id: id, pending: Vec::new(), _rx: rx});
+        self.addr = Some(addr.to_string());
+        Ok(id)
+    }
+
+    fn accept(&mut self) -> io::Result<Vec<Box<Stream>>> {
+        let addr = match self.addr {
+            Some(ref addr) => addr,
+            None => return Ok(Vec::new())
+        };
+        let mut state = self.network.state.lock().unwrap();
+        let pending: Vec<_> =
+            state.listeners.get_mut(addr).map_or(Vec::new(), |l| l.pending.drain(..).collect());
+        Ok(pending.into_iter()
+           .map(|(incoming, outgoing)| self.stream(&state, incoming, outgoing))
+           .collect())
+    }
+
+    fn connect(&mut self, node: &NodeId, _: &Registrar) -> io::Result<Box<Stream>> {
+        let mut state = self.network.state.lock().unwrap();
+        let to_server = state.new_pipe();
+        let to_client = state.new_pipe();
+        let (min, max) = (state.min_latency, state.max_latency);
+        let time = state.clock.elapsed_ms() + state.rng.between(min, max);
+        state.schedule(time, NetEvent::Connect(node.addr.clone(), to_server, to_client));
+
+        // A connection to an address nobody listens on is closed by the peer, as it would be if
+        // the connection was refused after it was started
+        Ok(self.stream(&state, to_client, to_server))
+    }
+}
+
+impl Drop for SimTransport {
+    fn drop(&mut self) {
+        if let Some(ref addr) = self.addr {
+            let mut state = self.network.state.lock().unwrap();
+            if let Some(listener) = state.listeners.remove(addr) {
+                for (to_server, to_client) in listener.pending {
+                    state.refuse(to_server, to_client);
+                }
+            }
+        }
+    }
+}
+
+/// Connects the ends of a stream in a `Simulation` through the events of the network
+struct SimLink {
+    network: SimNetwork,
+
+    /// The address of the node owning this end of the connection
+    addr: String,
+    incoming: u64,
+    outgoing: u64
+}
+
+impl fmt::Debug for SimLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SimLink {{ addr: {}, incoming: {}, outgoing: {} }}",
+               self.addr, self.incoming, self.outgoing)
+    }
+}
+
+impl Link for SimLink {
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        // Data written after the peer closed the connection is lost. The writer finds out when it
+        // reads the end of the stream.
+        self.network.state.lock().unwrap().send(self.outgoing, Some(data.to_vec()));
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        let mut state = self.network.state.lock().unwrap();
+        state.pipes.remove(&self.incoming);
+        state.send(self.outgoing, None);
+    }
+
+    fn register(&self, incoming: &Mutex<Pipe>, registrar: &Registrar, event: Event)
+        -> io::Result<usize>
+    {
+        let (_, rx) = try!(registrar.channel());
+        let id = rx.get_id();
+        let mut state = self.network.state.lock().unwrap();
+        if let Some(pipe) = state.pipes.get_mut(&self.incoming) {
+            pipe.reader = Some(Reader {addr: self.addr.clone(), id: id, _rx: rx});
+        }
+        if incoming.lock().unwrap().is_readable() {
+            state.notify(self.addr.clone(), id, Event::Read);
+        }
+        if event.writable() {
+            state.notify(self.addr.clone(), id, Event::Write);
+        }
+        Ok(id)
+    }
+
+    fn reregister(&self, id: usize, _: &Registrar, event: Event) -> io::Result<()> {
+        if event.writable() {
+            self.network.state.lock().unwrap().notify(self.addr.clone(), id, Event::Write);
+        }
+        Ok(())
+    }
+
+    fn deregister(&self, _: &Registrar) -> io::Result<()> {
+        let mut state = self.network.state.lock().unwrap();
+        if let Some(pipe) = state.pipes.get_mut(&self.incoming) {
+            pipe.reader = None;
+        }
+        Ok(())
+    }
+}
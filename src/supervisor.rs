@@ -0,0 +1,195 @@
+use std::fmt::Debug;
+use rustc_serialize::{Encodable, Decodable};
+use slog;
+use pid::Pid;
+use process::Process;
+use envelope::Envelope;
+use correlation_id::CorrelationId;
+use msg::Msg;
+use effect::Effect;
+
+/// Determines which children are restarted when a child exits
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Strategy {
+    /// Only restart the child that exited
+    OneForOne,
+
+    /// Restart all children
+    OneForAll,
+
+    /// Restart the child that exited and all children started after it
+    RestForOne
+}
+
+/// A specification for a child of a supervisor
+///
+/// The factory is called to create a new instance of the process every time the child is started.
+pub struct ChildSpec<T: Encodable + Decodable + Debug + Clone> {
+    pub pid: Pid,
+    factory: Box<Fn() -> Box<Process<Msg=T>> + Send>
+}
+
+impl<T: Encodable + Decodable + Debug + Clone> ChildSpec<T> {
+    pub fn new<F>(pid: Pid, factory: F) -> ChildSpec<T>
+        where F: Fn() -> Box<Process<Msg=T>> + Send + 'static
+    {
+        ChildSpec {
+            pid: pid,
+            factory: Box::new(factory)
+        }
+    }
+}
+
+/// A process that starts, monitors and restarts a set of child processes
+///
+/// Children are started in order when the supervisor is spawned. Whenever a child exits, for any
+/// reason, the children determined by the `Strategy` are restarted. If more than `max_restarts`
+/// restarts occur within `max_time` milliseconds, the supervisor stops all of its children and
+/// then stops itself.
+///
+/// Children are started and stopped with effects, and the restart intensity is measured with
+/// executor timers, so a supervisor can be tested in a `ProcessHarness` or a `Simulation`.
+pub struct Supervisor<T: Encodable + Decodable + Debug + Clone + Send + 'static> {
+    pid: Pid,
+    strategy: Strategy,
+    max_restarts: usize,
+    max_time: usize,
+    children: Vec<ChildSpec<T>>,
+
+    /// The number of times each child was started, and the number of `Msg::Down`s received for
+    /// it. Every start is monitored, so each instance of a child is reported down exactly once. A
+    /// `Msg::Down` is only for the running instance of a child if the counts are equal after it is
+    /// received. Any other `Msg::Down` is for an instance the supervisor already replaced.
+    starts: Vec<usize>,
+    downs: Vec<usize>,
+
+    /// The number of restarts in the last `max_time` ms. Each restart starts a timer that
+    /// decrements the count when it fires.
+    restarts: usize,
+
+    /// Set once the restart intensity was exceeded and the supervisor is stopping
+    stopped: bool,
+    executor_pid: Option<Pid>,
+    output: Vec<Envelope<T>>,
+    effects: Vec<Effect<T>>,
+    logger: slog::Logger
+}
+
+impl<T: Encodable + Decodable + Debug + Clone + Send + 'static> Supervisor<T> {
+    pub fn new(pid: Pid,
+               logger: slog::Logger,
+               strategy: Strategy,
+               max_restarts: usize,
+               max_time: usize,
+               children: Vec<ChildSpec<T>>) -> Supervisor<T>
+    {
+        let size = children.len();
+        Supervisor {
+            logger: logger.new(o!("component" => "supervisor", "pid" => pid.to_string())),
+            pid: pid,
+            strategy: strategy,
+            max_restarts: max_restarts,
+            max_time: max_time,
+            children: children,
+            starts: vec![0; size],
+            downs: vec![0; size],
+            restarts: 0,
+            stopped: false,
+            executor_pid: None,
+            output: Vec::with_capacity(size),
+            effects: Vec::with_capacity(size)
+        }
+    }
+
+    /// Start the children at `indexes` and monitor them.
+    ///
+    /// Effects are performed before the returned envelopes are sent, and the start request and
+    /// the monitor request reach the worker owning the child in order. The monitor is therefore
+    /// guaranteed to be installed on the new instance of the child.
+    fn start_children(&mut self, indexes: &[usize]) {
+        let executor_pid = self.executor_pid.as_ref().unwrap().clone();
+        for &i in indexes {
+            let child = &self.children[i];
+            self.starts[i] += 1;
+            self.effects.push(Effect::Spawn(child.pid.clone(), (child.factory)()));
+            let msg = Msg::Monitor(child.pid.clone());
+            self.output.push(Envelope::new(executor_pid.clone(), self.pid.clone(), msg, None));
+        }
+    }
+
+    /// Stop the children at `indexes` in reverse order
+    fn stop_children(&mut self, indexes: &[usize]) {
+        for &i in indexes.iter().rev() {
+            self.effects.push(Effect::Stop(self.children[i].pid.clone()));
+        }
+    }
+
+    /// Record a restart and return true if the restart intensity has been exceeded
+    fn intensity_exceeded(&mut self) -> bool {
+        self.restarts += 1;
+        let executor_pid = self.executor_pid.as_ref().unwrap().clone();
+        let msg = Msg::StartTimer(self.max_time);
+        self.output.push(Envelope::new(executor_pid, self.pid.clone(), msg, None));
+        self.restarts > self.max_restarts
+    }
+
+    fn restart(&mut self, index: usize) {
+        if self.intensity_exceeded() {
+            error!(self.logger, "Supervisor restart intensity exceeded. Shutting down.");
+            self.stopped = true;
+            let all: Vec<usize> = (0..self.children.len()).filter(|&i| i != index).collect();
+            self.stop_children(&all);
+            self.effects.push(Effect::Stop(self.pid.clone()));
+            return;
+        }
+
+        let to_restart: Vec<usize> = match self.strategy {
+            Strategy::OneForOne => vec![index],
+            Strategy::OneForAll => (0..self.children.len()).collect(),
+            Strategy::RestForOne => (index..self.children.len()).collect()
+        };
+        let to_stop: Vec<usize> = to_restart.iter().cloned().filter(|&i| i != index).collect();
+        self.stop_children(&to_stop);
+        self.start_children(&to_restart);
+    }
+}
+
+impl<T: Encodable + Decodable + Debug + Clone + Send + 'static> Process for Supervisor<T> {
+    type Msg = T;
+
+    fn init(&mut self, executor_pid: Pid) -> Vec<Envelope<T>> {
+        self.executor_pid = Some(executor_pid);
+        let all: Vec<usize> = (0..self.children.len()).collect();
+        self.start_children(&all);
+        self.output.drain(..).collect()
+    }
+
+    fn handle(&mut self,
+              msg: Msg<T>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<T>>
+    {
+        match msg {
+            Msg::Down(pid, reason) => {
+                let index = match self.children.iter().position(|child| child.pid == pid) {
+                    Some(index) => index,
+                    None => return &mut self.output
+                };
+                self.downs[index] += 1;
+                if self.stopped || self.downs[index] != self.starts[index] {
+                    return &mut self.output;
+                }
+                warn!(self.logger, "Supervised child exited";
+                      "child" => pid.to_string(), "reason" => format!("{:?}", reason));
+                self.restart(index);
+            },
+            Msg::Timeout => self.restarts -= 1,
+            _ => ()
+        }
+        &mut self.output
+    }
+
+    fn effects(&mut self) -> Option<&mut Vec<Effect<T>>> {
+        Some(&mut self.effects)
+    }
+}
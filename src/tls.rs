@@ -0,0 +1,178 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use rustls::{self, ClientConfig, ServerConfig, ClientConnection, ServerConnection, RootCertStore};
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls_pemfile::{self, Item};
+use webpki;
+use node_id::NodeId;
+use errors::*;
+
+/// Certificates used to encrypt and authenticate connections between nodes with TLS
+///
+/// All paths are to PEM files. Each node's certificate must be valid for the DNS name given by the
+/// `name` of its `NodeId`, and signed by the CA that all nodes in the cluster trust.
+///
+/// ```no_run
+/// let tls = rabble::TlsConfig::new("node1.pem", "node1.key", "ca.pem");
+/// let config = rabble::RabbleConfig::new().tls(tls);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsConfig {
+    /// The certificate chain of this node, starting with its own certificate
+    pub cert_chain: String,
+
+    /// The private key of this node's certificate
+    pub private_key: String,
+
+    /// The certificates of the CAs trusted to sign the certificates of other nodes
+    pub ca: String,
+
+    /// Whether connecting nodes must present a certificate as well. Without it, only the node
+    /// accepting a connection is authenticated.
+    pub verify_clients: bool
+}
+
+impl TlsConfig {
+    pub fn new(cert_chain: &str, private_key: &str, ca: &str) -> TlsConfig {
+        TlsConfig {
+            cert_chain: cert_chain.to_string(),
+            private_key: private_key.to_string(),
+            ca: ca.to_string(),
+            verify_clients: true
+        }
+    }
+
+    pub fn verify_clients(mut self, verify: bool) -> TlsConfig {
+        self.verify_clients = verify;
+        self
+    }
+
+    /// Load the certificates and private key and create the TLS configuration used for
+    /// connections
+    pub fn load(&self) -> Result<Tls> {
+        let certs = try!(read_pem(&self.cert_chain)).into_iter().filter_map(|item| match item {
+            Item::X509Certificate(cert) => Some(rustls::Certificate(cert)),
+            _ => None
+        }).collect::<Vec<_>>();
+        if certs.is_empty() {
+            return tls_error(format!("No certificates found in {}", self.cert_chain));
+        }
+
+        let key = try!(read_pem(&self.private_key)).into_iter().filter_map(|item| match item {
+            Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => {
+                Some(rustls::PrivateKey(key))
+            },
+            _ => None
+        }).next();
+        let key = match key {
+            Some(key) => key,
+            None => return tls_error(format!("No private key found in {}", self.private_key))
+        };
+
+        let mut roots = RootCertStore::empty();
+        for item in try!(read_pem(&self.ca)) {
+            if let Item::X509Certificate(cert) = item {
+                if let Err(e) = roots.add(&rustls::Certificate(cert)) {
+                    return tls_error(format!("Invalid CA certificate in {}: {}", self.ca, e));
+                }
+            }
+        }
+        if roots.is_empty() {
+            return tls_error(format!("No CA certificates found in {}", self.ca));
+        }
+
+        let client = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots.clone())
+            .with_client_auth_cert(certs.clone(), key.clone());
+        let client = match client {
+            Ok(client) => client,
+            Err(e) => return tls_error(format!("Invalid certificate or key: {}", e))
+        };
+
+        let server = ServerConfig::builder().with_safe_defaults();
+        let server = if self.verify_clients {
+            server.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+        } else {
+            server.with_no_client_auth()
+        };
+        let server = match server.with_single_cert(certs, key) {
+            Ok(server) => server,
+            Err(e) => return tls_error(format!("Invalid certificate or key: {}", e))
+        };
+
+        Ok(Tls {
+            client: Arc::new(client),
+            server: Arc::new(server),
+            verify_clients: self.verify_clients
+        })
+    }
+}
+
+/// Loaded TLS configuration used to create sessions for connections to other nodes
+pub struct Tls {
+    client: Arc<ClientConfig>,
+    server: Arc<ServerConfig>,
+    verify_clients: bool
+}
+
+impl Tls {
+    /// Create a session for a connection to `node`. The node's certificate must be valid for its
+    /// name.
+    pub fn client(&self, node: &NodeId) -> Result<rustls::Connection> {
+        let name = match rustls::ServerName::try_from(&node.name[..]) {
+            Ok(name) => name,
+            Err(_) => return tls_error(format!("Node name {} is not a valid DNS name", node.name))
+        };
+        match ClientConnection::new(self.client.clone(), name) {
+            Ok(session) => Ok(rustls::Connection::Client(session)),
+            Err(e) => tls_error(e.to_string())
+        }
+    }
+
+    /// Create a session for a connection accepted from another node
+    pub fn server(&self) -> Result<rustls::Connection> {
+        match ServerConnection::new(self.server.clone()) {
+            Ok(session) => Ok(rustls::Connection::Server(session)),
+            Err(e) => tls_error(e.to_string())
+        }
+    }
+
+    /// Return true if the peer of `session` presented a certificate that is valid for `node`.
+    ///
+    /// Peers without a certificate are only accepted if they connected to this node and
+    /// `verify_clients` is false.
+    pub fn verify_identity(&self, session: &rustls::Connection, node: &NodeId) -> bool {
+        let cert = match session.peer_certificates().and_then(|certs| certs.first()) {
+            Some(cert) => cert,
+            None => {
+                if let rustls::Connection::Server(_) = *session {
+                    return !self.verify_clients;
+                }
+                return false;
+            }
+        };
+        let name = match webpki::DnsNameRef::try_from_ascii_str(&node.name) {
+            Ok(name) => name,
+            Err(_) => return false
+        };
+        match webpki::EndEntityCert::try_from(&cert.0[..]) {
+            Ok(cert) => cert.verify_is_valid_for_subject_name(webpki::SubjectNameRef::DnsName(name))
+                            .is_ok(),
+            Err(_) => false
+        }
+    }
+}
+
+fn read_pem(path: &str) -> Result<Vec<Item>> {
+    let file = try!(File::open(path)
+                    .chain_err(|| ErrorKind::TlsError(format!("Failed to open {}", path))));
+    rustls_pemfile::read_all(&mut BufReader::new(file))
+        .chain_err(|| ErrorKind::TlsError(format!("Failed to read PEM file {}", path)))
+}
+
+fn tls_error<T>(reason: String) -> Result<T> {
+    Err(ErrorKind::TlsError(reason).into())
+}
@@ -0,0 +1,337 @@
+use std::io::{self, Read, Write};
+use std::cmp;
+use std::fmt;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use amy::{self, Registrar, Event};
+use node_id::NodeId;
+use super::{Transport, Stream};
+
+/// An in-process network that connects nodes with channels instead of sockets
+///
+/// Each node is given its own transport from the same network. Nodes listen on and connect to the
+/// `addr` of their `NodeId` as usual, but addresses are just names in the network, so any string
+/// can be used. This allows running many nodes in a single test process without binding ports.
+///
+/// ```no_run
+/// let network = rabble::MemoryNetwork::new();
+/// let node_id = rabble::NodeId {name: "node1".to_string(), addr: "node1".to_string()};
+/// let (node, handle) = rabble::NodeBuilder::new(node_id)
+///     .transport(network.transport())
+///     .build::<()>()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct MemoryNetwork {
+    listeners: Arc<Mutex<HashMap<String, Listener>>>
+}
+
+/// A node listening on a `MemoryNetwork`
+struct Listener {
+    registrar: Registrar,
+    tx: amy::Sender<MemoryStream<ChannelLink>>
+}
+
+impl MemoryNetwork {
+    pub fn new() -> MemoryNetwork {
+        MemoryNetwork {
+            listeners: Arc::new(Mutex::new(HashMap::new()))
+        }
+    }
+
+    /// Create a transport for a node on this network
+    pub fn transport(&self) -> MemoryTransport {
+        MemoryTransport {
+            network: self.clone(),
+            addr: None,
+            rx: None
+        }
+    }
+}
+
+/// The transport of a single node on a `MemoryNetwork`
+pub struct MemoryTransport {
+    network: MemoryNetwork,
+    addr: Option<String>,
+
+    /// Streams from nodes that connected to this one
+    rx: Option<amy::Receiver<MemoryStream<ChannelLink>>>
+}
+
+impl Transport for MemoryTransport {
+    fn listen(&mut self, addr: &str, registrar: &Registrar) -> io::Result<usize> {
+        let mut listeners = self.network.listeners.lock().unwrap();
+        if listeners.contains_key(addr) {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse,
+                                      format!("{} is already in use", addr)));
+        }
+        let (tx, rx) = try!(registrar.channel());
+        let id = rx.get_id();
+        listeners.insert(addr.to_string(), Listener {registrar: registrar.clone(), tx: tx});
+        self.addr = Some(addr.to_string());
+        self.rx = Some(rx);
+        Ok(id)
+    }
+
+    fn accept(&mut self) -> io::Result<Vec<Box<Stream>>> {
+        let mut streams = Vec::new();
+        if let Some(ref rx) = self.rx {
+            while let Ok(stream) = rx.try_recv() {
+                streams.push(Box::new(stream) as Box<Stream>);
+            }
+        }
+        Ok(streams)
+    }
+
+    fn connect(&mut self, node: &NodeId, registrar: &Registrar) -> io::Result<Box<Stream>> {
+        let listeners = self.network.listeners.lock().unwrap();
+        let listener = match listeners.get(&node.addr) {
+            Some(listener) => listener,
+            None => return Err(connection_refused(node))
+        };
+        // Each side reads from a channel registered with the poller of its own node
+        let (client_tx, client_rx) = try!(registrar.channel());
+        let (server_tx, server_rx) = try!(listener.registrar.channel());
+        let to_client = Pipe::new();
+        let to_server = Pipe::new();
+        let client = MemoryStream::new(to_client.clone(), ChannelLink {
+            outgoing: to_server.clone(),
+            rx: client_rx,
+            tx: server_tx.clone(),
+            wake: client_tx.clone()
+        });
+        let server = MemoryStream::new(to_server, ChannelLink {
+            outgoing: to_client,
+            rx: server_rx,
+            tx: client_tx,
+            wake: server_tx
+        });
+        if let Err(_) = listener.tx.send(server) {
+            return Err(connection_refused(node));
+        }
+        Ok(Box::new(client))
+    }
+}
+
+impl Drop for MemoryTransport {
+    fn drop(&mut self) {
+        if let Some(ref addr) = self.addr {
+            self.network.listeners.lock().unwrap().remove(addr);
+        }
+    }
+}
+
+fn connection_refused(node: &NodeId) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionRefused,
+                   format!("No node is listening on {}", node.addr))
+}
+
+/// The bytes sent in one direction of a `MemoryStream`
+pub(crate) struct Pipe {
+    data: VecDeque<u8>,
+    closed: bool
+}
+
+impl Pipe {
+    pub(crate) fn new() -> Arc<Mutex<Pipe>> {
+        Arc::new(Mutex::new(Pipe {
+            data: VecDeque::new(),
+            closed: false
+        }))
+    }
+
+    /// Append data written by the other end of the stream
+    pub(crate) fn push(&mut self, data: &[u8]) {
+        self.data.extend(data.iter());
+    }
+
+    /// Signal end of file after the data already in the pipe
+    pub(crate) fn close(&mut self) {
+        self.closed = true;
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Return true if a read wouldn't block
+    pub(crate) fn is_readable(&self) -> bool {
+        !self.data.is_empty() || self.closed
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.data.is_empty() {
+            if self.closed {
+                return Ok(0);
+            }
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "No data to read"));
+        }
+        let n = cmp::min(buf.len(), self.data.len());
+        for (dst, src) in buf.iter_mut().zip(self.data.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+/// How data written to a `MemoryStream` reaches the other end, and how the poller of the node
+/// owning the stream is notified
+///
+/// `ChannelLink` delivers data immediately and wakes pollers through amy channels. The simulated
+/// network schedules deliveries and notifications on its virtual clock instead.
+pub(crate) trait Link: fmt::Debug + Send {
+    /// Pass data written to the stream on to the other end
+    fn send(&mut self, data: &[u8]) -> io::Result<()>;
+
+    /// The stream was dropped. The other end reads the rest of the data followed by end of file.
+    fn close(&mut self);
+
+    /// Called before reading from the incoming pipe
+    fn consume_notifications(&self) {}
+
+    fn register(&self, incoming: &Mutex<Pipe>, registrar: &Registrar, event: Event)
+        -> io::Result<usize>;
+
+    fn reregister(&self, id: usize, registrar: &Registrar, event: Event) -> io::Result<()>;
+
+    fn deregister(&self, registrar: &Registrar) -> io::Result<()>;
+}
+
+/// One end of an in-process connection
+///
+/// Data is read from a pipe filled by the other end. Writes never block.
+pub(crate) struct MemoryStream<L: Link> {
+    incoming: Arc<Mutex<Pipe>>,
+    link: L
+}
+
+impl<L: Link> MemoryStream<L> {
+    pub(crate) fn new(incoming: Arc<Mutex<Pipe>>, link: L) -> MemoryStream<L> {
+        MemoryStream {
+            incoming: incoming,
+            link: link
+        }
+    }
+}
+
+impl<L: Link> fmt::Debug for MemoryStream<L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MemoryStream {{ link: {:?} }}", self.link)
+    }
+}
+
+impl<L: Link> Read for MemoryStream<L> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.link.consume_notifications();
+        self.incoming.lock().unwrap().read(buf)
+    }
+}
+
+impl<L: Link> Write for MemoryStream<L> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        try!(self.link.send(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<L: Link> Stream for MemoryStream<L> {
+    fn register(&self, registrar: &Registrar, event: Event) -> io::Result<usize> {
+        self.link.register(&self.incoming, registrar, event)
+    }
+
+    fn reregister(&self, id: usize, registrar: &Registrar, event: Event) -> io::Result<()> {
+        self.link.reregister(id, registrar, event)
+    }
+
+    fn deregister(&self, registrar: &Registrar) -> io::Result<()> {
+        self.link.deregister(registrar)
+    }
+}
+
+impl<L: Link> Drop for MemoryStream<L> {
+    fn drop(&mut self) {
+        self.incoming.lock().unwrap().close();
+        self.link.close();
+    }
+}
+
+/// Connects the ends of a stream on a `MemoryNetwork`
+///
+/// Data is written straight to the incoming pipe of the peer, and the poller of the peer is woken
+/// up through an amy channel. Since the poller only reports data to read, a registration for write
+/// events is reported once as a read event.
+struct ChannelLink {
+    outgoing: Arc<Mutex<Pipe>>,
+
+    /// Notifications that data was written to the incoming pipe
+    rx: amy::Receiver<()>,
+
+    /// Notify the peer that data was written to `outgoing`
+    tx: amy::Sender<()>,
+
+    /// Notify this end of the stream
+    wake: amy::Sender<()>
+}
+
+impl ChannelLink {
+    fn wake(&self, event: Event) -> io::Result<()> {
+        if let Event::Read = event {
+            return Ok(());
+        }
+        self.wake.send(()).map_err(|_| io::Error::new(io::ErrorKind::Other, "Failed to wake"))
+    }
+}
+
+impl fmt::Debug for ChannelLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ChannelLink {{ id: {} }}", self.rx.get_id())
+    }
+}
+
+impl Link for ChannelLink {
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        {
+            let mut pipe = self.outgoing.lock().unwrap();
+            if pipe.is_closed() {
+                return Err(broken_pipe());
+            }
+            pipe.push(data);
+        }
+        self.tx.send(()).map_err(|_| broken_pipe())
+    }
+
+    fn close(&mut self) {
+        // The peer fails to write, since the incoming pipe was closed as well
+        self.outgoing.lock().unwrap().close();
+        let _ = self.tx.send(());
+    }
+
+    fn consume_notifications(&self) {
+        // Consume notifications before checking for data, so that data written afterwards always
+        // triggers a new notification
+        while let Ok(()) = self.rx.try_recv() {}
+    }
+
+    fn register(&self, _: &Mutex<Pipe>, _: &Registrar, event: Event) -> io::Result<usize> {
+        // The receiver was registered with the poller when it was created
+        try!(self.wake(event));
+        Ok(self.rx.get_id())
+    }
+
+    fn reregister(&self, _: usize, _: &Registrar, event: Event) -> io::Result<()> {
+        self.wake(event)
+    }
+
+    fn deregister(&self, _: &Registrar) -> io::Result<()> {
+        // The receiver is deregistered when the stream is dropped
+        Ok(())
+    }
+}
+
+fn broken_pipe() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "The other end of the stream was closed")
+}
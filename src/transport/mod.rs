@@ -0,0 +1,87 @@
+//! The transports that carry messages between nodes
+//!
+//! The cluster server doesn't talk to the network directly. It listens, accepts and connects
+//! through a `Transport`, and reads and writes the resulting `Stream`s. Streams must be pollable by
+//! the amy poller of the node, so that the cluster server is notified when they become readable or
+//! writable, and they must be non-blocking.
+
+mod tcp;
+pub(crate) mod memory;
+
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::thread::JoinHandle;
+use amy::{Registrar, Event};
+use node_id::NodeId;
+
+pub use self::tcp::TcpTransport;
+pub use self::memory::{MemoryNetwork, MemoryTransport};
+
+/// A non-blocking, bidirectional byte stream between two nodes
+///
+/// Reads and writes that can't make progress must return `io::ErrorKind::WouldBlock`. A read
+/// returning 0 bytes means the peer closed the stream.
+pub trait Stream: Read + Write + Send {
+    /// Register the stream with the poller of the node, and return the id of notifications about
+    /// it
+    fn register(&self, registrar: &Registrar, event: Event) -> io::Result<usize>;
+
+    /// Change the events the stream is registered for
+    fn reregister(&self, id: usize, registrar: &Registrar, event: Event) -> io::Result<()>;
+
+    /// Remove the stream from the poller before it's closed
+    fn deregister(&self, registrar: &Registrar) -> io::Result<()>;
+}
+
+/// The means by which nodes connect to each other
+///
+/// `TcpTransport` is used unless another transport is given to `NodeBuilder::transport`.
+pub trait Transport: Send {
+    /// Start listening for connections from other nodes on `addr`. Return the id of the
+    /// notifications the poller delivers when connections are pending.
+    fn listen(&mut self, addr: &str, registrar: &Registrar) -> io::Result<usize>;
+
+    /// Accept all pending connections
+    fn accept(&mut self) -> io::Result<Vec<Box<Stream>>>;
+
+    /// Start any threads the transport needs, before the node starts polling. `registrar` belongs
+    /// to the poller of `node`.
+    ///
+    /// Return the handles of the threads, which the node joins when it shuts down. The threads must
+    /// exit once the transport is dropped.
+    fn start(&mut self, _node: &NodeId, _registrar: &Registrar)
+        -> io::Result<Vec<JoinHandle<()>>>
+    {
+        Ok(Vec::new())
+    }
+
+    /// Start connecting to `node`. The connection may still be in progress when the stream is
+    /// returned. Errors must be returned by reads and writes of the stream in that case.
+    ///
+    /// Return an `io::ErrorKind::WouldBlock` error if the connection can't be started yet, such as
+    /// while the address of `node` is looked up. The cluster server connects again once `ready`
+    /// returns the node, or on its next tick.
+    fn connect(&mut self, node: &NodeId, registrar: &Registrar) -> io::Result<Box<Stream>>;
+
+    /// Return the nodes that `connect` returned `io::ErrorKind::WouldBlock` for, and that can be
+    /// connected to now. The transport must wake the poller when there are any, using a
+    /// notification registered with the registrar given to `start`.
+    fn ready(&mut self) -> Vec<NodeId> {
+        Vec::new()
+    }
+
+    /// A connection returned by `connect` was established with `node`
+    fn established(&mut self, _node: &NodeId) {}
+
+    /// `node` left the cluster, and won't be connected to again unless it rejoins
+    fn node_left(&mut self, _node: &NodeId) {}
+}
+
+/// A raw file descriptor, which amy needs to deregister a socket
+struct Fd(RawFd);
+
+impl AsRawFd for Fd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
@@ -0,0 +1,339 @@
+use std::io;
+use std::fs;
+use std::thread::{self, JoinHandle};
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream, SocketAddr};
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{mpsc, Arc, Mutex};
+use libc::{self, EINPROGRESS};
+use net2::{TcpBuilder, TcpStreamExt};
+use amy::{self, Registrar, Event};
+use node_id::{self, NodeId};
+use super::{Transport, Stream, Fd};
+
+/// Connect nodes over TCP, or over Unix domain sockets for addresses of the form `unix:/path`
+///
+/// Hostnames are looked up on a separate thread, so that a slow DNS server doesn't block the
+/// cluster server. The thread is started by `start` and exits when the transport is dropped. Each
+/// connection attempt uses the addresses found by the last completed lookup and starts a new one,
+/// so that a node whose address changed is found when reconnecting. The first attempt to connect
+/// to a hostname waits for its lookup to complete, and the node is returned by `ready` once it
+/// has. If a node has multiple addresses, each attempt that doesn't lead to an established
+/// connection moves on to the next address. Addresses that fail immediately are skipped.
+///
+/// A Unix domain socket left behind by a node that didn't shut down cleanly is replaced when
+/// listening, as long as no node accepts connections on it anymore.
+pub struct TcpTransport {
+    listener: Option<Listener>,
+
+    /// Created by `start`
+    resolver: Option<Resolver>,
+
+    /// The index of the resolved address of each node used by the last connection attempt, and
+    /// whether that connection was established
+    addr_index: HashMap<NodeId, (usize, bool)>
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, String)
+}
+
+impl TcpTransport {
+    pub fn new() -> TcpTransport {
+        TcpTransport {
+            listener: None,
+            resolver: None,
+            addr_index: HashMap::new()
+        }
+    }
+
+    fn connect_tcp(&mut self, node: &NodeId) -> io::Result<Box<Stream>> {
+        let addrs = match node.addr.parse::<SocketAddr>() {
+            Ok(addr) => vec![addr],
+            Err(_) => {
+                match self.resolver {
+                    Some(ref resolver) => try!(resolver.resolve(node)),
+                    None => return Err(io::Error::new(io::ErrorKind::NotConnected,
+                                                      "The transport wasn't started"))
+                }
+            }
+        };
+        if addrs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound,
+                                      format!("{} has no addresses", node.addr)));
+        }
+        let start = match self.addr_index.get(node) {
+            Some(&(i, true)) => i,
+            Some(&(i, false)) => i + 1,
+            None => 0
+        };
+        let mut error = None;
+        for i in start..start + addrs.len() {
+            match connect_addr(&addrs[i % addrs.len()]) {
+                Ok(sock) => {
+                    self.addr_index.insert(node.clone(), (i % addrs.len(), false));
+                    return Ok(Box::new(sock));
+                },
+                Err(e) => error = Some(e)
+            }
+        }
+        Err(error.unwrap())
+    }
+}
+
+impl Transport for TcpTransport {
+    fn listen(&mut self, addr: &str, registrar: &Registrar) -> io::Result<usize> {
+        let (id, listener) = match node_id::unix_socket_path(addr) {
+            Some(path) => {
+                let listener = try!(bind_unix(path));
+                try!(listener.set_nonblocking(true));
+                let id = try!(registrar.register(&listener, Event::Read));
+                (id, Listener::Unix(listener, path.to_string()))
+            },
+            None => {
+                let listener = try!(TcpListener::bind(addr));
+                try!(listener.set_nonblocking(true));
+                let id = try!(registrar.register(&listener, Event::Read));
+                (id, Listener::Tcp(listener))
+            }
+        };
+        self.listener = Some(listener);
+        Ok(id)
+    }
+
+    fn accept(&mut self) -> io::Result<Vec<Box<Stream>>> {
+        let mut streams = Vec::new();
+        match self.listener {
+            Some(Listener::Tcp(ref listener)) => {
+                while let Ok((sock, _)) = listener.accept() {
+                    try!(sock.set_nonblocking(true));
+                    streams.push(Box::new(sock) as Box<Stream>);
+                }
+            },
+            Some(Listener::Unix(ref listener, _)) => {
+                while let Ok((sock, _)) = listener.accept() {
+                    try!(sock.set_nonblocking(true));
+                    streams.push(Box::new(sock) as Box<Stream>);
+                }
+            },
+            None => ()
+        }
+        Ok(streams)
+    }
+
+    fn start(&mut self, node: &NodeId, registrar: &Registrar) -> io::Result<Vec<JoinHandle<()>>> {
+        let (resolver, handle) = try!(Resolver::start(node, registrar));
+        self.resolver = Some(resolver);
+        Ok(vec![handle])
+    }
+
+    fn connect(&mut self, node: &NodeId, _: &Registrar) -> io::Result<Box<Stream>> {
+        match node_id::unix_socket_path(&node.addr) {
+            Some(path) => Ok(Box::new(try!(connect_unix(path)))),
+            None => self.connect_tcp(node)
+        }
+    }
+
+    fn established(&mut self, node: &NodeId) {
+        // Reconnect to the address that worked first
+        if let Some(entry) = self.addr_index.get_mut(node) {
+            entry.1 = true;
+        }
+    }
+
+    fn ready(&mut self) -> Vec<NodeId> {
+        self.resolver.as_ref().map_or(Vec::new(), |resolver| resolver.ready())
+    }
+
+    fn node_left(&mut self, node: &NodeId) {
+        self.addr_index.remove(node);
+        if let Some(ref resolver) = self.resolver {
+            resolver.forget(node);
+        }
+    }
+}
+
+impl Drop for TcpTransport {
+    fn drop(&mut self) {
+        if let Some(Listener::Unix(_, ref path)) = self.listener {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Looks up the addresses of nodes on its own thread, which exits when the resolver is dropped
+struct Resolver {
+    requests: mpsc::Sender<NodeId>,
+    lookups: Arc<Mutex<HashMap<NodeId, Lookup>>>,
+
+    /// Receives the nodes whose first lookup completed, and wakes the poller
+    ready: amy::Receiver<NodeId>
+}
+
+#[derive(Default)]
+struct Lookup {
+    /// The result of the last completed lookup
+    result: Option<Result<Vec<SocketAddr>, String>>,
+
+    /// Whether a lookup is in progress
+    pending: bool
+}
+
+impl Resolver {
+    /// Start the thread of the resolver for `node`, and return the resolver along with the handle
+    /// of the thread
+    fn start(node: &NodeId, registrar: &Registrar) -> io::Result<(Resolver, JoinHandle<()>)> {
+        let (tx, rx) = mpsc::channel::<NodeId>();
+        let (ready_tx, ready_rx) = try!(registrar.channel());
+        let lookups: Arc<Mutex<HashMap<NodeId, Lookup>>> = Arc::new(Mutex::new(HashMap::new()));
+        let results = lookups.clone();
+        let name = format!("resolver::{}", node);
+        let handle = try!(thread::Builder::new().name(name).spawn(move || {
+            for node in rx {
+                let result = node.resolve().map_err(|e| e.to_string());
+                // The node may have been forgotten while it was looked up
+                if let Some(lookup) = results.lock().unwrap().get_mut(&node) {
+                    if lookup.result.is_none() {
+                        // The cluster server is waiting for the first lookup to connect
+                        let _ = ready_tx.send(node.clone());
+                    }
+                    lookup.result = Some(result);
+                    lookup.pending = false;
+                }
+            }
+        }));
+        let resolver = Resolver {
+            requests: tx,
+            lookups: lookups,
+            ready: ready_rx
+        };
+        Ok((resolver, handle))
+    }
+
+    /// Return the addresses of `node` found by the last completed lookup, and start a new lookup
+    /// unless one is in progress. Return an `io::ErrorKind::WouldBlock` error if no lookup has
+    /// completed yet.
+    fn resolve(&self, node: &NodeId) -> io::Result<Vec<SocketAddr>> {
+        let mut lookups = self.lookups.lock().unwrap();
+        let lookup = lookups.entry(node.clone()).or_insert_with(Lookup::default);
+        if !lookup.pending {
+            lookup.pending = self.requests.send(node.clone()).is_ok();
+        }
+        match lookup.result {
+            Some(Ok(ref addrs)) => Ok(addrs.clone()),
+            Some(Err(ref e)) => Err(io::Error::new(io::ErrorKind::NotFound, e.clone())),
+            None => Err(io::Error::new(io::ErrorKind::WouldBlock,
+                                       format!("Looking up the address of {}", node.addr)))
+        }
+    }
+
+    /// Return the nodes whose first lookup completed since the last call
+    fn ready(&self) -> Vec<NodeId> {
+        let mut nodes = Vec::new();
+        while let Ok(node) = self.ready.try_recv() {
+            nodes.push(node);
+        }
+        nodes
+    }
+
+    fn forget(&self, node: &NodeId) {
+        self.lookups.lock().unwrap().remove(node);
+    }
+}
+
+/// Bind a Unix domain socket, replacing the socket file of a node that is no longer running
+fn bind_unix(path: &str) -> io::Result<UnixListener> {
+    match UnixListener::bind(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::AddrInUse => {
+            if let Err(e) = UnixStream::connect(path) {
+                if e.kind() == io::ErrorKind::ConnectionRefused {
+                    try!(fs::remove_file(path));
+                    return UnixListener::bind(path);
+                }
+            }
+            Err(io::Error::new(io::ErrorKind::AddrInUse, format!("{} is already in use", path)))
+        },
+        result => result
+    }
+}
+
+/// Start a non-blocking connection to `addr`
+fn connect_addr(addr: &SocketAddr) -> io::Result<TcpStream> {
+    let sock = if addr.is_ipv4() { try!(TcpBuilder::new_v4()) } else { try!(TcpBuilder::new_v6()) };
+    let sock = try!(sock.to_tcp_stream());
+    try!(sock.set_nonblocking(true));
+    if let Err(e) = sock.connect(addr) {
+        if e.raw_os_error().is_some() && *e.raw_os_error().as_ref().unwrap() != EINPROGRESS {
+            return Err(e);
+        }
+    }
+    Ok(sock)
+}
+
+/// Start a non-blocking connection to the Unix domain socket at `path`
+///
+/// std can only connect Unix domain sockets in blocking mode, which would block the cluster server
+/// while the backlog of the listening node is full. A full backlog returns an
+/// `io::ErrorKind::WouldBlock` error instead, and the connection is retried on the next tick.
+fn connect_unix(path: &str) -> io::Result<UnixStream> {
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    if path.len() >= addr.sun_path.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("{} is too long for a Unix domain socket", path)));
+    }
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    for (dst, src) in addr.sun_path.iter_mut().zip(path.as_bytes()) {
+        *dst = *src as libc::c_char;
+    }
+    let len = mem::size_of::<libc::sa_family_t>() + path.len() + 1;
+
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_STREAM, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // The stream owns the socket from here on, and closes it if connecting fails
+    let sock = unsafe { UnixStream::from_raw_fd(fd) };
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    try!(sock.set_nonblocking(true));
+    let addr = &addr as *const libc::sockaddr_un as *const libc::sockaddr;
+    if unsafe { libc::connect(fd, addr, len as libc::socklen_t) } < 0 {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() != Some(EINPROGRESS) {
+            return Err(e);
+        }
+    }
+    Ok(sock)
+}
+
+impl Stream for TcpStream {
+    fn register(&self, registrar: &Registrar, event: Event) -> io::Result<usize> {
+        registrar.register(self, event)
+    }
+
+    fn reregister(&self, id: usize, registrar: &Registrar, event: Event) -> io::Result<()> {
+        registrar.reregister(id, self, event)
+    }
+
+    fn deregister(&self, registrar: &Registrar) -> io::Result<()> {
+        registrar.deregister(Fd(self.as_raw_fd()))
+    }
+}
+
+impl Stream for UnixStream {
+    fn register(&self, registrar: &Registrar, event: Event) -> io::Result<usize> {
+        registrar.register(self, event)
+    }
+
+    fn reregister(&self, id: usize, registrar: &Registrar, event: Event) -> io::Result<()> {
+        registrar.reregister(id, self, event)
+    }
+
+    fn deregister(&self, registrar: &Registrar) -> io::Result<()> {
+        registrar.deregister(Fd(self.as_raw_fd()))
+    }
+}
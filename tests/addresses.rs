@@ -0,0 +1,88 @@
+//! Test parsing node ids and connecting to nodes with IPv6 and hostname addresses
+
+extern crate amy;
+extern crate rabble;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use std::str::FromStr;
+
+use utils::start_service;
+
+use rabble::{
+    Pid,
+    NodeId,
+    NodeBuilder,
+    Envelope,
+    Msg
+};
+
+/// Join two nodes and send a message each way between them
+fn exchange_messages(node_id1: NodeId, node_id2: NodeId) {
+    let (node1, handle1) = NodeBuilder::new(node_id1).build::<u64>().unwrap();
+    let (node2, handle2) = NodeBuilder::new(node_id2).build::<u64>().unwrap();
+
+    let pid1 = Pid {name: "sender".to_string(), group: None, node: node1.id.clone()};
+    let (rx1, h1) = start_service(pid1.clone(), &node1);
+    let pid2 = Pid {name: "receiver".to_string(), group: None, node: node2.id.clone()};
+    let (rx2, h2) = start_service(pid2.clone(), &node2);
+
+    node1.join(&node2.id).unwrap();
+    node1.send(Envelope::new(pid2.clone(), pid1.clone(), Msg::User(1), None)).unwrap();
+    assert_eq!(rx2.recv().unwrap(), Msg::User(1));
+    node2.send(Envelope::new(pid1.clone(), pid2.clone(), Msg::User(2), None)).unwrap();
+    assert_eq!(rx1.recv().unwrap(), Msg::User(2));
+
+    handle1.shutdown_and_join().unwrap();
+    handle2.shutdown_and_join().unwrap();
+    h1.join().unwrap();
+    h2.join().unwrap();
+}
+
+#[test]
+fn parse_node_ids_and_pids() {
+    let node = NodeId::from_str("node1@[::1]:11000").unwrap();
+    assert_eq!(node, NodeId {name: "node1".to_string(), addr: "[::1]:11000".to_string()});
+    let node = NodeId::from_str("user@example.com@db1.example.com:11000").unwrap();
+    assert_eq!(node.name, "user@example.com");
+    assert_eq!(node.addr, "db1.example.com:11000");
+
+    for invalid in &["node1", "@127.0.0.1:11000", "node1@127.0.0.1", "node1@::1:11000",
+                     "node1@[::1]", "node1@[::1:11000", "node1@127.0.0.1:port"] {
+        assert!(NodeId::from_str(invalid).is_err(), "{} should be invalid", invalid);
+    }
+
+    let pid = Pid {
+        group: Some("group".to_string()),
+        name: "name".to_string(),
+        node: NodeId {name: "node1".to_string(), addr: "[fe80::1]:11000".to_string()}
+    };
+    assert_eq!(Pid::from_str(&pid.to_string()).unwrap(), pid);
+    let pid = Pid {group: None, ..pid};
+    assert_eq!(Pid::from_str(&pid.to_string()).unwrap(), pid);
+    assert!(Pid::from_str("name@[::1]:11000").is_err());
+    assert!(Pid::from_str("a::b::c::node1@[::1]:11000").is_err());
+}
+
+#[test]
+fn nodes_connect_over_ipv6() {
+    exchange_messages(NodeId {name: "node1".to_string(), addr: "[::1]:11190".to_string()},
+                      NodeId {name: "node2".to_string(), addr: "[::1]:11191".to_string()});
+}
+
+#[test]
+fn nodes_connect_by_hostname() {
+    exchange_messages(NodeId {name: "node1".to_string(), addr: "localhost:11192".to_string()},
+                      NodeId {name: "node2".to_string(), addr: "localhost:11193".to_string()});
+}
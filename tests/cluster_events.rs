@@ -0,0 +1,110 @@
+//! Test subscribing to cluster membership and connection events
+
+extern crate amy;
+extern crate rabble;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use rabble::{
+    Pid,
+    NodeId,
+    Node,
+    Envelope,
+    Msg,
+    ClusterEvent,
+    Service,
+    ThreadHandler
+};
+
+/// Start a service on `node` that subscribes to cluster events and forwards them to the returned
+/// receiver
+fn subscribe(node: &Node<()>) -> (Pid, amy::Sender<Envelope<()>>, thread::JoinHandle<()>,
+                                  mpsc::Receiver<ClusterEvent>)
+{
+    let service_pid = Pid {name: "test-service".to_string(), group: None, node: node.id.clone()};
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<()>| {
+        if let Msg::ClusterEvent(event) = envelope.msg {
+            tx.send(event).unwrap();
+        }
+    });
+    let mut service = Service::new(service_pid.clone(), node.clone(), handler).unwrap();
+    let service_tx = service.tx.clone();
+    let h = thread::spawn(move || {
+        service.wait();
+    });
+    node.subscribe_cluster_events(&service_pid).unwrap();
+    (service_pid, service_tx, h, rx)
+}
+
+#[test]
+fn subscribers_receive_cluster_events() {
+    let node_ids: Vec<_> = (1..3).map(|i| {
+        NodeId {name: format!("node{}", i), addr: format!("127.0.0.1:1108{}", i)}
+    }).collect();
+    let (node1, mut handles) = rabble::rouse::<()>(node_ids[0].clone(), None);
+    let (node2, handles2) = rabble::rouse::<()>(node_ids[1].clone(), None);
+    handles.extend(handles2);
+
+    let (service_pid, service_tx, h, rx) = subscribe(&node1);
+
+    node1.join(&node2.id).unwrap();
+    assert_eq!(rx.recv().unwrap(), ClusterEvent::NodeJoined(node2.id.clone()));
+    assert_eq!(rx.recv().unwrap(), ClusterEvent::ConnectionEstablished(node2.id.clone()));
+
+    node1.leave(&node2.id).unwrap();
+    assert_eq!(rx.recv().unwrap(), ClusterEvent::NodeLeft(node2.id.clone()));
+    assert_eq!(rx.recv().unwrap(), ClusterEvent::ConnectionLost(node2.id.clone()));
+
+    service_tx.send(Envelope::new(service_pid.clone(), service_pid, Msg::Shutdown, None)).unwrap();
+    h.join().unwrap();
+    node1.shutdown();
+    node2.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+#[test]
+fn nodes_join_before_their_connections_are_established() {
+    let node_ids: Vec<_> = (5..8).map(|i| {
+        NodeId {name: format!("node{}", i), addr: format!("127.0.0.1:1108{}", i)}
+    }).collect();
+    let (node1, mut handles) = rabble::rouse::<()>(node_ids[0].clone(), None);
+    let (node2, handles2) = rabble::rouse::<()>(node_ids[1].clone(), None);
+    let (node3, handles3) = rabble::rouse::<()>(node_ids[2].clone(), None);
+    handles.extend(handles2);
+    handles.extend(handles3);
+
+    // Node3 learns about node1 and node2 from the members sent by node1
+    let (_, _, h, rx) = subscribe(&node3);
+    node1.join(&node2.id).unwrap();
+    node1.join(&node3.id).unwrap();
+
+    let mut events = Vec::new();
+    while events.iter().filter(|e| match **e {
+        ClusterEvent::ConnectionEstablished(_) => true,
+        _ => false
+    }).count() < 2 {
+        events.push(rx.recv_timeout(Duration::from_secs(5)).unwrap());
+    }
+    for node in &[&node1.id, &node2.id] {
+        let joined = ClusterEvent::NodeJoined((*node).clone());
+        let established = ClusterEvent::ConnectionEstablished((*node).clone());
+        let joined = events.iter().position(|e| *e == joined);
+        let established = events.iter().position(|e| *e == established);
+        assert!(joined.is_some());
+        assert!(joined < established, "{:?}", events);
+    }
+
+    // Node3 keeps publishing events until it stops, and stops the service when it shuts down
+    node1.shutdown();
+    node2.shutdown();
+    node3.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+    h.join().unwrap();
+}
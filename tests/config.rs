@@ -0,0 +1,79 @@
+//! Test starting nodes with a `RabbleConfig`
+
+extern crate amy;
+extern crate rabble;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use utils::wait_for_connections;
+
+use rabble::{
+    NodeId,
+    RabbleConfig,
+    FailureDetectorConfig
+};
+use rabble::errors::ErrorKind;
+
+#[test]
+fn invalid_configs_are_rejected() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11100".to_string()};
+    let configs = vec![
+        RabbleConfig::new().num_workers(0),
+        RabbleConfig::new().tick_time(0),
+        RabbleConfig::new().service_poll_timeout(0),
+        RabbleConfig::new().listen_addr("not an address"),
+        RabbleConfig::new().failure_detector(FailureDetectorConfig {
+            threshold: -1.0,
+            ..FailureDetectorConfig::default()
+        }),
+        RabbleConfig::new().failure_detector(FailureDetectorConfig {
+            min_std_deviation: 0,
+            ..FailureDetectorConfig::default()
+        }),
+        // The default first_heartbeat_estimate is shorter than the tick
+        RabbleConfig::new().tick_time(2000)
+    ];
+    for config in configs {
+        let result = rabble::rouse_with_config::<()>(node_id.clone(), config, None);
+        assert_matches!(result.map(|_| ()).unwrap_err().kind(), &ErrorKind::InvalidConfig(_));
+    }
+}
+
+#[test]
+fn nodes_can_listen_on_a_different_address_than_they_advertise() {
+    let config = RabbleConfig::new()
+        .num_workers(2)
+        .listen_addr("0.0.0.0:11101")
+        .tick_time(200)
+        .executor_tick_time(10)
+        .service_poll_timeout(100);
+    let node_id1 = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11101".to_string()};
+    let node_id2 = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11102".to_string()};
+    let (node1, mut handles) =
+        rabble::rouse_with_config::<()>(node_id1, config.clone(), None).unwrap();
+    let (node2, handles2) =
+        rabble::rouse_with_config::<()>(node_id2, RabbleConfig::new(), None).unwrap();
+    handles.extend(handles2);
+    assert_eq!(node1.config, config);
+
+    node2.join(&node1.id).unwrap();
+    assert!(wait_for_connections(&node1, 1));
+    assert!(wait_for_connections(&node2, 1));
+
+    node1.shutdown();
+    node2.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
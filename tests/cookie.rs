@@ -0,0 +1,106 @@
+//! Test cookie authentication between nodes
+
+extern crate amy;
+extern crate rabble;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+use utils::start_service;
+
+use rabble::{
+    Pid,
+    NodeId,
+    NodeBuilder,
+    RabbleConfig,
+    Envelope,
+    Msg,
+    Metric
+};
+
+#[test]
+fn nodes_with_the_same_cookie_connect() {
+    let node_id1 = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11170".to_string()};
+    let node_id2 = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11171".to_string()};
+    let config = RabbleConfig::new().cookie("secret");
+    let (node1, handle1) = NodeBuilder::new(node_id1).config(config.clone()).build::<u64>().unwrap();
+    let (node2, handle2) = NodeBuilder::new(node_id2).config(config).build::<u64>().unwrap();
+
+    let pid1 = Pid {name: "sender".to_string(), group: None, node: node1.id.clone()};
+    let (rx1, h1) = start_service(pid1.clone(), &node1);
+    let pid2 = Pid {name: "receiver".to_string(), group: None, node: node2.id.clone()};
+    let (rx2, h2) = start_service(pid2.clone(), &node2);
+
+    node1.join(&node2.id).unwrap();
+    node1.send(Envelope::new(pid2.clone(), pid1.clone(), Msg::User(1), None)).unwrap();
+    assert_eq!(rx2.recv().unwrap(), Msg::User(1));
+    node2.send(Envelope::new(pid1.clone(), pid2.clone(), Msg::User(2), None)).unwrap();
+    assert_eq!(rx1.recv().unwrap(), Msg::User(2));
+
+    handle1.shutdown_and_join().unwrap();
+    handle2.shutdown_and_join().unwrap();
+    h1.join().unwrap();
+    h2.join().unwrap();
+}
+
+#[test]
+fn unauthenticated_nodes_cannot_join() {
+    let node_id1 = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11172".to_string()};
+    let config = RabbleConfig::new().cookie("secret").tick_time(100);
+    let (node1, handle1) = NodeBuilder::new(node_id1).config(config).build::<u64>().unwrap();
+    let pid1 = Pid {name: "events".to_string(), group: None, node: node1.id.clone()};
+    let (rx1, h1) = start_service(pid1.clone(), &node1);
+    node1.subscribe_cluster_events(&pid1).unwrap();
+
+    // Node2 knows the wrong cookie, and node3 doesn't use a cookie at all
+    let node_id2 = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11173".to_string()};
+    let config = RabbleConfig::new().cookie("wrong").tick_time(100);
+    let (node2, handle2) = NodeBuilder::new(node_id2).config(config).build::<u64>().unwrap();
+    let node_id3 = NodeId {name: "node3".to_string(), addr: "127.0.0.1:11174".to_string()};
+    let config = RabbleConfig::new().tick_time(100);
+    let (node3, handle3) = NodeBuilder::new(node_id3).config(config).build::<u64>().unwrap();
+    node2.join(&node1.id).unwrap();
+    node3.join(&node1.id).unwrap();
+
+    // Neither node is ever added to the membership of node1
+    match rx1.recv_timeout(Duration::from_secs(1)) {
+        Err(RecvTimeoutError::Timeout) => (),
+        result => panic!("Expected no cluster events, got {:?}", result)
+    }
+
+    let cluster_server = Pid {
+        name: "cluster_server".to_string(),
+        group: Some("rabble".to_string()),
+        node: node1.id.clone()
+    };
+    node1.send(Envelope::new(cluster_server, pid1.clone(), Msg::GetMetrics, None)).unwrap();
+    match rx1.recv().unwrap() {
+        Msg::Metrics(metrics) => {
+            let failures = metrics.iter().find(|&&(ref name, _)| name == "auth_failures")
+                .map(|m| m.1.clone());
+            match failures {
+                Some(Metric::Counter(count)) => assert!(count >= 2),
+                metric => panic!("Expected auth_failures counter, got {:?}", metric)
+            }
+        },
+        msg => panic!("Expected metrics, got {:?}", msg)
+    }
+
+    handle1.shutdown_and_join().unwrap();
+    handle2.shutdown_and_join().unwrap();
+    handle3.shutdown_and_join().unwrap();
+    h1.join().unwrap();
+}
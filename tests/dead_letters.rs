@@ -0,0 +1,102 @@
+//! Test reporting undeliverable envelopes to a dead letter pid
+
+extern crate rabble;
+
+use std::sync::mpsc;
+use std::thread;
+
+use rabble::{
+    Pid,
+    NodeId,
+    NodeBuilder,
+    RabbleConfig,
+    Envelope,
+    Msg,
+    Metric,
+    CorrelationId,
+    DeliveryFailure,
+    Service,
+    ThreadHandler
+};
+
+#[test]
+fn envelopes_to_unknown_pids_are_dead_letters() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11140".to_string()};
+    let dead_letters = Pid {name: "dead_letters".to_string(), group: None, node: node_id.clone()};
+    let config = RabbleConfig::new()
+        .dead_letters(dead_letters.clone())
+        .notify_undeliverable(true);
+    let (node, handle) = NodeBuilder::new(node_id).config(config).build::<u64>().unwrap();
+
+    let (dead_tx, dead_rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<u64>| {
+        dead_tx.send(envelope.msg).unwrap();
+    });
+    let mut dead_letter_service = Service::new(dead_letters, node.clone(), handler).unwrap();
+    let h1 = thread::spawn(move || dead_letter_service.wait());
+
+    let sender = Pid {name: "sender".to_string(), group: None, node: node.id.clone()};
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<u64>| {
+        tx.send(envelope).unwrap();
+    });
+    let mut sender_service = Service::new(sender.clone(), node.clone(), handler).unwrap();
+    let h2 = thread::spawn(move || sender_service.wait());
+
+    let unknown = Pid {name: "unknown".to_string(), group: None, node: node.id.clone()};
+    let c_id = CorrelationId::request(sender.clone(), 0, 1);
+    let envelope = Envelope::new(unknown.clone(), sender.clone(), Msg::User(1), Some(c_id.clone()));
+    node.send(envelope.clone()).unwrap();
+
+    assert_eq!(dead_rx.recv().unwrap(),
+               Msg::DeadLetter(Some(Box::new(envelope)), DeliveryFailure::NoSuchPid));
+    let reply = rx.recv().unwrap();
+    assert_eq!(reply.msg, Msg::Undeliverable(unknown, DeliveryFailure::NoSuchPid));
+    assert_eq!(reply.correlation_id, Some(c_id));
+
+    handle.shutdown_and_join().unwrap();
+    h1.join().unwrap();
+    h2.join().unwrap();
+}
+
+#[test]
+fn envelopes_to_non_members_are_dead_letters() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11141".to_string()};
+    let dead_letters = Pid {name: "dead_letters".to_string(), group: None, node: node_id.clone()};
+    let config = RabbleConfig::new().dead_letters(dead_letters.clone());
+    let (node, handle) = NodeBuilder::new(node_id).config(config).build::<u64>().unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<u64>| {
+        tx.send(envelope.msg).unwrap();
+    });
+    let mut service = Service::new(dead_letters.clone(), node.clone(), handler).unwrap();
+    let h = thread::spawn(move || service.wait());
+
+    // The node was never joined, so it isn't a member of the cluster
+    let other = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11142".to_string()};
+    let to = Pid {name: "receiver".to_string(), group: None, node: other};
+    let envelope = Envelope::new(to, dead_letters.clone(), Msg::User(1), None);
+    node.send(envelope.clone()).unwrap();
+    assert_eq!(rx.recv().unwrap(),
+               Msg::DeadLetter(Some(Box::new(envelope)), DeliveryFailure::NodeUnreachable));
+
+    let cluster_server = Pid {
+        name: "cluster_server".to_string(),
+        group: Some("rabble".to_string()),
+        node: node.id.clone()
+    };
+    node.send(Envelope::new(cluster_server, dead_letters, Msg::GetMetrics, None)).unwrap();
+    match rx.recv().unwrap() {
+        Msg::Metrics(metrics) => {
+            let count = metrics.iter()
+                .find(|&&(ref name, _)| name == "dropped_node_unreachable")
+                .map(|m| m.1.clone());
+            assert_eq!(count, Some(Metric::Counter(1)));
+        },
+        msg => panic!("Expected metrics, got {:?}", msg)
+    }
+
+    handle.shutdown_and_join().unwrap();
+    h.join().unwrap();
+}
@@ -0,0 +1,145 @@
+//! Test spawning, stopping and registering processes from inside a process
+
+extern crate rabble;
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+use rabble::{
+    Pid,
+    NodeId,
+    Process,
+    Effect,
+    Envelope,
+    Msg,
+    CorrelationId,
+    RabbleConfig
+};
+
+/// Forwards all received messages to the test
+struct Worker {
+    output: Vec<Envelope<u64>>,
+    tx: mpsc::Sender<Msg<u64>>
+}
+
+impl Process for Worker {
+    type Msg = u64;
+
+    fn handle(&mut self,
+              msg: Msg<u64>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<u64>>
+    {
+        self.tx.send(msg).unwrap();
+        &mut self.output
+    }
+}
+
+/// Registers itself as "pool" and manages a set of workers
+///
+/// `Msg::User(n)` spawns n workers and sends each of them its index. `Msg::User(0)` stops all
+/// workers and unregisters the pool. Both also look up the name of the pool.
+struct Pool {
+    pid: Pid,
+    workers: Vec<Pid>,
+    output: Vec<Envelope<u64>>,
+    effects: Vec<Effect<u64>>,
+    tx: mpsc::Sender<Msg<u64>>
+}
+
+impl Process for Pool {
+    type Msg = u64;
+
+    fn init(&mut self, _executor_pid: Pid) -> Vec<Envelope<u64>> {
+        self.effects.push(Effect::Register("pool".to_string(), self.pid.clone()));
+        Vec::new()
+    }
+
+    fn handle(&mut self,
+              msg: Msg<u64>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<u64>>
+    {
+        match msg {
+            Msg::User(0) => {
+                for pid in &self.workers {
+                    self.effects.push(Effect::Stop(pid.clone()));
+                }
+                self.effects.push(Effect::Unregister("pool".to_string(), self.pid.clone()));
+                // Stopped workers never receive this message
+                let to = self.workers[0].clone();
+                self.output.push(Envelope::new(to, self.pid.clone(), Msg::User(0), None));
+            },
+            Msg::User(n) => {
+                for i in 0..n {
+                    let pid = Pid {
+                        name: format!("worker{}", i),
+                        group: None,
+                        node: self.pid.node.clone()
+                    };
+                    let worker = Worker {output: Vec::new(), tx: self.tx.clone()};
+                    self.effects.push(Effect::Spawn(pid.clone(), Box::new(worker)));
+                    let msg = Msg::User(i);
+                    self.output.push(Envelope::new(pid.clone(), self.pid.clone(), msg, None));
+                    self.workers.push(pid);
+                }
+            },
+            msg => {
+                self.tx.send(msg).unwrap();
+                return &mut self.output;
+            }
+        }
+        let cluster_server = Pid {
+            name: "cluster_server".to_string(),
+            group: Some("rabble".to_string()),
+            node: self.pid.node.clone()
+        };
+        let msg = Msg::WhereIsName("pool".to_string());
+        self.output.push(Envelope::new(cluster_server, self.pid.clone(), msg, None));
+        &mut self.output
+    }
+
+    fn effects(&mut self) -> Option<&mut Vec<Effect<u64>>> {
+        Some(&mut self.effects)
+    }
+}
+
+#[test]
+fn processes_manage_other_processes_with_effects() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11040".to_string()};
+    let config = RabbleConfig::new().num_workers(4);
+    let (node, handles) = rabble::rouse_with_config::<u64>(node_id.clone(), config, None).unwrap();
+    let (tx, rx) = mpsc::channel();
+
+    let pool_pid = Pid {name: "pool".to_string(), group: None, node: node_id.clone()};
+    let pool = Pool {
+        pid: pool_pid.clone(),
+        workers: Vec::new(),
+        output: Vec::new(),
+        effects: Vec::new(),
+        tx: tx
+    };
+    node.spawn(&pool_pid, Box::new(pool)).unwrap();
+
+    // Spawned workers receive the messages sent to them in the same call that spawned them
+    node.send(Envelope::new(pool_pid.clone(), pool_pid.clone(), Msg::User(3), None)).unwrap();
+    let mut received: Vec<_> = (0..4).map(|_| rx.recv().unwrap()).collect();
+    let named_pid = Msg::NamedPid("pool".to_string(), Some(pool_pid.clone()));
+    assert!(received.contains(&named_pid));
+    received.retain(|msg| *msg != named_pid);
+    let mut values: Vec<_> = received.into_iter().map(|msg| match msg {
+        Msg::User(i) => i,
+        msg => panic!("Unexpected message {:?}", msg)
+    }).collect();
+    values.sort();
+    assert_eq!(values, vec![0, 1, 2]);
+
+    node.send(Envelope::new(pool_pid.clone(), pool_pid, Msg::User(0), None)).unwrap();
+    assert_eq!(rx.recv().unwrap(), Msg::NamedPid("pool".to_string(), None));
+    assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+    node.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+//! Test running processes on multiple executor worker threads
+
+extern crate rabble;
+#[macro_use]
+extern crate assert_matches;
+
+use std::thread;
+use std::sync::mpsc;
+
+use rabble::{
+    Pid,
+    NodeId,
+    Process,
+    Envelope,
+    Msg,
+    CorrelationId,
+    Service,
+    ThreadHandler,
+    RabbleConfig
+};
+
+const NUM_WORKERS: usize = 4;
+const NUM_SENDERS: u64 = 10;
+const NUM_MSGS: u64 = 100;
+
+/// Forwards every user message to the receiver, tagged with the index of the sender
+struct Forwarder {
+    pid: Pid,
+    index: u64,
+    receiver: Pid,
+    output: Vec<Envelope<u64>>
+}
+
+impl Process for Forwarder {
+    type Msg = u64;
+
+    fn handle(&mut self,
+              msg: Msg<u64>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<u64>>
+    {
+        if let Msg::User(seq) = msg {
+            let msg = Msg::User(self.index * NUM_MSGS + seq);
+            self.output.push(Envelope::new(self.receiver.clone(), self.pid.clone(), msg, None));
+        }
+        &mut self.output
+    }
+}
+
+/// Ensures messages from each sender arrive in order and signals the test when all have arrived
+struct OrderChecker {
+    next: Vec<u64>,
+    received: u64,
+    output: Vec<Envelope<u64>>,
+    tx: mpsc::Sender<()>
+}
+
+impl Process for OrderChecker {
+    type Msg = u64;
+
+    fn handle(&mut self,
+              msg: Msg<u64>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<u64>>
+    {
+        if let Msg::User(val) = msg {
+            let (sender, seq) = ((val / NUM_MSGS) as usize, val % NUM_MSGS);
+            assert_eq!(self.next[sender], seq);
+            self.next[sender] += 1;
+            self.received += 1;
+            if self.received == NUM_SENDERS * NUM_MSGS {
+                self.tx.send(()).unwrap();
+            }
+        }
+        &mut self.output
+    }
+}
+
+#[test]
+fn envelopes_between_workers_are_ordered() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11010".to_string()};
+    let config = RabbleConfig::new().num_workers(NUM_WORKERS);
+    let (node, handles) = rabble::rouse_with_config::<u64>(node_id, config, None).unwrap();
+    let test_pid = Pid {name: "test-runner".to_string(), group: None, node: node.id.clone()};
+
+    let (tx, rx) = mpsc::channel();
+    let receiver_pid = Pid {name: "receiver".to_string(), group: None, node: node.id.clone()};
+    let receiver = OrderChecker {
+        next: vec![0; NUM_SENDERS as usize],
+        received: 0,
+        output: Vec::new(),
+        tx: tx
+    };
+    node.spawn(&receiver_pid, Box::new(receiver)).unwrap();
+
+    let sender_pids: Vec<_> = (0..NUM_SENDERS).map(|i| {
+        let pid = Pid {name: format!("sender{}", i), group: None, node: node.id.clone()};
+        let sender = Forwarder {
+            pid: pid.clone(),
+            index: i,
+            receiver: receiver_pid.clone(),
+            output: Vec::with_capacity(1)
+        };
+        node.spawn(&pid, Box::new(sender)).unwrap();
+        pid
+    }).collect();
+
+    for seq in 0..NUM_MSGS {
+        for pid in &sender_pids {
+            node.send(Envelope::new(pid.clone(), test_pid.clone(), Msg::User(seq), None)).unwrap();
+        }
+    }
+
+    rx.recv().unwrap();
+
+    node.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+#[test]
+fn executor_status_is_aggregated_over_all_workers() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11011".to_string()};
+    let config = RabbleConfig::new().num_workers(NUM_WORKERS);
+    let (node, handles) = rabble::rouse_with_config::<u64>(node_id, config, None).unwrap();
+
+    for i in 0..NUM_SENDERS {
+        let pid = Pid {name: format!("sender{}", i), group: None, node: node.id.clone()};
+        let sender = Forwarder {
+            pid: pid.clone(),
+            index: i,
+            receiver: pid.clone(),
+            output: Vec::new()
+        };
+        node.spawn(&pid, Box::new(sender)).unwrap();
+    }
+
+    let service_pid = Pid {
+        name: "test-service".to_string(),
+        group: Some("Service".to_string()),
+        node: node.id.clone()
+    };
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<u64>| {
+        tx.send(envelope.msg).unwrap();
+    });
+    let mut service = Service::new(service_pid.clone(), node.clone(), handler).unwrap();
+    node.executor_status(CorrelationId::pid(service_pid.clone())).unwrap();
+    let service_tx = service.tx.clone();
+    let h = thread::spawn(move || {
+        service.wait();
+    });
+
+    let msg = rx.recv().unwrap();
+    assert_matches!(msg, Msg::ExecutorStatus(_));
+    if let Msg::ExecutorStatus(status) = msg {
+        assert_eq!(status.workers, NUM_WORKERS);
+        assert_eq!(status.total_processes, NUM_SENDERS as usize);
+        assert_eq!(status.services, vec![service_pid.clone()]);
+    }
+
+    service_tx.send(Envelope::new(service_pid.clone(), service_pid, Msg::Shutdown, None)).unwrap();
+    h.join().unwrap();
+    node.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+//! Test that peers are monitored with the phi accrual failure detector
+
+extern crate amy;
+extern crate rabble;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use std::sync::mpsc;
+use std::thread;
+use std::net::TcpStream;
+use time::Duration;
+use utils::{wait_for, wait_for_connections};
+
+use rabble::{
+    Pid,
+    NodeId,
+    Envelope,
+    Msg,
+    ClusterStatus,
+    CorrelationId,
+    FailureDetectorConfig,
+    Service,
+    ThreadHandler
+};
+
+#[test]
+fn silent_peers_are_suspected() {
+    let node_ids: Vec<_> = (1..3).map(|i| {
+        NodeId {name: format!("node{}", i), addr: format!("127.0.0.1:1109{}", i)}
+    }).collect();
+    let (node1, mut handles) = rabble::rouse::<()>(node_ids[0].clone(), None);
+    let (node2, handles2) = rabble::rouse::<()>(node_ids[1].clone(), None);
+    handles.extend(handles2);
+
+    node1.join(&node2.id).unwrap();
+    assert!(wait_for_connections(&node1, 1));
+
+    let service_pid = Pid {name: "test-service".to_string(), group: None, node: node1.id.clone()};
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<()>| {
+        if let Msg::ClusterStatus(status) = envelope.msg {
+            tx.send(status).unwrap();
+        }
+    });
+    let mut service = Service::new(service_pid.clone(), node1.clone(), handler).unwrap();
+    let service_tx = service.tx.clone();
+    let h = thread::spawn(move || {
+        service.wait();
+    });
+
+    // A healthy peer is reported with a suspicion level below the threshold
+    node1.cluster_status(CorrelationId::pid(service_pid.clone())).unwrap();
+    let status = rx.recv().unwrap();
+    assert_eq!(status.phi.len(), 1);
+    let threshold = (FailureDetectorConfig::default().threshold * 1000.0) as u64;
+    assert!(status.phi[&node2.id] < threshold);
+
+    // A connection that never sends a heartbeat is eventually closed
+    let _sock = TcpStream::connect(&node1.id.addr[..]).unwrap();
+    let accepted = wait_for(Duration::seconds(2), || {
+        node1.cluster_status(CorrelationId::pid(service_pid.clone())).unwrap();
+        if let Ok(ClusterStatus {num_connections, ..}) = rx.recv() {
+            return num_connections == 2;
+        }
+        false
+    });
+    assert!(accepted);
+    let closed = wait_for(Duration::seconds(10), || {
+        node1.cluster_status(CorrelationId::pid(service_pid.clone())).unwrap();
+        let status = rx.recv().unwrap();
+        status.num_connections == 1 && status.established.contains(&node2.id)
+    });
+    assert!(closed);
+
+    service_tx.send(Envelope::new(service_pid.clone(), service_pid, Msg::Shutdown, None)).unwrap();
+    h.join().unwrap();
+    node1.shutdown();
+    node2.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+//! Test faults injected into the links between nodes
+#![cfg(feature = "fault-injection")]
+
+extern crate rabble;
+extern crate amy;
+
+use rabble::{
+    Pid,
+    NodeId,
+    Node,
+    Envelope,
+    Msg,
+    RabbleConfig,
+    ClusterEvent,
+    LinkFaults,
+    Simulation
+};
+
+fn node_id(name: &str) -> NodeId {
+    NodeId {name: name.to_string(), addr: name.to_string()}
+}
+
+fn pid(name: &str, node: &NodeId) -> Pid {
+    Pid {name: name.to_string(), group: None, node: node.clone()}
+}
+
+/// Two connected nodes, with a service on node1 receiving cluster events and a service on node2
+/// receiving envelopes sent from node1
+struct Cluster {
+    sim: Simulation<u64>,
+    node1: Node<u64>,
+    node2: Node<u64>,
+    sender: Pid,
+    receiver: Pid,
+    events: amy::Receiver<Envelope<u64>>,
+    received: amy::Receiver<Envelope<u64>>
+}
+
+impl Cluster {
+    fn new() -> Cluster {
+        let mut sim = Simulation::<u64>::new(5);
+        let node1 = sim.add_node(node_id("node1"), RabbleConfig::new()).unwrap();
+        let node2 = sim.add_node(node_id("node2"), RabbleConfig::new()).unwrap();
+        let sender = pid("sender", &node1.id);
+        let receiver = pid("receiver", &node2.id);
+        let events = sim.service(&sender).unwrap();
+        let received = sim.service(&receiver).unwrap();
+        node1.subscribe_cluster_events(&sender).unwrap();
+        node1.join(&node2.id).unwrap();
+        let mut cluster = Cluster {
+            sim: sim,
+            node1: node1,
+            node2: node2,
+            sender: sender,
+            receiver: receiver,
+            events: events,
+            received: received
+        };
+        let established = ClusterEvent::ConnectionEstablished(cluster.node2.id.clone());
+        assert!(cluster.wait_for_event(1000, established));
+        cluster
+    }
+
+    fn envelope(&self, count: u64) -> Envelope<u64> {
+        Envelope::new(self.receiver.clone(), self.sender.clone(), Msg::User(count), None)
+    }
+
+    fn send(&self, count: u64) {
+        self.node1.send(self.envelope(count)).unwrap();
+    }
+
+    fn send_reliable(&self, count: u64) {
+        self.node1.send_reliable(self.envelope(count)).unwrap();
+    }
+
+    /// Return the counts received by node2 so far
+    fn received(&self) -> Vec<u64> {
+        let mut counts = Vec::new();
+        while let Ok(envelope) = self.received.try_recv() {
+            if let Msg::User(count) = envelope.msg {
+                counts.push(count);
+            }
+        }
+        counts
+    }
+
+    fn wait_for_event(&mut self, timeout: usize, event: ClusterEvent) -> bool {
+        let events = &self.events;
+        let msg = Msg::ClusterEvent(event);
+        self.sim.run_until(timeout, || {
+            events.try_recv().map_or(false, |envelope| envelope.msg == msg)
+        })
+    }
+}
+
+#[test]
+fn partitioned_nodes_reconnect_once_healed() {
+    let mut cluster = Cluster::new();
+    let (id1, id2) = (cluster.node1.id.clone(), cluster.node2.id.clone());
+    cluster.node1.set_faults(&id2, LinkFaults::new().partition()).unwrap();
+    cluster.node2.set_faults(&id1, LinkFaults::new().partition()).unwrap();
+
+    // Nothing gets through, so the failure detector closes the connection
+    cluster.send(1);
+    assert!(cluster.wait_for_event(20_000, ClusterEvent::ConnectionLost(id2.clone())));
+    cluster.sim.run_for(5000);
+    assert_eq!(cluster.received(), Vec::<u64>::new());
+
+    // Envelopes sent during the partition are queued until the nodes reconnect
+    cluster.send(2);
+    cluster.node1.clear_faults(&id2).unwrap();
+    cluster.node2.clear_faults(&id1).unwrap();
+    assert!(cluster.wait_for_event(5000, ClusterEvent::ConnectionEstablished(id2)));
+    cluster.sim.run_for(100);
+    assert_eq!(cluster.received(), vec![2]);
+}
+
+#[test]
+fn dropped_envelopes_never_arrive() {
+    let mut cluster = Cluster::new();
+    let id2 = cluster.node2.id.clone();
+    cluster.node1.set_faults(&id2, LinkFaults::new().drop_rate(1.0)).unwrap();
+    for i in 0..5 {
+        cluster.send(i);
+    }
+
+    // Pings still get through, so the connection stays up
+    assert!(!cluster.wait_for_event(10_000, ClusterEvent::ConnectionLost(id2.clone())));
+    assert_eq!(cluster.received(), Vec::<u64>::new());
+
+    cluster.node1.clear_faults(&id2).unwrap();
+    cluster.send(5);
+    cluster.sim.run_for(100);
+    assert_eq!(cluster.received(), vec![5]);
+}
+
+#[test]
+fn duplicated_envelopes_arrive_twice_unless_reliable() {
+    let mut cluster = Cluster::new();
+
+    // Faults on the receiving node apply to envelopes as they are read
+    let id1 = cluster.node1.id.clone();
+    cluster.node2.set_faults(&id1, LinkFaults::new().duplicate_rate(1.0)).unwrap();
+    cluster.send(1);
+    cluster.sim.run_for(100);
+    assert_eq!(cluster.received(), vec![1, 1]);
+
+    cluster.send_reliable(2);
+    cluster.sim.run_for(100);
+    assert_eq!(cluster.received(), vec![2]);
+}
+
+#[test]
+fn reordered_envelopes_arrive_out_of_order() {
+    let mut cluster = Cluster::new();
+    let id2 = cluster.node2.id.clone();
+    cluster.node1.set_faults(&id2, LinkFaults::new().reorder_rate(1.0)).unwrap();
+    for i in 1..6 {
+        cluster.send(i);
+    }
+    cluster.sim.run_for(100);
+
+    // Each envelope held back is passed by the next one. The last one is released on the next
+    // executor tick.
+    assert_eq!(cluster.received(), vec![2, 1, 4, 3, 5]);
+}
+
+#[test]
+fn delayed_envelopes_arrive_late() {
+    let mut cluster = Cluster::new();
+    let id2 = cluster.node2.id.clone();
+    cluster.node1.set_faults(&id2, LinkFaults::new().delay(500, 500)).unwrap();
+    let sent = cluster.sim.now();
+    cluster.send(1);
+    cluster.sim.run_for(450);
+    assert_eq!(cluster.received(), Vec::<u64>::new());
+
+    let received = &cluster.received;
+    assert!(cluster.sim.run_until(200, || received.try_recv().is_ok()));
+    let delay = cluster.sim.now() - sent;
+    assert!(delay >= 500 && delay <= 620, "Envelope arrived after {}ms", delay);
+}
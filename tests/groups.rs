@@ -0,0 +1,132 @@
+//! Test process groups spanning multiple nodes
+
+extern crate amy;
+extern crate rabble;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use std::sync::mpsc;
+use std::thread;
+use time::Duration;
+
+use utils::{wait_for, wait_for_connections};
+
+use rabble::{
+    Pid,
+    NodeId,
+    Node,
+    Process,
+    Envelope,
+    Msg,
+    CorrelationId,
+    Service,
+    ThreadHandler
+};
+
+/// Forwards all received messages to the test along with its own pid
+struct Echo {
+    pid: Pid,
+    output: Vec<Envelope<u64>>,
+    tx: mpsc::Sender<(Pid, Msg<u64>)>
+}
+
+impl Process for Echo {
+    type Msg = u64;
+
+    fn handle(&mut self,
+              msg: Msg<u64>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<u64>>
+    {
+        self.tx.send((self.pid.clone(), msg)).unwrap();
+        &mut self.output
+    }
+}
+
+/// Wait for the cluster server on `node` to report `expected` as the members of `group`
+fn wait_for_members(node: &Node<u64>,
+                    service_pid: &Pid,
+                    rx: &mpsc::Receiver<Msg<u64>>,
+                    group: &str,
+                    expected: Vec<Pid>) -> bool
+{
+    let cluster_server = Pid {
+        name: "cluster_server".to_string(),
+        group: Some("rabble".to_string()),
+        node: node.id.clone()
+    };
+    wait_for(Duration::seconds(5), || {
+        let msg = Msg::GetGroupMembers(group.to_string());
+        node.send(Envelope::new(cluster_server.clone(), service_pid.clone(), msg, None)).unwrap();
+        rx.recv().unwrap() == Msg::GroupMembers(group.to_string(), expected.clone())
+    })
+}
+
+#[test]
+fn send_to_process_groups() {
+    let node_ids: Vec<_> = (1..3).map(|i| {
+        NodeId {name: format!("node{}", i), addr: format!("127.0.0.1:1107{}", i)}
+    }).collect();
+    let (node1, mut handles) = rabble::rouse::<u64>(node_ids[0].clone(), None);
+    let (node2, handles2) = rabble::rouse::<u64>(node_ids[1].clone(), None);
+    handles.extend(handles2);
+    node1.join(&node2.id).unwrap();
+    assert!(wait_for_connections(&node1, 1));
+
+    let service_pid = Pid {name: "test-service".to_string(), group: None, node: node1.id.clone()};
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<u64>| {
+        tx.send(envelope.msg).unwrap();
+    });
+    let mut service = Service::new(service_pid.clone(), node1.clone(), handler).unwrap();
+    let service_tx = service.tx.clone();
+    let h = thread::spawn(move || {
+        service.wait();
+    });
+
+    let (echo_tx, echo_rx) = mpsc::channel();
+    let mut members = Vec::new();
+    for node in &[&node1, &node2] {
+        let pid = Pid {name: "echo".to_string(), group: None, node: node.id.clone()};
+        let echo = Echo {pid: pid.clone(), output: Vec::new(), tx: echo_tx.clone()};
+        node.spawn(&pid, Box::new(echo)).unwrap();
+        node.join_group("echoes", &pid).unwrap();
+        members.push(pid);
+    }
+    members.sort();
+    assert!(wait_for_members(&node1, &service_pid, &rx, "echoes", members.clone()));
+
+    node1.multicast("echoes", service_pid.clone(), Msg::User(1), None).unwrap();
+    let mut received: Vec<_> = (0..2).map(|_| echo_rx.recv().unwrap()).collect();
+    received.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(received, members.iter().map(|pid| (pid.clone(), Msg::User(1))).collect::<Vec<_>>());
+
+    // Sending to any member cycles through all members
+    for i in 0..2 {
+        node1.send_any("echoes", service_pid.clone(), Msg::User(2), None).unwrap();
+        assert_eq!(echo_rx.recv().unwrap(), (members[i].clone(), Msg::User(2)));
+    }
+
+    // Processes are removed from groups when they exit
+    node2.stop(&members[1]).unwrap();
+    assert!(wait_for_members(&node1, &service_pid, &rx, "echoes", vec![members[0].clone()]));
+
+    service_tx.send(Envelope::new(service_pid.clone(), service_pid, Msg::Shutdown, None)).unwrap();
+    h.join().unwrap();
+    node1.shutdown();
+    node2.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
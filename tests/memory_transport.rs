@@ -0,0 +1,134 @@
+//! Test running several nodes in one process connected by a `MemoryNetwork`
+
+extern crate amy;
+extern crate rabble;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use utils::start_named_service;
+
+use rabble::{
+    NodeId,
+    Node,
+    NodeBuilder,
+    NodeHandle,
+    RabbleConfig,
+    TlsConfig,
+    MemoryNetwork,
+    ClusterEvent,
+    Envelope,
+    Msg
+};
+use rabble::errors::ErrorKind;
+
+fn start_node(network: &MemoryNetwork, name: &str, config: RabbleConfig)
+    -> (Node<u64>, NodeHandle)
+{
+    // The address is only a name on the network, and can't be bound as a socket
+    let node_id = NodeId {name: name.to_string(), addr: format!("memory-{}", name)};
+    NodeBuilder::new(node_id).config(config).transport(network.transport()).build().unwrap()
+}
+
+#[test]
+fn nodes_communicate_without_sockets() {
+    let network = MemoryNetwork::new();
+    let (node1, handle1) = start_node(&network, "node1", RabbleConfig::new());
+    let (node2, handle2) = start_node(&network, "node2", RabbleConfig::new());
+    let (node3, handle3) = start_node(&network, "node3", RabbleConfig::new());
+
+    let (pid1, rx1, h1) = start_named_service("service1", &node1);
+    let (pid3, rx3, h3) = start_named_service("service3", &node3);
+    node1.subscribe_cluster_events(&pid1).unwrap();
+
+    node1.join(&node2.id).unwrap();
+    node1.join(&node3.id).unwrap();
+    for i in 0..100 {
+        node1.send(Envelope::new(pid3.clone(), pid1.clone(), Msg::User(i), None)).unwrap();
+    }
+    for i in 0..100 {
+        assert_eq!(rx3.recv().unwrap(), Msg::User(i));
+    }
+
+    // Node3 learns about node2 from node1 and connects to it
+    let (pid2, rx2, h2) = start_named_service("service2", &node2);
+    node3.send(Envelope::new(pid2.clone(), pid3.clone(), Msg::User(1), None)).unwrap();
+    assert_eq!(rx2.recv().unwrap(), Msg::User(1));
+
+    let mut joined = 0;
+    while joined < 2 {
+        if let Msg::ClusterEvent(ClusterEvent::NodeJoined(_)) = rx1.recv().unwrap() {
+            joined += 1;
+        }
+    }
+
+    // Shutting down a node closes its connections
+    handle3.shutdown_and_join().unwrap();
+    h3.join().unwrap();
+    loop {
+        if let Msg::ClusterEvent(ClusterEvent::ConnectionLost(node)) = rx1.recv().unwrap() {
+            assert_eq!(node, node3.id);
+            break;
+        }
+    }
+
+    handle1.shutdown_and_join().unwrap();
+    handle2.shutdown_and_join().unwrap();
+    h1.join().unwrap();
+    h2.join().unwrap();
+}
+
+#[test]
+fn tls_over_a_memory_network() {
+    let tls_config = |name: &str| {
+        TlsConfig::new(&format!("tests/tls/{}.pem", name),
+                       &format!("tests/tls/{}.key", name),
+                       "tests/tls/ca.pem")
+    };
+    let network = MemoryNetwork::new();
+    let (node1, handle1) = start_node(&network, "node1",
+                                      RabbleConfig::new().tls(tls_config("node1")));
+    let (node2, handle2) = start_node(&network, "node2",
+                                      RabbleConfig::new().tls(tls_config("node2")));
+    let (pid1, _rx1, h1) = start_named_service("sender", &node1);
+    let (pid2, rx2, h2) = start_named_service("receiver", &node2);
+
+    node1.join(&node2.id).unwrap();
+    node1.send(Envelope::new(pid2, pid1, Msg::User(1), None)).unwrap();
+    assert_eq!(rx2.recv().unwrap(), Msg::User(1));
+
+    handle1.shutdown_and_join().unwrap();
+    handle2.shutdown_and_join().unwrap();
+    h1.join().unwrap();
+    h2.join().unwrap();
+}
+
+#[test]
+fn addresses_are_unique_within_a_network() {
+    let network = MemoryNetwork::new();
+    let (_node, handle) = start_node(&network, "node1", RabbleConfig::new());
+    let node_id = NodeId {name: "node2".to_string(), addr: "memory-node1".to_string()};
+    let result = NodeBuilder::new(node_id).transport(network.transport()).build::<u64>();
+    match result {
+        Err(e) => match *e.kind() {
+            ErrorKind::BindError(_) => (),
+            ref kind => panic!("Expected a BindError, got {:?}", kind)
+        },
+        Ok(_) => panic!("Expected a BindError")
+    }
+
+    // The address can be reused once the node shuts down
+    handle.shutdown_and_join().unwrap();
+    let (_node, handle) = start_node(&network, "node1", RabbleConfig::new());
+    handle.shutdown_and_join().unwrap();
+}
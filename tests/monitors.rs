@@ -0,0 +1,266 @@
+//! Test process monitors and links
+
+extern crate amy;
+extern crate rabble;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use std::sync::mpsc;
+
+use utils::wait_for_connections;
+
+use rabble::{
+    Pid,
+    NodeId,
+    Node,
+    Process,
+    Envelope,
+    Msg,
+    Reason,
+    CorrelationId,
+    RabbleConfig,
+    DeliveryFailure
+};
+
+/// Sends `init_requests` to the executor on init and `user_requests` on receipt of the first user
+/// message. Every user message is acknowledged to the test, as is every `Down` notification.
+struct Watcher {
+    pid: Pid,
+    executor_pid: Option<Pid>,
+    init_requests: Vec<Msg<()>>,
+    user_requests: Vec<Msg<()>>,
+    output: Vec<Envelope<()>>,
+    tx: mpsc::Sender<Msg<()>>
+}
+
+impl Watcher {
+    fn new(pid: Pid,
+           init_requests: Vec<Msg<()>>,
+           user_requests: Vec<Msg<()>>,
+           tx: mpsc::Sender<Msg<()>>) -> Watcher
+    {
+        Watcher {
+            pid: pid,
+            executor_pid: None,
+            init_requests: init_requests,
+            user_requests: user_requests,
+            output: Vec::new(),
+            tx: tx
+        }
+    }
+}
+
+impl Process for Watcher {
+    type Msg = ();
+
+    fn init(&mut self, executor_pid: Pid) -> Vec<Envelope<()>> {
+        self.executor_pid = Some(executor_pid.clone());
+        let from = self.pid.clone();
+        self.init_requests.drain(..).map(|msg| {
+            Envelope::new(executor_pid.clone(), from.clone(), msg, None)
+        }).collect()
+    }
+
+    fn handle(&mut self,
+              msg: Msg<()>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<()>>
+    {
+        let executor_pid = self.executor_pid.as_ref().unwrap().clone();
+        for msg in self.user_requests.drain(..) {
+            self.output.push(Envelope::new(executor_pid.clone(), self.pid.clone(), msg, None));
+        }
+        self.tx.send(msg).unwrap();
+        &mut self.output
+    }
+}
+
+fn pid(name: &str, node: &NodeId) -> Pid {
+    Pid {name: name.to_string(), group: None, node: node.clone()}
+}
+
+/// Send a user message to `to` and wait for the watcher to acknowledge it. Since monitor requests
+/// from `init` are enqueued before the user message is handled, they are guaranteed to be
+/// processed by the executor before anything the test does afterwards.
+fn sync(node: &Node<()>, to: &Pid, rx: &mpsc::Receiver<Msg<()>>) {
+    node.send(Envelope::new(to.clone(), to.clone(), Msg::User(()), None)).unwrap();
+    assert_eq!(rx.recv().unwrap(), Msg::User(()));
+}
+
+#[test]
+fn monitor_local_processes() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11020".to_string()};
+    let config = RabbleConfig::new().num_workers(2);
+    let (node, handles) = rabble::rouse_with_config::<()>(node_id.clone(), config, None).unwrap();
+    let (tx, rx) = mpsc::channel();
+
+    let target = pid("target", &node_id);
+    let ghost = pid("ghost", &node_id);
+    let watcher = pid("watcher", &node_id);
+    node.spawn(&target, Box::new(Watcher::new(target.clone(), vec![], vec![], tx.clone())))
+        .unwrap();
+    let requests = vec![Msg::Monitor(target.clone())];
+    node.spawn(&watcher, Box::new(Watcher::new(watcher.clone(), requests, vec![], tx.clone())))
+        .unwrap();
+    sync(&node, &watcher, &rx);
+    node.stop(&target).unwrap();
+    assert_eq!(rx.recv().unwrap(), Msg::Down(target, Reason::Normal));
+
+    // Monitoring a process that doesn't exist results in an immediate notification
+    let watcher2 = pid("watcher2", &node_id);
+    let requests = vec![Msg::Monitor(ghost.clone())];
+    node.spawn(&watcher2, Box::new(Watcher::new(watcher2.clone(), requests, vec![], tx.clone())))
+        .unwrap();
+    assert_eq!(rx.recv().unwrap(), Msg::Down(ghost, Reason::NoProc));
+
+    node.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+#[test]
+fn monitors_are_removed_when_the_watcher_exits() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11024".to_string()};
+    let dead_letters = pid("dead_letters", &node_id);
+    let config = RabbleConfig::new().dead_letters(dead_letters.clone());
+    let (node, handles) = rabble::rouse_with_config::<()>(node_id.clone(), config, None).unwrap();
+    let (tx, rx) = mpsc::channel();
+    let (dead_tx, dead_rx) = mpsc::channel();
+
+    let target = pid("target", &node_id);
+    let watcher = pid("watcher", &node_id);
+    let ghost = pid("ghost", &node_id);
+    node.spawn(&dead_letters, Box::new(Watcher::new(dead_letters.clone(), vec![], vec![], dead_tx)))
+        .unwrap();
+    node.spawn(&target, Box::new(Watcher::new(target.clone(), vec![], vec![], tx.clone())))
+        .unwrap();
+    let requests = vec![Msg::Monitor(target.clone())];
+    node.spawn(&watcher, Box::new(Watcher::new(watcher.clone(), requests, vec![], tx.clone())))
+        .unwrap();
+    sync(&node, &watcher, &rx);
+
+    // The target exiting after the watcher doesn't notify the watcher, so the first dead letter is
+    // the envelope sent to the ghost afterwards
+    node.stop(&watcher).unwrap();
+    node.stop(&target).unwrap();
+    let envelope = Envelope::new(ghost, target.clone(), Msg::User(()), None);
+    node.send(envelope.clone()).unwrap();
+    assert_eq!(dead_rx.recv().unwrap(),
+               Msg::DeadLetter(Some(Box::new(envelope)), DeliveryFailure::NoSuchPid));
+
+    node.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+#[test]
+fn linked_processes_exit_together() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11021".to_string()};
+    let config = RabbleConfig::new().num_workers(2);
+    let (node, handles) = rabble::rouse_with_config::<()>(node_id.clone(), config, None).unwrap();
+    let (tx, rx) = mpsc::channel();
+
+    let ghost = pid("ghost", &node_id);
+    let linker = pid("linker", &node_id);
+    let watcher = pid("watcher", &node_id);
+    let requests = vec![Msg::Link(ghost.clone())];
+    node.spawn(&linker, Box::new(Watcher::new(linker.clone(), vec![], requests, tx.clone())))
+        .unwrap();
+    let requests = vec![Msg::Monitor(linker.clone())];
+    node.spawn(&watcher, Box::new(Watcher::new(watcher.clone(), requests, vec![], tx.clone())))
+        .unwrap();
+    sync(&node, &watcher, &rx);
+
+    // Linking to a process that doesn't exist causes the linked process to exit
+    sync(&node, &linker, &rx);
+    assert_eq!(rx.recv().unwrap(), Msg::Down(linker, Reason::Linked(ghost)));
+
+    node.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+#[test]
+fn exit_signals_from_unlinked_processes_are_dropped() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11025".to_string()};
+    let config = RabbleConfig::new().num_workers(2);
+    let (node, handles) = rabble::rouse_with_config::<()>(node_id.clone(), config, None).unwrap();
+    let (tx, rx) = mpsc::channel();
+
+    let target = pid("target", &node_id);
+    let watcher = pid("watcher", &node_id);
+    let intruder = pid("intruder", &node_id);
+    node.spawn(&target, Box::new(Watcher::new(target.clone(), vec![], vec![], tx.clone())))
+        .unwrap();
+    let requests = vec![Msg::Monitor(target.clone())];
+    node.spawn(&watcher, Box::new(Watcher::new(watcher.clone(), requests, vec![], tx.clone())))
+        .unwrap();
+    sync(&node, &watcher, &rx);
+
+    // Neither an exit signal from another pid, nor one that claims to come from the exited process,
+    // stops a process that isn't linked to the sender
+    let reason = Reason::Panic("intruder".to_string());
+    let msg = Msg::Exit(intruder.clone(), reason.clone());
+    node.send(Envelope::new(target.clone(), watcher.clone(), msg, None)).unwrap();
+    let msg = Msg::Exit(intruder.clone(), reason);
+    node.send(Envelope::new(target.clone(), intruder, msg, None)).unwrap();
+    sync(&node, &target, &rx);
+    node.stop(&target).unwrap();
+    assert_eq!(rx.recv().unwrap(), Msg::Down(target, Reason::Normal));
+
+    node.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+#[test]
+fn monitors_are_notified_when_a_node_leaves() {
+    let node_ids: Vec<_> = (1..3).map(|i| {
+        NodeId {name: format!("node{}", i), addr: format!("127.0.0.1:1102{}", i + 1)}
+    }).collect();
+    let (nodes, handles) = node_ids.iter().fold((Vec::new(), Vec::new()),
+                                                |(mut nodes, mut handles), node_id| {
+        let (node, h) = rabble::rouse::<()>(node_id.clone(), None);
+        nodes.push(node);
+        handles.extend(h);
+        (nodes, handles)
+    });
+
+    nodes[0].join(&nodes[1].id).unwrap();
+    assert!(wait_for_connections(&nodes[0], 1));
+
+    let (tx, rx) = mpsc::channel();
+    let target = pid("target", &node_ids[1]);
+    let watcher = pid("watcher", &node_ids[0]);
+    nodes[1].spawn(&target, Box::new(Watcher::new(target.clone(), vec![], vec![], tx.clone())))
+        .unwrap();
+    let requests = vec![Msg::Monitor(target.clone())];
+    nodes[0].spawn(&watcher, Box::new(Watcher::new(watcher.clone(), requests, vec![], tx.clone())))
+        .unwrap();
+    sync(&nodes[0], &watcher, &rx);
+
+    nodes[0].leave(&nodes[1].id).unwrap();
+    assert_eq!(rx.recv().unwrap(), Msg::Down(target, Reason::NodeDown));
+
+    for node in nodes {
+        node.shutdown();
+    }
+    for h in handles {
+        h.join().unwrap();
+    }
+}
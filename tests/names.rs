@@ -0,0 +1,131 @@
+//! Test the cluster wide name registry
+
+extern crate amy;
+extern crate rabble;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use std::sync::mpsc;
+use std::thread;
+use time::Duration;
+
+use utils::{wait_for, wait_for_connections};
+
+use rabble::{
+    Pid,
+    NodeId,
+    Node,
+    Process,
+    Envelope,
+    Msg,
+    CorrelationId,
+    Service,
+    ThreadHandler
+};
+
+/// Forwards all received messages to the test
+struct Echo {
+    output: Vec<Envelope<u64>>,
+    tx: mpsc::Sender<Msg<u64>>
+}
+
+impl Process for Echo {
+    type Msg = u64;
+
+    fn handle(&mut self,
+              msg: Msg<u64>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<u64>>
+    {
+        self.tx.send(msg).unwrap();
+        &mut self.output
+    }
+}
+
+fn pid(name: &str, node: &NodeId) -> Pid {
+    Pid {name: name.to_string(), group: None, node: node.clone()}
+}
+
+/// Wait for the cluster server on `node` to resolve `name` to `expected`
+fn wait_for_name(node: &Node<u64>,
+                 service_pid: &Pid,
+                 rx: &mpsc::Receiver<Msg<u64>>,
+                 name: &str,
+                 expected: Option<Pid>) -> bool
+{
+    let cluster_server = Pid {
+        name: "cluster_server".to_string(),
+        group: Some("rabble".to_string()),
+        node: node.id.clone()
+    };
+    wait_for(Duration::seconds(5), || {
+        let msg = Msg::WhereIsName(name.to_string());
+        node.send(Envelope::new(cluster_server.clone(), service_pid.clone(), msg, None)).unwrap();
+        rx.recv().unwrap() == Msg::NamedPid(name.to_string(), expected.clone())
+    })
+}
+
+#[test]
+fn names_are_replicated_and_removed() {
+    let node_ids: Vec<_> = (1..3).map(|i| {
+        NodeId {name: format!("node{}", i), addr: format!("127.0.0.1:1106{}", i)}
+    }).collect();
+    let (node1, mut handles) = rabble::rouse::<u64>(node_ids[0].clone(), None);
+    let (node2, handles2) = rabble::rouse::<u64>(node_ids[1].clone(), None);
+    handles.extend(handles2);
+    node1.join(&node2.id).unwrap();
+    assert!(wait_for_connections(&node1, 1));
+
+    let service_pid = pid("test-service", &node1.id);
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<u64>| {
+        tx.send(envelope.msg).unwrap();
+    });
+    let mut service = Service::new(service_pid.clone(), node1.clone(), handler).unwrap();
+    let service_tx = service.tx.clone();
+    let h = thread::spawn(move || {
+        service.wait();
+    });
+
+    let (echo_tx, echo_rx) = mpsc::channel();
+    let echo = pid("echo", &node2.id);
+    let echo2 = pid("echo2", &node2.id);
+    for pid in &[&echo, &echo2] {
+        node2.spawn(pid, Box::new(Echo {output: Vec::new(), tx: echo_tx.clone()})).unwrap();
+        node2.register_name(&pid.name, pid).unwrap();
+    }
+    assert!(wait_for_name(&node1, &service_pid, &rx, "echo", Some(echo.clone())));
+    assert!(wait_for_name(&node1, &service_pid, &rx, "echo2", Some(echo2.clone())));
+
+    // Registering a name that is already registered is ignored
+    node2.register_name("echo", &echo2).unwrap();
+    node1.send_named("echo", service_pid.clone(), Msg::User(1), None).unwrap();
+    assert_eq!(echo_rx.recv().unwrap(), Msg::User(1));
+
+    // Names are removed when their process exits
+    node2.stop(&echo).unwrap();
+    assert!(wait_for_name(&node1, &service_pid, &rx, "echo", None));
+
+    // Names are removed when their node leaves the cluster
+    node1.leave(&node2.id).unwrap();
+    assert!(wait_for_name(&node1, &service_pid, &rx, "echo2", None));
+
+    service_tx.send(Envelope::new(service_pid.clone(), service_pid, Msg::Shutdown, None)).unwrap();
+    h.join().unwrap();
+    node1.shutdown();
+    node2.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
@@ -0,0 +1,44 @@
+//! Test starting and stopping nodes with a `NodeBuilder`
+
+extern crate rabble;
+#[macro_use]
+extern crate assert_matches;
+
+use rabble::{
+    NodeId,
+    NodeBuilder,
+    RabbleConfig
+};
+use rabble::errors::ErrorKind;
+
+#[test]
+fn startup_errors_are_returned() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11110".to_string()};
+    let (node, handle) = NodeBuilder::new(node_id.clone()).build::<()>().unwrap();
+
+    // The address is already in use by the running node
+    let node_id2 = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11110".to_string()};
+    let result = NodeBuilder::new(node_id2).build::<()>();
+    assert_matches!(result.map(|_| ()).unwrap_err().kind(),
+                    &ErrorKind::BindError(ref addr) if addr == "127.0.0.1:11110");
+
+    // The listen address overrides the advertised address
+    let node_id3 = NodeId {name: "node3".to_string(), addr: "127.0.0.1:11111".to_string()};
+    let result = NodeBuilder::new(node_id3)
+        .config(RabbleConfig::new().listen_addr("127.0.0.1:11110"))
+        .build::<()>();
+    assert_matches!(result.map(|_| ()).unwrap_err().kind(), &ErrorKind::BindError(_));
+
+    let result = NodeBuilder::new(node_id)
+        .config(RabbleConfig::new().num_workers(0))
+        .build::<()>();
+    assert_matches!(result.map(|_| ()).unwrap_err().kind(), &ErrorKind::InvalidConfig(_));
+
+    assert_eq!(node.id.addr, "127.0.0.1:11110");
+    handle.shutdown_and_join().unwrap();
+
+    // The address can be reused once the node has shut down
+    let node_id4 = NodeId {name: "node4".to_string(), addr: "127.0.0.1:11110".to_string()};
+    let (_node, handle) = NodeBuilder::new(node_id4).build::<()>().unwrap();
+    handle.shutdown_and_join().unwrap();
+}
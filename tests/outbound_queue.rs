@@ -0,0 +1,139 @@
+//! Test queueing envelopes to members of the cluster that aren't connected yet
+
+extern crate rabble;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use rabble::{
+    Pid,
+    NodeId,
+    NodeBuilder,
+    RabbleConfig,
+    Envelope,
+    Msg,
+    Metric,
+    CorrelationId,
+    DeliveryFailure,
+    Service,
+    ThreadHandler
+};
+
+#[test]
+fn envelopes_are_sent_once_connected() {
+    let node_id1 = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11130".to_string()};
+    let node_id2 = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11131".to_string()};
+    let (node1, handle1) = NodeBuilder::new(node_id1).build::<u64>().unwrap();
+    let (node2, handle2) = NodeBuilder::new(node_id2).build::<u64>().unwrap();
+
+    let pid2 = Pid {name: "receiver".to_string(), group: None, node: node2.id.clone()};
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<u64>| {
+        tx.send(envelope.msg).unwrap();
+    });
+    let mut service = Service::new(pid2.clone(), node2.clone(), handler).unwrap();
+    let h = thread::spawn(move || service.wait());
+
+    // Node2 is a member as soon as the join is handled, but the connection takes a little longer
+    node1.join(&node2.id).unwrap();
+    let from = Pid {name: "sender".to_string(), group: None, node: node1.id.clone()};
+    for i in 0..10 {
+        node1.send(Envelope::new(pid2.clone(), from.clone(), Msg::User(i), None)).unwrap();
+    }
+    for i in 0..10 {
+        assert_eq!(rx.recv().unwrap(), Msg::User(i));
+    }
+
+    handle1.shutdown_and_join().unwrap();
+    handle2.shutdown_and_join().unwrap();
+    h.join().unwrap();
+}
+
+#[test]
+fn dropped_envelopes_are_counted_and_reported() {
+    let node_id1 = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11132".to_string()};
+    let config = RabbleConfig::new()
+        .outbound_queue_size(2)
+        .outbound_queue_timeout(100)
+        .tick_time(50)
+        .notify_undeliverable(true);
+    let (node1, handle1) = NodeBuilder::new(node_id1).config(config).build::<u64>().unwrap();
+
+    let pid1 = Pid {name: "sender".to_string(), group: None, node: node1.id.clone()};
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<u64>| {
+        tx.send(envelope).unwrap();
+    });
+    let mut service = Service::new(pid1.clone(), node1.clone(), handler).unwrap();
+    let h = thread::spawn(move || service.wait());
+
+    // Nothing is listening on this address, so the connection is never established
+    let unreachable = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11133".to_string()};
+    node1.join(&unreachable).unwrap();
+    let pid2 = Pid {name: "receiver".to_string(), group: None, node: unreachable.clone()};
+    for i in 0..3 {
+        let c_id = CorrelationId::request(pid1.clone(), 0, i);
+        node1.send(Envelope::new(pid2.clone(), pid1.clone(), Msg::User(i), Some(c_id))).unwrap();
+    }
+
+    // The third envelope doesn't fit in the queue, and the others expire
+    let expected = vec![(2, DeliveryFailure::QueueFull),
+                        (0, DeliveryFailure::Expired),
+                        (1, DeliveryFailure::Expired)];
+    for (request, failure) in expected {
+        let envelope = rx.recv().unwrap();
+        assert_eq!(envelope.msg, Msg::Undeliverable(pid2.clone(), failure));
+        assert_eq!(envelope.correlation_id, Some(CorrelationId::request(pid1.clone(), 0, request)));
+    }
+
+    let cluster_server = Pid {
+        name: "cluster_server".to_string(),
+        group: Some("rabble".to_string()),
+        node: node1.id.clone()
+    };
+    node1.send(Envelope::new(cluster_server, pid1.clone(), Msg::GetMetrics, None)).unwrap();
+    match rx.recv().unwrap().msg {
+        Msg::Metrics(metrics) => {
+            let get = |name: &str| {
+                metrics.iter().find(|&&(ref n, _)| n == name).map(|m| m.1.clone())
+            };
+            assert_eq!(get("queued_envelopes"), Some(Metric::Counter(2)));
+            assert_eq!(get("dropped_queue_full"), Some(Metric::Counter(1)));
+            assert_eq!(get("dropped_expired"), Some(Metric::Counter(2)));
+        },
+        msg => panic!("Expected metrics, got {:?}", msg)
+    }
+
+    handle1.shutdown_and_join().unwrap();
+    h.join().unwrap();
+}
+
+#[test]
+fn reliable_envelopes_wait_for_the_first_connection_without_expiring() {
+    let node_id1 = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11134".to_string()};
+    let node_id2 = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11135".to_string()};
+    let config = RabbleConfig::new().outbound_queue_timeout(100).tick_time(200);
+    let (node1, handle1) = NodeBuilder::new(node_id1).config(config).build::<u64>().unwrap();
+
+    // Node2 isn't running yet, so the connection can't be established. Node1 retries on its ticks,
+    // after the receiver below is started.
+    node1.join(&node_id2).unwrap();
+    let pid2 = Pid {name: "receiver".to_string(), group: None, node: node_id2.clone()};
+    let from = Pid {name: "sender".to_string(), group: None, node: node1.id.clone()};
+    node1.send_reliable(Envelope::new(pid2.clone(), from, Msg::User(1), None)).unwrap();
+    thread::sleep(Duration::from_millis(500));
+
+    let (node2, handle2) = NodeBuilder::new(node_id2).build::<u64>().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<u64>| {
+        tx.send(envelope.msg).unwrap();
+    });
+    let mut service = Service::new(pid2, node2.clone(), handler).unwrap();
+    let h = thread::spawn(move || service.wait());
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), Msg::User(1));
+
+    handle1.shutdown_and_join().unwrap();
+    handle2.shutdown_and_join().unwrap();
+    h.join().unwrap();
+}
@@ -0,0 +1,258 @@
+//! Test driving processes directly with a `ProcessHarness`
+
+extern crate rabble;
+
+use rabble::{
+    Pid,
+    NodeId,
+    Process,
+    Effect,
+    Envelope,
+    Msg,
+    CorrelationId,
+    ProcessHarness
+};
+
+fn node_id() -> NodeId {
+    NodeId {name: "node1".to_string(), addr: "127.0.0.1:11210".to_string()}
+}
+
+fn pid(name: &str) -> Pid {
+    Pid {name: name.to_string(), group: None, node: node_id()}
+}
+
+/// Sends an incrementing count to `to` every `interval` ms. `Msg::User(0)` stops the timer.
+struct Ticker {
+    pid: Pid,
+    to: Pid,
+    interval: usize,
+    count: u64,
+    executor_pid: Option<Pid>,
+    output: Vec<Envelope<u64>>
+}
+
+impl Ticker {
+    fn new(name: &str, to: Pid, interval: usize) -> Ticker {
+        Ticker {
+            pid: pid(name),
+            to: to,
+            interval: interval,
+            count: 0,
+            executor_pid: None,
+            output: Vec::new()
+        }
+    }
+
+    fn correlation_id(&self) -> Option<CorrelationId> {
+        Some(CorrelationId::pid(self.pid.clone()))
+    }
+
+    fn start_timer(&self) -> Envelope<u64> {
+        Envelope::new(self.executor_pid.clone().unwrap(), self.pid.clone(),
+                      Msg::StartTimer(self.interval), self.correlation_id())
+    }
+}
+
+impl Process for Ticker {
+    type Msg = u64;
+
+    fn init(&mut self, executor_pid: Pid) -> Vec<Envelope<u64>> {
+        self.executor_pid = Some(executor_pid);
+        vec![self.start_timer()]
+    }
+
+    fn handle(&mut self,
+              msg: Msg<u64>,
+              _from: Pid,
+              correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<u64>>
+    {
+        match msg {
+            Msg::Timeout => {
+                assert_eq!(correlation_id, self.correlation_id());
+                self.count += 1;
+                let envelope = Envelope::new(self.to.clone(), self.pid.clone(),
+                                             Msg::User(self.count), None);
+                self.output.push(envelope);
+                let timer = self.start_timer();
+                self.output.push(timer);
+            },
+            Msg::User(0) => {
+                let cancel = Envelope::new(self.executor_pid.clone().unwrap(), self.pid.clone(),
+                                           Msg::CancelTimer(self.correlation_id()), None);
+                self.output.push(cancel);
+            },
+            _ => ()
+        }
+        &mut self.output
+    }
+}
+
+#[test]
+fn timers_fire_when_the_clock_is_advanced() {
+    let test = pid("test");
+    let ticker = pid("ticker");
+    let mut harness = ProcessHarness::new(node_id());
+    harness.spawn(ticker.clone(), Box::new(Ticker::new("ticker", test.clone(), 100)));
+    assert_eq!(harness.timers(),
+               vec![(ticker.clone(), Some(CorrelationId::pid(ticker.clone())), 100)]);
+
+    harness.advance(99);
+    assert_eq!(harness.sent_to(&test), Vec::<&Msg<u64>>::new());
+    harness.advance(1);
+    harness.assert_sent_exactly(&test, &[Msg::User(1)]);
+
+    // Timers restarted on timeout fire during the same advance
+    harness.advance(250);
+    harness.assert_sent_exactly(&test, &[Msg::User(1), Msg::User(2), Msg::User(3)]);
+    assert_eq!(harness.now(), 350);
+    assert_eq!(harness.timers()[0].2, 400);
+}
+
+#[test]
+fn cancelled_timers_never_fire() {
+    let test = pid("test");
+    let ticker = pid("ticker");
+    let mut harness = ProcessHarness::new(node_id());
+    harness.spawn(ticker.clone(), Box::new(Ticker::new("ticker", test.clone(), 100)));
+    harness.send(Envelope::new(ticker.clone(), test.clone(), Msg::User(0), None));
+    harness.advance(1000);
+    assert!(harness.timers().is_empty());
+    harness.assert_not_sent(&test, &Msg::User(1));
+    harness.assert_sent(harness.executor_pid(),
+                        &Msg::CancelTimer(Some(CorrelationId::pid(ticker))));
+}
+
+/// Passes a count back and forth with its peer until it reaches 5, then reports it to `done`
+struct Player {
+    pid: Pid,
+    peer: Pid,
+    done: Pid,
+    output: Vec<Envelope<u64>>
+}
+
+impl Process for Player {
+    type Msg = u64;
+
+    fn handle(&mut self,
+              msg: Msg<u64>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<u64>>
+    {
+        if let Msg::User(count) = msg {
+            let to = if count < 5 { self.peer.clone() } else { self.done.clone() };
+            self.output.push(Envelope::new(to, self.pid.clone(), Msg::User(count + 1), None));
+        }
+        &mut self.output
+    }
+}
+
+fn players(harness: &mut ProcessHarness<u64>) {
+    for &(name, peer) in [("ping", "pong"), ("pong", "ping")].iter() {
+        let player = Player {
+            pid: pid(name),
+            peer: pid(peer),
+            done: pid("test"),
+            output: Vec::new()
+        };
+        harness.spawn(pid(name), Box::new(player));
+    }
+}
+
+#[test]
+fn envelopes_are_routed_between_processes() {
+    let mut harness = ProcessHarness::new(node_id());
+    players(&mut harness);
+    harness.send(Envelope::new(pid("ping"), pid("test"), Msg::User(0), None));
+    assert_eq!(harness.run(), 6);
+    harness.assert_sent_exactly(&pid("pong"), &[Msg::User(1), Msg::User(3), Msg::User(5)]);
+    harness.assert_sent_exactly(&pid("test"), &[Msg::User(6)]);
+    assert_eq!(harness.take_sent().len(), 6);
+    assert!(harness.sent().is_empty());
+}
+
+#[test]
+fn pending_envelopes_can_be_reordered_and_dropped() {
+    let mut harness = ProcessHarness::new(node_id());
+    players(&mut harness);
+    harness.send(Envelope::new(pid("ping"), pid("test"), Msg::User(5), None));
+    harness.send(Envelope::new(pid("pong"), pid("test"), Msg::User(3), None));
+    harness.pending().swap(0, 1);
+    assert!(harness.step());
+    harness.assert_sent_exactly(&pid("ping"), &[Msg::User(4)]);
+    harness.assert_sent_exactly(&pid("test"), &[]);
+
+    // Drop the reply to ping before it's delivered
+    harness.pending().pop_back();
+    assert!(harness.step());
+    assert!(!harness.step());
+    harness.assert_sent_exactly(&pid("test"), &[Msg::User(6)]);
+    harness.assert_sent_exactly(&pid("pong"), &[]);
+}
+
+#[test]
+#[should_panic(expected = "User(7) was not sent to test")]
+fn assert_sent_panics_if_the_message_was_not_sent() {
+    let mut harness = ProcessHarness::new(node_id());
+    players(&mut harness);
+    harness.send(Envelope::new(pid("ping"), pid("test"), Msg::User(0), None));
+    harness.run();
+    harness.assert_sent(&pid("test"), &Msg::User(7));
+}
+
+/// Spawns a `Ticker` named after the count it receives, and stops it on the next message
+struct Spawner {
+    child: Option<Pid>,
+    output: Vec<Envelope<u64>>,
+    effects: Vec<Effect<u64>>
+}
+
+impl Process for Spawner {
+    type Msg = u64;
+
+    fn handle(&mut self,
+              msg: Msg<u64>,
+              from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<u64>>
+    {
+        if let Msg::User(count) = msg {
+            match self.child.take() {
+                Some(child) => self.effects.push(Effect::Stop(child)),
+                None => {
+                    let name = format!("ticker{}", count);
+                    let ticker = Ticker::new(&name, from, 10);
+                    self.child = Some(ticker.pid.clone());
+                    let pid = ticker.pid.clone();
+                    self.effects.push(Effect::Spawn(pid.clone(), Box::new(ticker)));
+                    self.effects.push(Effect::Register(name, pid));
+                }
+            }
+        }
+        &mut self.output
+    }
+
+    fn effects(&mut self) -> Option<&mut Vec<Effect<u64>>> {
+        Some(&mut self.effects)
+    }
+}
+
+#[test]
+fn effects_spawn_and_stop_processes() {
+    let test = pid("test");
+    let spawner = pid("spawner");
+    let mut harness = ProcessHarness::new(node_id());
+    let process = Spawner {child: None, output: Vec::new(), effects: Vec::new()};
+    harness.spawn(spawner.clone(), Box::new(process));
+    harness.send(Envelope::new(spawner.clone(), test.clone(), Msg::User(1), None));
+    harness.run();
+
+    assert!(harness.is_running(&pid("ticker1")));
+    assert_eq!(harness.whereis("ticker1"), Some(&pid("ticker1")));
+    harness.advance(10);
+    harness.assert_sent_exactly(&test, &[Msg::User(1)]);
+
+    harness.send(Envelope::new(spawner, test.clone(), Msg::User(2), None));
+    harness.advance(100);
+    assert!(!harness.is_running(&pid("ticker1")));
+    assert_eq!(harness.whereis("ticker1"), None);
+    harness.assert_sent_exactly(&test, &[Msg::User(1)]);
+}
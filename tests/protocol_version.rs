@@ -0,0 +1,87 @@
+//! Test that nodes reject peers speaking an incompatible protocol
+
+extern crate amy;
+extern crate rabble;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use std::io::Read;
+use std::net::TcpStream;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+use utils::{start_service, get_metric};
+
+use rabble::{
+    Pid,
+    NodeId,
+    NodeBuilder,
+    RabbleConfig,
+    MsgpackSerializer,
+    Serialize
+};
+
+/// The hello frame of a future version of the protocol
+#[derive(Debug, Clone, RustcEncodable, RustcDecodable)]
+struct Hello {
+    version: u32,
+    msg_version: u32
+}
+
+#[test]
+fn nodes_with_different_message_versions_cannot_join() {
+    let node_id1 = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11180".to_string()};
+    let config = RabbleConfig::new().tick_time(100).msg_version(1);
+    let (node1, handle1) = NodeBuilder::new(node_id1).config(config).build::<u64>().unwrap();
+    let pid1 = Pid {name: "events".to_string(), group: None, node: node1.id.clone()};
+    let (rx1, h1) = start_service(pid1.clone(), &node1);
+    node1.subscribe_cluster_events(&pid1).unwrap();
+
+    let node_id2 = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11181".to_string()};
+    let config = RabbleConfig::new().tick_time(100).msg_version(2);
+    let (node2, handle2) = NodeBuilder::new(node_id2).config(config).build::<u64>().unwrap();
+    node2.join(&node1.id).unwrap();
+
+    match rx1.recv_timeout(Duration::from_secs(1)) {
+        Err(RecvTimeoutError::Timeout) => (),
+        result => panic!("Expected no cluster events, got {:?}", result)
+    }
+    assert!(get_metric(&node1, &pid1, &rx1, "incompatible_peers") >= 1);
+
+    handle1.shutdown_and_join().unwrap();
+    handle2.shutdown_and_join().unwrap();
+    h1.join().unwrap();
+}
+
+#[test]
+fn peers_with_a_different_protocol_version_are_disconnected() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11182".to_string()};
+    let (node, handle) = NodeBuilder::new(node_id).build::<u64>().unwrap();
+    let pid = Pid {name: "metrics".to_string(), group: None, node: node.id.clone()};
+    let (rx, h) = start_service(pid.clone(), &node);
+
+    let mut sock = TcpStream::connect("127.0.0.1:11182").unwrap();
+    let mut serializer = MsgpackSerializer::<Hello>::new();
+    serializer.write_msgs(&mut sock, Some(&Hello {version: 1000, msg_version: 0})).unwrap();
+
+    // The node sends its own hello and then closes the connection
+    sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut buf = Vec::new();
+    sock.read_to_end(&mut buf).unwrap();
+    assert!(buf.len() > 0);
+    assert_eq!(get_metric(&node, &pid, &rx, "incompatible_peers"), 1);
+
+    handle.shutdown_and_join().unwrap();
+    h.join().unwrap();
+}
@@ -0,0 +1,136 @@
+//! Test at-least-once delivery of envelopes between nodes
+
+extern crate amy;
+extern crate rabble;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, Shutdown};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use utils::{start_service, wait_for_event, get_metric};
+
+use rabble::{
+    Pid,
+    NodeId,
+    NodeBuilder,
+    RabbleConfig,
+    Envelope,
+    Msg,
+    ClusterEvent
+};
+
+const NODE1_ADDR: &'static str = "127.0.0.1:11150";
+const NODE2_LISTEN_ADDR: &'static str = "127.0.0.1:11151";
+const PROXY_ADDR: &'static str = "127.0.0.1:11152";
+
+#[test]
+fn unacknowledged_envelopes_are_retransmitted_after_reconnect() {
+    let (drop_replies, proxied) = start_proxy(PROXY_ADDR, NODE2_LISTEN_ADDR);
+    let node_id1 = NodeId {name: "node1".to_string(), addr: NODE1_ADDR.to_string()};
+    let node_id2 = NodeId {name: "node2".to_string(), addr: PROXY_ADDR.to_string()};
+    let config = RabbleConfig::new().tick_time(100);
+    let (node1, handle1) = NodeBuilder::new(node_id1).config(config.clone()).build::<u64>().unwrap();
+    let (node2, handle2) = NodeBuilder::new(node_id2)
+        .config(config.listen_addr(NODE2_LISTEN_ADDR))
+        .build::<u64>()
+        .unwrap();
+
+    let pid1 = Pid {name: "sender".to_string(), group: None, node: node1.id.clone()};
+    let (rx1, h1) = start_service(pid1.clone(), &node1);
+    let pid2 = Pid {name: "receiver".to_string(), group: None, node: node2.id.clone()};
+    let (rx2, h2) = start_service(pid2.clone(), &node2);
+
+    // Node1 connects to node2 through the proxy
+    node1.subscribe_cluster_events(&pid1).unwrap();
+    node1.join(&node2.id).unwrap();
+    wait_for_event(&rx1, ClusterEvent::ConnectionEstablished(node2.id.clone()));
+
+    // Node2 receives the envelopes, but its acknowledgements are lost along with the connection
+    drop_replies.store(true, Ordering::SeqCst);
+    for i in 0..10 {
+        let envelope = Envelope::new(pid2.clone(), pid1.clone(), Msg::User(i), None);
+        node1.send_reliable(envelope).unwrap();
+    }
+    for i in 0..10 {
+        assert_eq!(rx2.recv().unwrap(), Msg::User(i));
+    }
+    for sock in proxied.lock().unwrap().drain(..) {
+        let _ = sock.shutdown(Shutdown::Both);
+    }
+    drop_replies.store(false, Ordering::SeqCst);
+    wait_for_event(&rx1, ClusterEvent::ConnectionLost(node2.id.clone()));
+    wait_for_event(&rx1, ClusterEvent::ConnectionEstablished(node2.id.clone()));
+
+    // The retransmitted envelopes are sent before this one, and dropped as duplicates by node2
+    let envelope = Envelope::new(pid2.clone(), pid1.clone(), Msg::User(10), None);
+    node1.send_reliable(envelope).unwrap();
+    assert_eq!(rx2.recv().unwrap(), Msg::User(10));
+
+    assert!(get_metric(&node1, &pid1, &rx1, "retransmitted_envelopes") >= 10);
+    assert!(get_metric(&node2, &pid2, &rx2, "duplicate_envelopes") >= 10);
+
+    handle1.shutdown_and_join().unwrap();
+    handle2.shutdown_and_join().unwrap();
+    h1.join().unwrap();
+    h2.join().unwrap();
+}
+
+/// Forward connections from `addr` to `target`. Data sent back from `target` is discarded while
+/// the returned flag is set. All proxied sockets are returned so that the connections can be
+/// killed.
+fn start_proxy(addr: &str, target: &str) -> (Arc<AtomicBool>, Arc<Mutex<Vec<TcpStream>>>) {
+    let drop_replies = Arc::new(AtomicBool::new(false));
+    let proxied = Arc::new(Mutex::new(Vec::new()));
+    let listener = TcpListener::bind(addr).unwrap();
+    let target = target.to_string();
+    let (drop_replies2, proxied2) = (drop_replies.clone(), proxied.clone());
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            let client = client.unwrap();
+            let server = TcpStream::connect(&target[..]).unwrap();
+            {
+                let mut proxied = proxied2.lock().unwrap();
+                proxied.push(client.try_clone().unwrap());
+                proxied.push(server.try_clone().unwrap());
+            }
+            let (client2, server2) = (client.try_clone().unwrap(), server.try_clone().unwrap());
+            let never_drop = Arc::new(AtomicBool::new(false));
+            thread::spawn(move || pump(client, server, never_drop));
+            let drop_replies = drop_replies2.clone();
+            thread::spawn(move || pump(server2, client2, drop_replies));
+        }
+    });
+    (drop_replies, proxied)
+}
+
+fn pump(mut from: TcpStream, mut to: TcpStream, drop_data: Arc<AtomicBool>) {
+    let mut buf = [0; 4096];
+    loop {
+        let n = match from.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n
+        };
+        if drop_data.load(Ordering::SeqCst) {
+            continue;
+        }
+        if let Err(_) = to.write_all(&buf[..n]) {
+            break;
+        }
+    }
+    let _ = to.shutdown(Shutdown::Both);
+}
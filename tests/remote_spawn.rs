@@ -0,0 +1,116 @@
+//! Test spawning processes on remote nodes
+
+extern crate amy;
+extern crate rabble;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use std::sync::mpsc;
+use std::thread;
+
+use utils::wait_for_connections;
+
+use rabble::{
+    Pid,
+    NodeId,
+    Process,
+    ProcessSpec,
+    Envelope,
+    Msg,
+    CorrelationId,
+    Service,
+    ThreadHandler
+};
+
+/// Forwards all received messages to the test
+struct Echo {
+    output: Vec<Envelope<u64>>,
+    tx: mpsc::Sender<Msg<u64>>
+}
+
+impl Process for Echo {
+    type Msg = u64;
+
+    fn handle(&mut self,
+              msg: Msg<u64>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<u64>>
+    {
+        self.tx.send(msg).unwrap();
+        &mut self.output
+    }
+}
+
+#[test]
+fn spawn_processes_on_remote_nodes() {
+    let node_ids: Vec<_> = (1..3).map(|i| {
+        NodeId {name: format!("node{}", i), addr: format!("127.0.0.1:1105{}", i)}
+    }).collect();
+    let (node1, mut handles) = rabble::rouse::<u64>(node_ids[0].clone(), None);
+    let (node2, handles2) = rabble::rouse::<u64>(node_ids[1].clone(), None);
+    handles.extend(handles2);
+
+    // The factory sends its argument to the test, and the process forwards all its messages
+    let (echo_tx, echo_rx) = mpsc::channel();
+    node2.register_factory("echo", move |_pid, arg| {
+        echo_tx.send(Msg::User(arg.unwrap())).unwrap();
+        Box::new(Echo {output: Vec::new(), tx: echo_tx.clone()}) as Box<Process<Msg=u64>>
+    }).unwrap();
+    node2.register_factory("panic", |_pid, _arg| -> Box<Process<Msg=u64>> {
+        panic!("Not today")
+    }).unwrap();
+
+    node1.join(&node2.id).unwrap();
+    assert!(wait_for_connections(&node1, 1));
+
+    let service_pid = Pid {name: "test-service".to_string(), group: None, node: node1.id.clone()};
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<u64>| {
+        tx.send(envelope.msg).unwrap();
+    });
+    let mut service = Service::new(service_pid.clone(), node1.clone(), handler).unwrap();
+    let service_tx = service.tx.clone();
+    let h = thread::spawn(move || {
+        service.wait();
+    });
+
+    let pid = Pid {name: "echo".to_string(), group: None, node: node2.id.clone()};
+    let correlation_id = CorrelationId::pid(service_pid.clone());
+    node1.spawn_remote(&pid, ProcessSpec::new("echo", Some(7)), correlation_id.clone()).unwrap();
+    assert_eq!(rx.recv().unwrap(), Msg::Spawned(pid.clone()));
+    assert_eq!(echo_rx.recv().unwrap(), Msg::User(7));
+
+    node1.send(Envelope::new(pid.clone(), service_pid.clone(), Msg::User(8), None)).unwrap();
+    assert_eq!(echo_rx.recv().unwrap(), Msg::User(8));
+
+    node1.spawn_remote(&pid, ProcessSpec::new("unknown", None), correlation_id.clone()).unwrap();
+    assert_matches!(rx.recv().unwrap(), Msg::SpawnFailed(failed, _) => assert_eq!(failed, pid));
+
+    // A panicking factory fails the spawn without taking down the remote cluster server
+    node1.spawn_remote(&pid, ProcessSpec::new("panic", None), correlation_id.clone()).unwrap();
+    let reason = "Process factory panicked: Not today".to_string();
+    assert_eq!(rx.recv().unwrap(), Msg::SpawnFailed(pid.clone(), reason));
+    let pid2 = Pid {name: "echo2".to_string(), group: None, node: node2.id.clone()};
+    node1.spawn_remote(&pid2, ProcessSpec::new("echo", Some(9)), correlation_id).unwrap();
+    assert_eq!(rx.recv().unwrap(), Msg::Spawned(pid2));
+    assert_eq!(echo_rx.recv().unwrap(), Msg::User(9));
+
+    service_tx.send(Envelope::new(service_pid.clone(), service_pid, Msg::Shutdown, None)).unwrap();
+    h.join().unwrap();
+    node1.shutdown();
+    node2.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+//! Test graceful node shutdown
+
+extern crate rabble;
+#[macro_use]
+extern crate assert_matches;
+extern crate time;
+
+use std::sync::mpsc;
+use std::thread;
+use time::{SteadyTime, Duration};
+
+use rabble::{
+    Pid,
+    NodeId,
+    NodeBuilder,
+    Process,
+    Envelope,
+    Msg,
+    CorrelationId,
+    ClusterEvent,
+    Service,
+    ThreadHandler
+};
+use rabble::errors::ErrorKind;
+
+/// A process that ignores all messages
+struct Idle {
+    output: Vec<Envelope<u64>>
+}
+
+impl Process for Idle {
+    type Msg = u64;
+
+    fn handle(&mut self,
+              _msg: Msg<u64>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<u64>>
+    {
+        &mut self.output
+    }
+}
+
+#[test]
+fn shutdown_flushes_messages_and_stops_all_threads() {
+    let node_id1 = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11120".to_string()};
+    let node_id2 = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11121".to_string()};
+    let (node1, handle1) = NodeBuilder::new(node_id1).build::<u64>().unwrap();
+    let (node2, handle2) = NodeBuilder::new(node_id2).build::<u64>().unwrap();
+
+    // A service on node2 that records user messages and cluster events
+    let pid2 = Pid {name: "receiver".to_string(), group: None, node: node2.id.clone()};
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<u64>| {
+        tx.send(envelope.msg).unwrap();
+    });
+    let mut service2 = Service::new(pid2.clone(), node2.clone(), handler).unwrap();
+    let h2 = thread::spawn(move || service2.wait());
+    node2.subscribe_cluster_events(&pid2).unwrap();
+    node2.join(&node1.id).unwrap();
+    assert_eq!(rx.recv().unwrap(), Msg::ClusterEvent(ClusterEvent::NodeJoined(node1.id.clone())));
+    assert_eq!(rx.recv().unwrap(),
+               Msg::ClusterEvent(ClusterEvent::ConnectionEstablished(node1.id.clone())));
+
+    // A service on node1 exits when the node shuts down
+    let pid1 = Pid {name: "idle".to_string(), group: None, node: node1.id.clone()};
+    let handler = ThreadHandler::new(|_node, _envelope: Envelope<u64>| ());
+    let mut service1 = Service::new(pid1.clone(), node1.clone(), handler).unwrap();
+    let h1 = thread::spawn(move || service1.wait());
+
+    // Messages sent before shutdown are delivered to the other node
+    for i in 0..100 {
+        node1.send(Envelope::new(pid2.clone(), pid1.clone(), Msg::User(i), None)).unwrap();
+    }
+    let start = SteadyTime::now();
+    node1.shutdown();
+    let result = node1.send(Envelope::new(pid2.clone(), pid1.clone(), Msg::User(100), None));
+    assert_matches!(result.unwrap_err().kind(), &ErrorKind::NodeShutdown(_));
+    let result = node1.spawn(&pid1, Box::new(Idle {output: Vec::new()}));
+    assert_matches!(result.unwrap_err().kind(), &ErrorKind::NodeShutdown(_));
+    let result = node1.join(&node2.id);
+    assert_matches!(result.unwrap_err().kind(), &ErrorKind::NodeShutdown(_));
+    h1.join().unwrap();
+    handle1.shutdown_and_join().unwrap();
+    // The poller thread no longer waits for its poll timeout to expire
+    assert!(SteadyTime::now() - start < Duration::seconds(2));
+
+    for i in 0..100 {
+        assert_eq!(rx.recv().unwrap(), Msg::User(i));
+    }
+    assert_eq!(rx.recv().unwrap(),
+               Msg::ClusterEvent(ClusterEvent::ConnectionLost(node1.id.clone())));
+
+    // The node that shut down is removed from the cluster, so it isn't reconnected to
+    assert_eq!(rx.recv().unwrap(), Msg::ClusterEvent(ClusterEvent::NodeLeft(node1.id.clone())));
+    node2.cluster_status(CorrelationId::pid(pid2.clone())).unwrap();
+    match rx.recv().unwrap() {
+        Msg::ClusterStatus(status) => {
+            assert!(!status.members.contains(&node1.id));
+            assert_eq!(status.num_connections, 0);
+        },
+        msg => panic!("Expected cluster status, got {:?}", msg)
+    }
+
+    // Services exit on their own, without the test sending them a shutdown message
+    handle2.shutdown_and_join().unwrap();
+    h2.join().unwrap();
+}
@@ -0,0 +1,197 @@
+//! Test running clusters deterministically under a virtual clock
+
+extern crate rabble;
+
+use std::sync::{Arc, Mutex};
+
+use rabble::{
+    Pid,
+    NodeId,
+    Node,
+    Process,
+    Envelope,
+    Msg,
+    RabbleConfig,
+    ClusterEvent,
+    CorrelationId,
+    Simulation
+};
+
+/// Passes a counter on to the next process in a ring until it reaches `MAX_COUNT`, and fires a
+/// timer started on init. Everything it handles is appended to a log shared by all processes.
+struct Relay {
+    pid: Pid,
+    next: Pid,
+    timeout: usize,
+    log: Arc<Mutex<Vec<String>>>,
+    output: Vec<Envelope<u64>>
+}
+
+const MAX_COUNT: u64 = 20;
+
+impl Process for Relay {
+    type Msg = u64;
+
+    fn init(&mut self, executor_pid: Pid) -> Vec<Envelope<u64>> {
+        vec![Envelope::new(executor_pid, self.pid.clone(), Msg::StartTimer(self.timeout), None)]
+    }
+
+    fn handle(&mut self,
+              msg: Msg<u64>,
+              from: Pid,
+              _: Option<CorrelationId>) -> &mut Vec<Envelope<u64>>
+    {
+        self.log.lock().unwrap().push(format!("{} got {:?} from {}", self.pid.name, msg, from));
+        if let Msg::User(count) = msg {
+            if count < MAX_COUNT {
+                let envelope = Envelope::new(self.next.clone(), self.pid.clone(),
+                                             Msg::User(count + 1), None);
+                self.output.push(envelope);
+            }
+        }
+        &mut self.output
+    }
+}
+
+fn node_id(name: &str) -> NodeId {
+    NodeId {name: name.to_string(), addr: name.to_string()}
+}
+
+fn pid(name: &str, node: &NodeId) -> Pid {
+    Pid {name: name.to_string(), group: None, node: node.clone()}
+}
+
+/// Run three nodes passing counters around a ring of processes, and return the log of everything
+/// the processes handled along with the virtual time at which the last counter arrived.
+fn run_relays(seed: u64) -> (Vec<String>, usize) {
+    let mut sim = Simulation::<u64>::new(seed).latency(1, 20);
+    let nodes: Vec<Node<u64>> = (1..4).map(|i| {
+        sim.add_node(node_id(&format!("node{}", i)), RabbleConfig::new()).unwrap()
+    }).collect();
+    nodes[0].join(&nodes[1].id).unwrap();
+    nodes[0].join(&nodes[2].id).unwrap();
+
+    // Node2 and node3 connect to each other on their next tick, after learning about each other
+    // from node1
+    sim.run_for(2000);
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let pids: Vec<Pid> = nodes.iter().map(|node| pid("relay", &node.id)).collect();
+    for (i, node) in nodes.iter().enumerate() {
+        let relay = Relay {
+            pid: pids[i].clone(),
+            next: pids[(i + 1) % 3].clone(),
+            timeout: 100 * (i + 1),
+            log: log.clone(),
+            output: Vec::new()
+        };
+        node.spawn(&pids[i], Box::new(relay)).unwrap();
+    }
+    let test_pid = pid("test", &nodes[0].id);
+    for (i, node) in nodes.iter().enumerate() {
+        node.send(Envelope::new(pids[i].clone(), test_pid.clone(), Msg::User(0), None)).unwrap();
+    }
+
+    // Each counter is received MAX_COUNT + 1 times, and each relay gets one timeout
+    let expected = 3 * (MAX_COUNT as usize + 1) + 3;
+    let log_ref = log.clone();
+    assert!(sim.run_until(60_000, || log_ref.lock().unwrap().len() == expected));
+    let now = sim.now();
+    let log = log.lock().unwrap().clone();
+    (log, now)
+}
+
+#[test]
+fn same_seed_same_history() {
+    let (log, time) = run_relays(7);
+    assert_eq!(run_relays(7), (log.clone(), time));
+
+    // Counters are passed in a different order with other seeds
+    assert!((8..12).any(|seed| run_relays(seed).0 != log));
+}
+
+/// Sends `Msg::User(0)` to `to` when its timer fires
+struct Alarm {
+    pid: Pid,
+    to: Pid,
+    timeout: usize,
+    output: Vec<Envelope<u64>>
+}
+
+impl Process for Alarm {
+    type Msg = u64;
+
+    fn init(&mut self, executor_pid: Pid) -> Vec<Envelope<u64>> {
+        vec![Envelope::new(executor_pid, self.pid.clone(), Msg::StartTimer(self.timeout), None)]
+    }
+
+    fn handle(&mut self, msg: Msg<u64>, _: Pid, _: Option<CorrelationId>)
+        -> &mut Vec<Envelope<u64>>
+    {
+        if let Msg::Timeout = msg {
+            self.output.push(Envelope::new(self.to.clone(), self.pid.clone(), Msg::User(0), None));
+        }
+        &mut self.output
+    }
+}
+
+#[test]
+fn timers_fire_on_the_virtual_clock() {
+    let mut sim = Simulation::<u64>::new(1);
+    let config = RabbleConfig::new().executor_tick_time(10);
+    let node = sim.add_node(node_id("node1"), config).unwrap();
+    let alarm_pid = pid("alarm", &node.id);
+    let service_pid = pid("service", &node.id);
+    let rx = sim.service(&service_pid).unwrap();
+    let alarm = Alarm {
+        pid: alarm_pid.clone(),
+        to: service_pid,
+        timeout: 300,
+        output: Vec::new()
+    };
+    node.spawn(&alarm_pid, Box::new(alarm)).unwrap();
+
+    sim.run_for(250);
+    assert!(rx.try_recv().is_err());
+
+    // Each executor tick of 10ms advances the timer wheel by one 10ms slot
+    let mut received = None;
+    assert!(sim.run_until(1000, || {
+        received = rx.try_recv().ok();
+        received.is_some()
+    }));
+    assert_eq!(received.unwrap().msg, Msg::User(0));
+    assert!(sim.now() >= 300 && sim.now() <= 320, "Timer fired at {}ms", sim.now());
+
+    // An hour passes in no time
+    let fired = sim.now();
+    sim.run_for(60 * 60 * 1000);
+    assert_eq!(sim.now(), fired + 60 * 60 * 1000);
+}
+
+#[test]
+fn crashed_nodes_lose_their_connections() {
+    let mut sim = Simulation::<u64>::new(3);
+    let node1 = sim.add_node(node_id("node1"), RabbleConfig::new()).unwrap();
+    let node2 = sim.add_node(node_id("node2"), RabbleConfig::new()).unwrap();
+    let service_pid = pid("service", &node1.id);
+    let rx = sim.service(&service_pid).unwrap();
+    node1.subscribe_cluster_events(&service_pid).unwrap();
+    node1.join(&node2.id).unwrap();
+
+    let established = ClusterEvent::ConnectionEstablished(node2.id.clone());
+    let is_established = |envelope: Envelope<u64>| {
+        envelope.msg == Msg::ClusterEvent(established.clone())
+    };
+    assert!(sim.run_until(1000, || rx.try_recv().map_or(false, &is_established)));
+
+    sim.crash(&node2.id);
+    let lost = ClusterEvent::ConnectionLost(node2.id.clone());
+    assert!(sim.run_until(100, || {
+        rx.try_recv().map_or(false, |envelope| envelope.msg == Msg::ClusterEvent(lost.clone()))
+    }));
+
+    // The node reconnects once it restarts
+    sim.add_node(node2.id.clone(), RabbleConfig::new()).unwrap();
+    assert!(sim.run_until(5000, || rx.try_recv().map_or(false, &is_established)));
+}
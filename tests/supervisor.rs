@@ -0,0 +1,351 @@
+//! Test supervisor restart strategies and panic isolation
+
+extern crate rabble;
+extern crate slog;
+
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use rabble::{
+    Pid,
+    NodeId,
+    Node,
+    Process,
+    Envelope,
+    Msg,
+    Reason,
+    CorrelationId,
+    Metric,
+    Supervisor,
+    ChildSpec,
+    Strategy,
+    ProcessHarness,
+    RabbleConfig
+};
+
+/// A process that panics whenever it receives a user message
+struct Crasher {
+    output: Vec<Envelope<()>>
+}
+
+impl Process for Crasher {
+    type Msg = ();
+
+    fn handle(&mut self,
+              msg: Msg<()>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<()>>
+    {
+        if let Msg::User(()) = msg {
+            panic!("Crasher received a user message");
+        }
+        &mut self.output
+    }
+}
+
+/// A process that panics as soon as it is started
+struct InitCrasher {
+    output: Vec<Envelope<()>>
+}
+
+impl Process for InitCrasher {
+    type Msg = ();
+
+    fn init(&mut self, _executor_pid: Pid) -> Vec<Envelope<()>> {
+        panic!("InitCrasher started");
+    }
+
+    fn handle(&mut self,
+              _msg: Msg<()>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<()>>
+    {
+        &mut self.output
+    }
+}
+
+/// Monitors a single pid and forwards all received messages to the test
+struct Reporter {
+    pid: Pid,
+    target: Pid,
+    output: Vec<Envelope<()>>,
+    tx: mpsc::Sender<Msg<()>>
+}
+
+impl Process for Reporter {
+    type Msg = ();
+
+    fn init(&mut self, executor_pid: Pid) -> Vec<Envelope<()>> {
+        vec![Envelope::new(executor_pid, self.pid.clone(), Msg::Monitor(self.target.clone()), None)]
+    }
+
+    fn handle(&mut self,
+              msg: Msg<()>,
+              _from: Pid,
+              _correlation_id: Option<CorrelationId>) -> &mut Vec<Envelope<()>>
+    {
+        self.tx.send(msg).unwrap();
+        &mut self.output
+    }
+}
+
+fn pid(name: &str, node: &NodeId) -> Pid {
+    Pid {name: name.to_string(), group: None, node: node.clone()}
+}
+
+/// Create child specs for crashers that notify `tx` every time they are started
+fn child_specs(node_id: &NodeId, tx: &mpsc::Sender<Pid>) -> Vec<ChildSpec<()>> {
+    ["a", "b", "c"].iter().map(|name| {
+        let pid = pid(name, node_id);
+        let tx = tx.clone();
+        ChildSpec::new(pid.clone(), move || {
+            tx.send(pid.clone()).unwrap();
+            Box::new(Crasher {output: Vec::new()}) as Box<Process<Msg=()>>
+        })
+    }).collect()
+}
+
+/// Create a child spec for a process that panics in `init` the first time it is started, and
+/// notifies `tx` every time it is started
+fn init_crasher_spec(pid: Pid, tx: mpsc::Sender<Pid>) -> ChildSpec<()> {
+    let starts = Arc::new(AtomicUsize::new(0));
+    ChildSpec::new(pid.clone(), move || {
+        tx.send(pid.clone()).unwrap();
+        if starts.fetch_add(1, Ordering::SeqCst) == 0 {
+            Box::new(InitCrasher {output: Vec::new()}) as Box<Process<Msg=()>>
+        } else {
+            Box::new(Crasher {output: Vec::new()})
+        }
+    })
+}
+
+fn crash(node: &Node<()>, pid: &Pid) {
+    node.send(Envelope::new(pid.clone(), pid.clone(), Msg::User(()), None)).unwrap();
+}
+
+fn assert_started(rx: &mpsc::Receiver<Pid>, names: &[&str], node_id: &NodeId) {
+    for name in names {
+        assert_eq!(rx.recv().unwrap(), pid(name, node_id));
+    }
+    // No other children should be restarted
+    assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+}
+
+fn restart_strategy(port: u16, strategy: Strategy, restarted: &[&str]) {
+    let node_id = NodeId {name: "node1".to_string(), addr: format!("127.0.0.1:{}", port)};
+    let config = RabbleConfig::new().num_workers(2);
+    let (node, handles) = rabble::rouse_with_config::<()>(node_id.clone(), config, None).unwrap();
+    let (tx, rx) = mpsc::channel();
+
+    let supervisor_pid = pid("supervisor", &node_id);
+    let supervisor = Supervisor::new(supervisor_pid.clone(),
+                                     node.logger.clone(),
+                                     strategy,
+                                     5,
+                                     10000,
+                                     child_specs(&node_id, &tx));
+    node.spawn(&supervisor_pid, Box::new(supervisor)).unwrap();
+    assert_started(&rx, &["a", "b", "c"], &node_id);
+
+    crash(&node, &pid("b", &node_id));
+    assert_started(&rx, restarted, &node_id);
+
+    node.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+#[test]
+fn one_for_one() {
+    restart_strategy(11030, Strategy::OneForOne, &["b"]);
+}
+
+#[test]
+fn one_for_all() {
+    restart_strategy(11031, Strategy::OneForAll, &["a", "b", "c"]);
+}
+
+#[test]
+fn rest_for_one() {
+    restart_strategy(11032, Strategy::RestForOne, &["b", "c"]);
+}
+
+#[test]
+fn supervisor_stops_when_restart_intensity_is_exceeded() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11033".to_string()};
+    let config = RabbleConfig::new().num_workers(2);
+    let (node, handles) = rabble::rouse_with_config::<()>(node_id.clone(), config, None).unwrap();
+    let (tx, rx) = mpsc::channel();
+
+    let supervisor_pid = pid("supervisor", &node_id);
+    let supervisor = Supervisor::new(supervisor_pid.clone(),
+                                     node.logger.clone(),
+                                     Strategy::OneForOne,
+                                     1,
+                                     10000,
+                                     child_specs(&node_id, &tx));
+    node.spawn(&supervisor_pid, Box::new(supervisor)).unwrap();
+    assert_started(&rx, &["a", "b", "c"], &node_id);
+
+    let (reporter_tx, reporter_rx) = mpsc::channel();
+    let reporter_pid = pid("reporter", &node_id);
+    let reporter = Reporter {
+        pid: reporter_pid.clone(),
+        target: supervisor_pid.clone(),
+        output: Vec::new(),
+        tx: reporter_tx
+    };
+    node.spawn(&reporter_pid, Box::new(reporter)).unwrap();
+    // Ensure the monitor is installed by waiting for the reporter to handle a message
+    node.send(Envelope::new(reporter_pid.clone(), reporter_pid, Msg::User(()), None)).unwrap();
+    assert_eq!(reporter_rx.recv().unwrap(), Msg::User(()));
+
+    let b = pid("b", &node_id);
+    crash(&node, &b);
+    assert_started(&rx, &["b"], &node_id);
+    crash(&node, &b);
+    assert_eq!(reporter_rx.recv().unwrap(), Msg::Down(supervisor_pid, Reason::Normal));
+    assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+    node.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+#[test]
+fn panics_only_stop_the_panicking_process() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11034".to_string()};
+    let config = RabbleConfig::new().num_workers(2);
+    let (node, handles) = rabble::rouse_with_config::<()>(node_id.clone(), config, None).unwrap();
+
+    node.spawn(&pid("init-crasher", &node_id), Box::new(InitCrasher {output: Vec::new()}))
+        .unwrap();
+    let crasher_pid = pid("crasher", &node_id);
+    node.spawn(&crasher_pid, Box::new(Crasher {output: Vec::new()})).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let reporter_pid = pid("reporter", &node_id);
+    let reporter = Reporter {
+        pid: reporter_pid.clone(),
+        target: crasher_pid.clone(),
+        output: Vec::new(),
+        tx: tx
+    };
+    node.spawn(&reporter_pid, Box::new(reporter)).unwrap();
+    node.send(Envelope::new(reporter_pid.clone(), reporter_pid.clone(), Msg::User(()), None))
+        .unwrap();
+    assert_eq!(rx.recv().unwrap(), Msg::User(()));
+
+    crash(&node, &crasher_pid);
+    let reason = Reason::Panic("Crasher received a user message".to_string());
+    assert_eq!(rx.recv().unwrap(), Msg::Down(crasher_pid, reason));
+
+    // Both panics are counted and the executor keeps serving requests
+    let executor_pid = Pid {
+        name: "executor".to_string(),
+        group: Some("rabble".to_string()),
+        node: node_id.clone()
+    };
+    node.send(Envelope::new(executor_pid, reporter_pid, Msg::GetMetrics, None)).unwrap();
+    match rx.recv().unwrap() {
+        Msg::Metrics(metrics) => {
+            let panics = metrics.iter().find(|&&(ref name, _)| name == "panics").map(|m| &m.1);
+            assert_eq!(panics, Some(&Metric::Counter(2)));
+        },
+        msg => panic!("Expected metrics, got {:?}", msg)
+    }
+
+    node.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+#[test]
+fn panics_in_init_are_reported_to_monitors() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11035".to_string()};
+    let config = RabbleConfig::new().num_workers(2);
+    let (node, handles) = rabble::rouse_with_config::<()>(node_id.clone(), config, None).unwrap();
+
+    // Monitoring a process right after spawning it reports the panic rather than Reason::NoProc
+    let init_crasher_pid = pid("init-crasher", &node_id);
+    node.spawn(&init_crasher_pid, Box::new(InitCrasher {output: Vec::new()})).unwrap();
+    let (tx, rx) = mpsc::channel();
+    let reporter_pid = pid("reporter", &node_id);
+    let reporter = Reporter {
+        pid: reporter_pid.clone(),
+        target: init_crasher_pid.clone(),
+        output: Vec::new(),
+        tx: tx
+    };
+    node.spawn(&reporter_pid, Box::new(reporter)).unwrap();
+    let reason = Reason::Panic("InitCrasher started".to_string());
+    assert_eq!(rx.recv().unwrap(), Msg::Down(init_crasher_pid, reason));
+
+    // A supervised child whose init panics is restarted
+    let (tx, rx) = mpsc::channel();
+    let supervisor_pid = pid("supervisor", &node_id);
+    let child = init_crasher_spec(pid("a", &node_id), tx);
+    let supervisor = Supervisor::new(supervisor_pid.clone(),
+                                     node.logger.clone(),
+                                     Strategy::OneForOne,
+                                     5,
+                                     10000,
+                                     vec![child]);
+    node.spawn(&supervisor_pid, Box::new(supervisor)).unwrap();
+    assert_started(&rx, &["a", "a"], &node_id);
+
+    node.shutdown();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+#[test]
+fn supervisors_restart_children_with_effects() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11036".to_string()};
+    let (tx, rx) = mpsc::channel();
+    let children: Vec<_> = child_specs(&node_id, &tx).into_iter().take(2).collect();
+    let supervisor_pid = pid("supervisor", &node_id);
+    let logger = slog::Logger::root(slog::Discard, None);
+    let supervisor = Supervisor::new(supervisor_pid.clone(),
+                                     logger,
+                                     Strategy::OneForAll,
+                                     1,
+                                     1000,
+                                     children);
+    let mut harness = ProcessHarness::new(node_id.clone());
+    harness.spawn(supervisor_pid.clone(), Box::new(supervisor));
+    harness.run();
+    assert_started(&rx, &["a", "b"], &node_id);
+    let (a, b) = (pid("a", &node_id), pid("b", &node_id));
+
+    let crash = |harness: &mut ProcessHarness<()>, pid: &Pid| {
+        let msg = Msg::Down(pid.clone(), Reason::Panic("crash".to_string()));
+        harness.send(Envelope::new(supervisor_pid.clone(), supervisor_pid.clone(), msg, None));
+        harness.run();
+    };
+
+    crash(&mut harness, &a);
+    assert_started(&rx, &["a", "b"], &node_id);
+    assert!(harness.is_running(&a) && harness.is_running(&b));
+
+    // The monitor of the instance of b that the supervisor stopped reports it down. The instance
+    // that replaced it is still running.
+    crash(&mut harness, &b);
+    assert!(rx.try_recv().is_err());
+
+    // Restarts are forgotten after max_time
+    harness.advance(1000);
+    crash(&mut harness, &a);
+    assert_started(&rx, &["a", "b"], &node_id);
+    crash(&mut harness, &b);
+    crash(&mut harness, &a);
+    assert!(rx.try_recv().is_err());
+    assert!(!harness.is_running(&supervisor_pid));
+    assert!(!harness.is_running(&b));
+}
@@ -0,0 +1,228 @@
+//! Test TLS encrypted connections between nodes
+//!
+//! The certificates in tests/tls are signed by a test CA whose key was discarded. Each node
+//! certificate is valid for the DNS name of the node.
+
+extern crate amy;
+extern crate orset;
+extern crate rabble;
+extern crate rustls;
+extern crate rustls_pemfile;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+use orset::{ORSet, Delta};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use rustls_pemfile::Item;
+
+use utils::start_service;
+
+use rabble::{
+    Pid,
+    NodeId,
+    NodeBuilder,
+    RabbleConfig,
+    TlsConfig,
+    Envelope,
+    Msg,
+    MsgpackSerializer,
+    Serialize
+};
+use rabble::errors::ErrorKind;
+
+fn tls_config(name: &str) -> TlsConfig {
+    TlsConfig::new(&format!("tests/tls/{}.pem", name),
+                   &format!("tests/tls/{}.key", name),
+                   "tests/tls/ca.pem")
+}
+
+/// The first variants of the messages sent between nodes, in the same order so that they are
+/// encoded the same way
+#[allow(dead_code)] // Only Delta is sent
+#[derive(Debug, Clone, RustcEncodable, RustcDecodable)]
+enum ExternalMsg {
+    Members {from: NodeId, orset: ORSet<NodeId>},
+    Ping,
+    Envelope(Envelope<u64>),
+    Delta(Delta<NodeId>)
+}
+
+fn read_pem(path: &str) -> Vec<Item> {
+    let mut reader = BufReader::new(File::open(path).unwrap());
+    rustls_pemfile::read_all(&mut reader).unwrap()
+}
+
+/// Connect to the node named `server` at `addr` with the certificate of node `name`
+fn tls_connect(name: &str, server: &str, addr: &str) -> StreamOwned<ClientConnection, TcpStream> {
+    let certs = read_pem(&format!("tests/tls/{}.pem", name)).into_iter().filter_map(|item| {
+        match item {
+            Item::X509Certificate(cert) => Some(rustls::Certificate(cert)),
+            _ => None
+        }
+    }).collect();
+    let key = read_pem(&format!("tests/tls/{}.key", name)).into_iter().filter_map(|item| {
+        match item {
+            Item::PKCS8Key(key) => Some(rustls::PrivateKey(key)),
+            _ => None
+        }
+    }).next().unwrap();
+    let mut roots = RootCertStore::empty();
+    for item in read_pem("tests/tls/ca.pem") {
+        if let Item::X509Certificate(cert) = item {
+            roots.add(&rustls::Certificate(cert)).unwrap();
+        }
+    }
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_client_auth_cert(certs, key)
+        .unwrap();
+    let server = rustls::ServerName::try_from(server).unwrap();
+    let session = ClientConnection::new(Arc::new(config), server).unwrap();
+    let sock = TcpStream::connect(addr).unwrap();
+    sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    StreamOwned::new(session, sock)
+}
+
+#[test]
+fn nodes_communicate_over_tls() {
+    let node_id1 = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11160".to_string()};
+    let node_id2 = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11161".to_string()};
+    let (node1, handle1) = NodeBuilder::new(node_id1)
+        .config(RabbleConfig::new().tls(tls_config("node1")))
+        .build::<u64>()
+        .unwrap();
+    let (node2, handle2) = NodeBuilder::new(node_id2)
+        .config(RabbleConfig::new().tls(tls_config("node2")))
+        .build::<u64>()
+        .unwrap();
+
+    let pid1 = Pid {name: "sender".to_string(), group: None, node: node1.id.clone()};
+    let (rx1, h1) = start_service(pid1.clone(), &node1);
+    let pid2 = Pid {name: "receiver".to_string(), group: None, node: node2.id.clone()};
+    let (rx2, h2) = start_service(pid2.clone(), &node2);
+
+    node1.join(&node2.id).unwrap();
+    for i in 0..10 {
+        node1.send(Envelope::new(pid2.clone(), pid1.clone(), Msg::User(i), None)).unwrap();
+    }
+    for i in 0..10 {
+        assert_eq!(rx2.recv().unwrap(), Msg::User(i));
+    }
+    node2.send(Envelope::new(pid1.clone(), pid2.clone(), Msg::User(10), None)).unwrap();
+    assert_eq!(rx1.recv().unwrap(), Msg::User(10));
+
+    handle1.shutdown_and_join().unwrap();
+    handle2.shutdown_and_join().unwrap();
+    h1.join().unwrap();
+    h2.join().unwrap();
+}
+
+#[test]
+fn peers_must_present_a_certificate_for_their_node() {
+    let node_id1 = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11162".to_string()};
+    let (node1, handle1) = NodeBuilder::new(node_id1)
+        .config(RabbleConfig::new().tls(tls_config("node1")).tick_time(100))
+        .build::<u64>()
+        .unwrap();
+
+    // Node2 has a certificate signed by the trusted CA, but for a different node
+    let node_id2 = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11163".to_string()};
+    let (node2, handle2) = NodeBuilder::new(node_id2)
+        .config(RabbleConfig::new().tls(tls_config("node1")).tick_time(100))
+        .build::<u64>()
+        .unwrap();
+
+    let pid1 = Pid {name: "events".to_string(), group: None, node: node1.id.clone()};
+    let (rx1, h1) = start_service(pid1.clone(), &node1);
+    node1.subscribe_cluster_events(&pid1).unwrap();
+
+    // Node1 rejects node2 when it announces itself, so node2 never becomes a member
+    node2.join(&node1.id).unwrap();
+    match rx1.recv_timeout(Duration::from_secs(1)) {
+        Err(RecvTimeoutError::Timeout) => (),
+        result => panic!("Expected no cluster events, got {:?}", result)
+    }
+
+    handle1.shutdown_and_join().unwrap();
+    handle2.shutdown_and_join().unwrap();
+    h1.join().unwrap();
+}
+
+#[test]
+fn peers_must_establish_the_connection_before_sending_other_messages() {
+    let node_id = NodeId {name: "node2".to_string(), addr: "127.0.0.1:11165".to_string()};
+    let (node, handle) = NodeBuilder::new(node_id)
+        .config(RabbleConfig::new().tls(tls_config("node2")))
+        .build::<u64>()
+        .unwrap();
+    let pid = Pid {name: "events".to_string(), group: None, node: node.id.clone()};
+    let (rx, h) = start_service(pid.clone(), &node);
+    node.subscribe_cluster_events(&pid).unwrap();
+
+    // Answer with the node's own hello, which is compatible by definition
+    let mut sock = tls_connect("node1", "node2", "127.0.0.1:11165");
+    let mut header = [0; 4];
+    sock.read_exact(&mut header).unwrap();
+    let len = (header[0] as usize) << 24 | (header[1] as usize) << 16 |
+        (header[2] as usize) << 8 | header[3] as usize;
+    let mut hello = vec![0; len];
+    sock.read_exact(&mut hello).unwrap();
+    sock.write_all(&header).unwrap();
+    sock.write_all(&hello).unwrap();
+
+    // Skip sending members, and add a node to the cluster directly
+    let intruder = NodeId {name: "intruder".to_string(), addr: "127.0.0.1:11166".to_string()};
+    let mut orset = ORSet::new(intruder.to_string());
+    let delta = orset.add(intruder);
+    let mut serializer = MsgpackSerializer::<ExternalMsg>::new();
+    assert!(serializer.write_msgs(&mut sock, Some(&ExternalMsg::Delta(delta))).unwrap());
+
+    match rx.recv_timeout(Duration::from_secs(1)) {
+        Err(RecvTimeoutError::Timeout) => (),
+        result => panic!("Expected no cluster events, got {:?}", result)
+    }
+
+    // The node closes the connection instead of handling the delta
+    let mut buf = Vec::new();
+    match sock.read_to_end(&mut buf) {
+        Ok(_) => (),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => (),
+        Err(e) => panic!("Expected the connection to be closed, got {}", e)
+    }
+
+    handle.shutdown_and_join().unwrap();
+    h.join().unwrap();
+}
+
+#[test]
+fn invalid_tls_config_is_a_startup_error() {
+    let node_id = NodeId {name: "node1".to_string(), addr: "127.0.0.1:11164".to_string()};
+    let tls = TlsConfig::new("tests/tls/missing.pem", "tests/tls/node1.key", "tests/tls/ca.pem");
+    let result = NodeBuilder::new(node_id).config(RabbleConfig::new().tls(tls)).build::<u64>();
+    match result {
+        Err(e) => match *e.kind() {
+            ErrorKind::TlsError(_) => (),
+            ref kind => panic!("Expected a TlsError, got {:?}", kind)
+        },
+        Ok(_) => panic!("Expected a TlsError")
+    }
+}
@@ -0,0 +1,110 @@
+//! Test connecting nodes on the same host over Unix domain sockets
+
+extern crate amy;
+extern crate rabble;
+
+#[macro_use]
+extern crate assert_matches;
+extern crate rustc_serialize;
+
+extern crate slog;
+extern crate slog_stdlog;
+extern crate slog_envlogger;
+extern crate slog_term;
+extern crate log;
+extern crate time;
+
+mod utils;
+
+use std::env;
+use std::fs;
+use std::os::unix::net::UnixListener;
+use std::process;
+use std::str::FromStr;
+
+use utils::{start_named_service, wait_for_connections};
+
+use rabble::{
+    NodeId,
+    NodeBuilder,
+    Envelope,
+    Msg
+};
+
+fn unix_addr(name: &str) -> String {
+    let path = env::temp_dir().join(format!("rabble-{}-{}.sock", process::id(), name));
+    format!("unix:{}", path.display())
+}
+
+#[test]
+fn nodes_communicate_over_unix_sockets() {
+    // Node3 listens on TCP, and reaches the other nodes over their Unix domain sockets
+    let node_ids = vec![NodeId {name: "node1".to_string(), addr: unix_addr("node1")},
+                        NodeId {name: "node2".to_string(), addr: unix_addr("node2")},
+                        NodeId {name: "node3".to_string(), addr: "127.0.0.1:11200".to_string()}];
+    let (nodes, handles): (Vec<_>, Vec<_>) = node_ids.into_iter().map(|node_id| {
+        NodeBuilder::new(node_id).build::<u64>().unwrap()
+    }).unzip();
+    let services: Vec<_> = nodes.iter().enumerate().map(|(i, node)| {
+        start_named_service(&format!("service{}", i + 1), node)
+    }).collect();
+
+    nodes[0].join(&nodes[1].id).unwrap();
+    nodes[0].join(&nodes[2].id).unwrap();
+    for node in &nodes {
+        assert!(wait_for_connections(node, 2));
+    }
+    for i in 0..3 {
+        for j in 0..3 {
+            let (ref from, _, _) = services[i];
+            let (ref to, _, _) = services[j];
+            nodes[i].send(Envelope::new(to.clone(), from.clone(), Msg::User(i as u64), None))
+                .unwrap();
+        }
+    }
+    for &(_, ref rx, _) in &services {
+        let mut received: Vec<_> = (0..3).map(|_| rx.recv().unwrap()).collect();
+        received.sort_by_key(|msg| if let Msg::User(i) = *msg { i } else { 100 });
+        assert_eq!(received, vec![Msg::User(0), Msg::User(1), Msg::User(2)]);
+    }
+
+    let paths: Vec<String> = nodes[0..2].iter().map(|node| {
+        node.id.addr["unix:".len()..].to_string()
+    }).collect();
+    for handle in handles {
+        handle.shutdown_and_join().unwrap();
+    }
+    for (_, _, h) in services {
+        h.join().unwrap();
+    }
+
+    // Socket files are removed when nodes shut down
+    for path in paths {
+        assert!(fs::metadata(path).is_err());
+    }
+}
+
+#[test]
+fn stale_socket_files_are_replaced() {
+    let addr = unix_addr("stale");
+    let path = addr["unix:".len()..].to_string();
+
+    // A node that crashed leaves its socket file behind
+    drop(UnixListener::bind(&path).unwrap());
+    assert!(fs::metadata(&path).is_ok());
+
+    let node_id = NodeId {name: "node1".to_string(), addr: addr.clone()};
+    let (_node, handle) = NodeBuilder::new(node_id.clone()).build::<u64>().unwrap();
+
+    // A socket used by a running node isn't replaced
+    assert!(NodeBuilder::new(node_id).build::<u64>().is_err());
+    handle.shutdown_and_join().unwrap();
+}
+
+#[test]
+fn parse_unix_socket_node_ids() {
+    let node = NodeId::from_str("node1@unix:/var/run/rabble.sock").unwrap();
+    assert_eq!(node.name, "node1");
+    assert_eq!(node.addr, "unix:/var/run/rabble.sock");
+    assert!(NodeId::from_str("node1@unix:").is_err());
+}
@@ -10,6 +10,9 @@ pub mod messages;
 
 use std::thread::{self, JoinHandle};
 use std::net::TcpStream;
+use std::sync::mpsc;
+use std::fmt::Debug;
+use rustc_serialize::{Encodable, Decodable};
 use amy::{Poller, Receiver, Sender};
 use self::slog::DrainExt;
 use self::time::{SteadyTime, Duration};
@@ -23,7 +26,12 @@ use rabble::{
     Envelope,
     Pid,
     CorrelationId,
-    Msg
+    Msg,
+    ClusterStatus,
+    Service,
+    ThreadHandler,
+    ClusterEvent,
+    Metric
 };
 
 type CrNode = Node<RabbleUserMsg>;
@@ -50,6 +58,41 @@ pub fn wait_for<F>(timeout: Duration, mut f: F) -> bool
     true
 }
 
+/// Wait for `node` to establish connections with `num_peers` other nodes
+#[allow(dead_code)] // Not used in all tests
+pub fn wait_for_connections<T>(node: &Node<T>, num_peers: usize) -> bool
+    where T: Encodable + Decodable + Debug + Clone + Send + 'static
+{
+    let service_pid = Pid {
+        name: "connection-waiter".to_string(),
+        group: None,
+        node: node.id.clone()
+    };
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<T>| {
+        if let Msg::ClusterStatus(status) = envelope.msg {
+            let _ = tx.send(status);
+        }
+    });
+    let mut service = Service::new(service_pid.clone(), node.clone(), handler).unwrap();
+    let service_tx = service.tx.clone();
+    let h = thread::spawn(move || {
+        service.wait();
+    });
+
+    let connected = wait_for(Duration::seconds(5), || {
+        node.cluster_status(CorrelationId::pid(service_pid.clone())).unwrap();
+        if let Ok(ClusterStatus {established, ..}) = rx.recv() {
+            return established.len() == num_peers;
+        }
+        false
+    });
+
+    service_tx.send(Envelope::new(service_pid.clone(), service_pid, Msg::Shutdown, None)).unwrap();
+    h.join().unwrap();
+    connected
+}
+
 /// Send a message over a non-blocking socket
 /// Wait for it to finish sending or timeout after 5 seconds
 /// In practice the first call to serializer.write_msgs should succeed unless the TCP send buffer is
@@ -142,4 +185,57 @@ pub fn register_test_as_service(poller: &mut Poller,
 
 }
 
+/// Start a service with `pid` on `node` that forwards every message it receives to the returned
+/// receiver
+#[allow(dead_code)] // Not used in all tests
+pub fn start_service(pid: Pid, node: &Node<u64>) -> (mpsc::Receiver<Msg<u64>>, JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel();
+    let handler = ThreadHandler::new(move |_node, envelope: Envelope<u64>| {
+        tx.send(envelope.msg).unwrap();
+    });
+    let mut service = Service::new(pid, node.clone(), handler).unwrap();
+    let h = thread::spawn(move || service.wait());
+    (rx, h)
+}
+
+/// Start a service named `name` on `node` with `start_service`, and return its pid
+#[allow(dead_code)] // Not used in all tests
+pub fn start_named_service(name: &str, node: &Node<u64>)
+    -> (Pid, mpsc::Receiver<Msg<u64>>, JoinHandle<()>)
+{
+    let pid = Pid {name: name.to_string(), group: None, node: node.id.clone()};
+    let (rx, h) = start_service(pid.clone(), node);
+    (pid, rx, h)
+}
+
+/// Discard messages received by a service started with `start_service` until `event` arrives
+#[allow(dead_code)] // Not used in all tests
+pub fn wait_for_event(rx: &mpsc::Receiver<Msg<u64>>, event: ClusterEvent) {
+    loop {
+        if let Msg::ClusterEvent(e) = rx.recv().unwrap() {
+            if e == event {
+                return;
+            }
+        }
+    }
+}
 
+/// Return the value of the cluster server counter `name` on `node`, as received by the service
+/// `from`
+#[allow(dead_code)] // Not used in all tests
+pub fn get_metric(node: &Node<u64>, from: &Pid, rx: &mpsc::Receiver<Msg<u64>>, name: &str) -> u64 {
+    let cluster_server = Pid {
+        name: "cluster_server".to_string(),
+        group: Some("rabble".to_string()),
+        node: node.id.clone()
+    };
+    node.send(Envelope::new(cluster_server, from.clone(), Msg::GetMetrics, None)).unwrap();
+    loop {
+        if let Msg::Metrics(metrics) = rx.recv().unwrap() {
+            match metrics.into_iter().find(|&(ref n, _)| n == name) {
+                Some((_, Metric::Counter(count))) => return count,
+                metric => panic!("Expected counter {}, got {:?}", name, metric)
+            }
+        }
+    }
+}